@@ -2,6 +2,7 @@ use clap::{App, Arg};
 use easy_fs::{BlockDevice, EasyFileSystem};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -49,6 +50,15 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("extra")
+                .short("e")
+                .long("extra")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Extra host file to pack, named by its file name"),
+        )
         .get_matches();
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
@@ -65,18 +75,29 @@ fn easy_fs_pack() -> std::io::Result<()> {
     // 32MiB, at most 4095 files
     let efs = EasyFileSystem::create(block_file, 32 * 2048, 1);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
-    let apps: Vec<_> = read_dir(src_path)
+    let mut apps: Vec<_> = read_dir(src_path)
         .unwrap()
         .into_iter()
         .map(|dir_entry| {
             let mut name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();
             name_with_ext.drain(name_with_ext.find('.').unwrap()..name_with_ext.len());
-            name_with_ext
+            let host_path = format!("{}{}", target_path, name_with_ext);
+            (name_with_ext, host_path)
         })
         .collect();
-    for app in apps {
+    // 不是 user 里编译出来的程序，比如静态链接的 busybox
+    for host_path in matches.values_of("extra").into_iter().flatten() {
+        let name = Path::new(host_path)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        apps.push((name, host_path.to_string()));
+    }
+    for (app, host_path) in apps {
         // load app data from host file system
-        let mut host_file = File::open(format!("{}{}", target_path, app)).unwrap();
+        let mut host_file = File::open(host_path).unwrap();
         let mut all_data: Vec<u8> = Vec::new();
         host_file.read_to_end(&mut all_data).unwrap();
         // create a file in easy-fs
@@ -156,6 +156,16 @@ impl Inode {
         })
     }
 
+    /// Position of the disk inode, which identifies the file
+    pub fn id(&self) -> (usize, usize) {
+        (self.block_id, self.block_offset)
+    }
+
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
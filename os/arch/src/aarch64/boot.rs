@@ -8,7 +8,7 @@ use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
 #[link_section = ".data.prepage"]
 static mut BOOT_PT_L1: [usize; 512] = [0; 512];
 
-unsafe fn switch_to_el1() {
+pub(super) unsafe fn switch_to_el1() {
     SPSel.write(SPSel::SP::ELx);
     SP_EL0.set(0);
     let current_el = CurrentEL.read(CurrentEL::EL);
@@ -51,7 +51,7 @@ unsafe fn switch_to_el1() {
     }
 }
 
-unsafe fn init_mmu() {
+pub(super) unsafe fn init_mmu() {
     MAIR_EL1.set(0x44_ff_04);
 
     // Enable TTBR0 and TTBR1 walks, page size = 4K, vaddr size = 39 bits, paddr size = 40 bits.
@@ -86,8 +86,15 @@ unsafe fn init_mmu() {
 
 unsafe fn init_boot_page_table() {
     // Level 1 Entry for Huge Page
-    BOOT_PT_L1[0] =
-        0 | (PTEFlags::VALID | PTEFlags::AF | PTEFlags::ATTR_INDX | PTEFlags::NG).bits();
+    // 第一个 1G 里只有设备，不需要执行权限
+    BOOT_PT_L1[0] = 0
+        | (PTEFlags::VALID
+            | PTEFlags::AF
+            | PTEFlags::ATTR_INDX
+            | PTEFlags::NG
+            | PTEFlags::PXN
+            | PTEFlags::UXN)
+            .bits();
     BOOT_PT_L1[1] = (0x4000_0000)
         | (PTEFlags::VALID | PTEFlags::AF | PTEFlags::ATTR_INDX | PTEFlags::NG).bits();
 }
@@ -1,6 +1,6 @@
 use core::ops::{Index, IndexMut};
 
-use crate::ContextArgs;
+use crate::{ContextArgs, ContextOps};
 
 /// Saved registers when a trap (interrupt or exception) occurs.#[allow(missing_docs)]
 #[repr(C)]
@@ -23,93 +23,85 @@ impl Context {
     }
 }
 
-// impl ContextOps for Context {
-//     #[inline]
-//     fn set_sp(&mut self, sp: usize) {
-//         self.sp = sp
-//     }
-
-//     #[inline]
-//     fn sp(&self) -> usize {
-//         self.sp
-//     }
-//     #[inline]
-//     fn set_ra(&mut self, ra: usize) {
-//         self.regs[30] = ra;
-//     }
-
-//     #[inline]
-//     fn ra(&self) -> usize {
-//         self.regs[30]
-//     }
-
-//     #[inline]
-//     fn set_sepc(&mut self, sepc: usize) {
-//         self.elr = sepc;
-//     }
-
-//     #[inline]
-//     fn sepc(&self) -> usize {
-//         self.elr
-//     }
-
-//     #[inline]
-//     fn syscall_number(&self) -> usize {
-//         self.regs[8]
-//     }
-
-//     #[inline]
-//     fn args(&self) -> [usize; 6] {
-//         [
-//             self.regs[0],
-//             self.regs[1],
-//             self.regs[2],
-//             self.regs[3],
-//             self.regs[4],
-//             self.regs[5],
-//         ]
-//     }
-
-//     #[inline]
-//     fn syscall_ok(&mut self) {}
-
-//     fn set_ret(&mut self, ret: usize) {
-//         self.regs[0] = ret;
-//     }
-
-//     fn set_arg0(&mut self, ret: usize) {
-//         self.regs[0] = ret;
-//     }
-
-//     fn set_arg1(&mut self, ret: usize) {
-//         self.regs[1] = ret;
-//     }
-
-//     fn set_arg2(&mut self, ret: usize) {
-//         self.regs[2] = ret;
-//     }
-
-//     #[inline]
-//     fn set_tls(&mut self, tls: usize) {
-//         self.tpidr = tls
-//     }
-// }
+impl ContextOps for Context {
+    #[inline]
+    fn set_sp(&mut self, sp: usize) {
+        self.sp = sp;
+    }
+
+    #[inline]
+    fn sp(&self) -> usize {
+        self.sp
+    }
+
+    #[inline]
+    fn set_ra(&mut self, ra: usize) {
+        self.regs[30] = ra;
+    }
+
+    #[inline]
+    fn ra(&self) -> usize {
+        self.regs[30]
+    }
+
+    #[inline]
+    fn set_pc(&mut self, pc: usize) {
+        self.elr = pc;
+    }
+
+    #[inline]
+    fn pc(&self) -> usize {
+        self.elr
+    }
+
+    #[inline]
+    fn set_tls(&mut self, tls: usize) {
+        self.tpidr = tls;
+    }
+
+    #[inline]
+    fn tls(&self) -> usize {
+        self.tpidr
+    }
+
+    #[inline]
+    fn syscall_number(&self) -> usize {
+        self.regs[8]
+    }
+
+    #[inline]
+    fn args(&self) -> [usize; 6] {
+        self.regs[0..6].try_into().expect("args slice force convert")
+    }
+
+    #[inline]
+    fn set_arg0(&mut self, arg: usize) {
+        self.regs[0] = arg;
+    }
+
+    #[inline]
+    fn set_arg1(&mut self, arg: usize) {
+        self.regs[1] = arg;
+    }
+
+    #[inline]
+    fn set_arg2(&mut self, arg: usize) {
+        self.regs[2] = arg;
+    }
+
+    #[inline]
+    fn set_ret(&mut self, ret: usize) {
+        self.regs[0] = ret;
+    }
 
-impl Context {
     #[inline]
-    pub fn args(&self) -> [usize; 6] {
-        [
-            self.regs[0],
-            self.regs[1],
-            self.regs[2],
-            self.regs[3],
-            self.regs[4],
-            self.regs[5],
-        ]
+    fn ret(&self) -> usize {
+        self.regs[0]
     }
 
+    // svc 陷入时 elr 已经指向下一条指令
     #[inline]
-    pub fn syscall_ok(&mut self) {}
+    fn syscall_ok(&mut self) {}
 }
 
 impl Index<ContextArgs> for Context {
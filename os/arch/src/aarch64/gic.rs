@@ -35,6 +35,11 @@ pub(crate) fn init() {
     GICC.init();
 }
 
+/// GICD 已经由主核初始化，从核只需要初始化自己的 GICC
+pub(crate) fn init_secondary() {
+    GICC.init();
+}
+
 #[inline]
 pub fn handle_irq<F>(f: F)
 where
@@ -0,0 +1,55 @@
+use core::arch::asm;
+
+/// 内核线程切换时保存的上下文，只包含 callee-saved 寄存器
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct KContext {
+    ksp: usize,
+    /// x19 - x29, lr(x30)
+    regs: [usize; 12],
+}
+
+impl KContext {
+    pub fn blank() -> Self {
+        Self::default()
+    }
+
+    /// 切换到该上下文时从 `entry` 开始执行，使用 `ksp` 作为内核栈
+    pub fn new(entry: usize, ksp: usize) -> Self {
+        let mut kcx = Self::blank();
+        kcx.ksp = ksp;
+        kcx.regs[11] = entry;
+        kcx
+    }
+}
+
+/// 保存当前的内核上下文到 `from`，并切换到 `to`
+#[naked]
+pub unsafe extern "C" fn context_switch(from: *mut KContext, to: *const KContext) {
+    asm!(
+        // 保存当前上下文
+        "
+            mov     x9, sp
+            str     x9, [x0]
+            stp     x19, x20, [x0, 1 * 8]
+            stp     x21, x22, [x0, 3 * 8]
+            stp     x23, x24, [x0, 5 * 8]
+            stp     x25, x26, [x0, 7 * 8]
+            stp     x27, x28, [x0, 9 * 8]
+            stp     x29, x30, [x0, 11 * 8]
+        ",
+        // 恢复下一个上下文
+        "
+            ldr     x9, [x1]
+            mov     sp, x9
+            ldp     x19, x20, [x1, 1 * 8]
+            ldp     x21, x22, [x1, 3 * 8]
+            ldp     x23, x24, [x1, 5 * 8]
+            ldp     x25, x26, [x1, 7 * 8]
+            ldp     x27, x28, [x1, 9 * 8]
+            ldp     x29, x30, [x1, 11 * 8]
+            ret
+        ",
+        options(noreturn)
+    )
+}
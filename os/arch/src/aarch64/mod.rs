@@ -1,10 +1,12 @@
 mod boot;
 mod consts;
 mod context;
-mod gic;
+pub(crate) mod gic;
+mod kcontext;
 mod page_table;
 mod pl011;
 mod psci;
+mod smp;
 mod timer;
 mod trap;
 
@@ -13,21 +15,29 @@ use aarch64_cpu::{asm::barrier, registers::CPACR_EL1};
 use alloc::vec::Vec;
 pub use consts::*;
 pub use context::Context;
+pub use kcontext::{context_switch, KContext};
 use fdt::Fdt;
 pub use page_table::*;
 pub use pl011::{console_getchar, console_putchar};
 pub use psci::system_off as shutdown;
 pub use timer::{get_time, time_to_usec};
-pub use trap::{enable_external_irq, enable_irq, init_interrupt, run_user_task};
+pub use boot::flush_tlb;
+pub use smp::{start_secondary_cpus, tlb_shootdown};
+pub use trap::{
+    disable_irq, enable_external_irq, enable_irq, init_interrupt, irq_enabled, run_user_task,
+};
 
 use crate::{clear_bss, ArchInterface};
 
 pub fn rust_tmp_main(hart_id: usize, device_tree: usize) {
     clear_bss();
+    crate::smp::primary_init(hart_id);
+    allocator::init();
     pl011::init_early();
     ArchInterface::init_logging();
+    // 只用 8 位 ASID，所有实现都支持，不用改 TCR_EL1.AS
+    crate::asid::init_asid(256);
     trap::init();
-    allocator::init();
     gic::init();
 
     timer::init();
@@ -42,6 +52,7 @@ pub fn rust_tmp_main(hart_id: usize, device_tree: usize) {
         });
 
         info!("There has {} CPU(s)", fdt.cpus().count());
+        smp::record_cpus(&fdt);
 
         fdt.memory()
             .regions()
@@ -1,10 +1,12 @@
 use core::arch::asm;
+use core::mem::ManuallyDrop;
 
-use aarch64_cpu::registers::{Writeable, TTBR0_EL1};
-use alloc::sync::Arc;
+use aarch64_cpu::registers::{Readable, Writeable, TTBR0_EL1, TTBR1_EL1};
 
+use crate::asid::{release_asid, switch_asid, AsidFlush};
 use crate::{
-    ArchInterface, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage, PAGE_ITEM_COUNT, PAGE_SIZE,
+    ArchInterface, MapPageSize, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage,
+    PAGE_ITEM_COUNT, PAGE_SIZE, VIRT_ADDR_START,
 };
 
 use super::boot::flush_tlb;
@@ -62,6 +64,12 @@ impl PTE {
     pub fn is_leaf(&self) -> bool {
         self.flags().contains(PTEFlags::VALID | PTEFlags::NON_BLOCK)
     }
+
+    /// 第 1、2 层的块描述符，也就是 1G/2M 大页
+    #[inline]
+    pub fn is_huge(&self) -> bool {
+        self.0 & 0b11 == 0b01
+    }
 }
 
 impl From<MappingFlags> for PTEFlags {
@@ -141,11 +149,23 @@ pub fn get_pte_list(paddr: PhysAddr) -> &'static mut [PTE] {
 pub struct PageTable(pub(crate) PhysAddr);
 
 impl PageTable {
-    pub fn alloc() -> Arc<Self> {
+    pub fn alloc() -> Self {
         let addr = ArchInterface::frame_alloc_persist().into();
         let page_table = Self(addr);
         page_table.restore();
-        Arc::new(page_table)
+        page_table
+    }
+
+    /// 页表的根物理地址，内核用它来标识地址空间
+    #[inline]
+    pub const fn token(&self) -> usize {
+        self.0 .0
+    }
+
+    /// 借用 token 对应的页表，返回值 drop 时不会回收页表
+    #[inline]
+    pub fn from_token(token: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self(PhysAddr(token)))
     }
 
     #[inline]
@@ -176,70 +196,182 @@ impl PageTable {
     #[inline]
     pub fn change(&self) {
         debug!("change ttbr0 to :{:#x}", self.0.addr());
-        TTBR0_EL1.set((self.0.addr() & 0xFFFF_FFFF_F000) as _);
-        flush_tlb(None)
+        let (asid, flush) = switch_asid(self.0 .0);
+        // TCR_EL1.A1 为 0，ASID 取自 TTBR0 的高 16 位
+        TTBR0_EL1.set((asid << 48 | self.0.addr() & 0xFFFF_FFFF_F000) as _);
+        unsafe {
+            match flush {
+                AsidFlush::None => asm!("isb"),
+                AsidFlush::Asid => asm!("tlbi aside1, {}; dsb sy; isb", in(reg) asid << 48),
+                AsidFlush::All => flush_tlb(None),
+            }
+        }
     }
 
-    pub fn get_mut_entry(&self, vpn: VirtPage) -> &mut PTE {
-        let l1_list = self.0.slice_mut_with_len::<PTE>(512);
-        let l1_index = (vpn.0 >> (9 * 2)) & 0x1ff;
+    /// 内核映射只在 TTBR1 指向的页表里，本来就是所有地址空间共用的
+    #[inline]
+    pub fn share_kernel(&self) {}
+
+    /// 内核地址由 TTBR1 翻译，不管是哪个页表都去改启动时建好的那一份
+    fn root(&self, vpn: VirtPage) -> PhysAddr {
+        if is_kernel(vpn) {
+            PhysAddr(TTBR1_EL1.get() as usize & 0xFFFF_FFFF_F000)
+        } else {
+            self.0
+        }
+    }
 
-        // l2 pte
-        let l2_pte = &mut l1_list[l1_index];
-        if !l2_pte.is_valid() {
-            *l2_pte = PTE(ArchInterface::frame_alloc_persist().to_addr() | 0b11);
+    /// 找到 `vpn` 在第 `level` 层的页表项，沿途缺少的页表会被分配，
+    /// 覆盖 `vpn` 的块会被拆开
+    pub fn get_mut_entry(&self, vpn: VirtPage, level: usize) -> &mut PTE {
+        let mut pte_list = get_pte_list(self.root(vpn));
+        for i in (level + 1..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * i)) & 0x1ff];
+            if !pte.is_valid() {
+                *pte = PTE(ArchInterface::frame_alloc_persist().to_addr() | 0b11);
+            } else if pte.is_huge() {
+                split_huge(pte, i);
+            }
+            pte_list = get_pte_list(pte.get_next_ptr());
         }
-        let l2_list = l2_pte.get_next_ptr().slice_mut_with_len::<PTE>(512);
-        let l2_index = (vpn.0 >> 9) & 0x1ff;
+        &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff]
+    }
 
-        // l3 pte
-        let l3_pte = &mut l2_list[l2_index];
-        if !l3_pte.is_valid() {
-            *l3_pte = PTE(ArchInterface::frame_alloc_persist().to_addr() | 0b11);
+    /// 找到映射 `vpn` 的页描述符或者块描述符，以及它所在的层级
+    fn find_pte(&self, vpn: VirtPage) -> Option<(&mut PTE, usize)> {
+        let mut pte_list = get_pte_list(self.root(vpn));
+        for level in (0..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff];
+            if !pte.is_valid() {
+                return None;
+            }
+            if level == 0 || pte.is_huge() {
+                return Some((pte, level));
+            }
+            pte_list = get_pte_list(pte.get_next_ptr());
         }
-        let l3_list = l3_pte.get_next_ptr().slice_mut_with_len::<PTE>(512);
-        let l3_index = vpn.0 & 0x1ff;
-        &mut l3_list[l3_index]
+        None
     }
 
+    /// 用 `size` 大小的页把 `vpn` 映射到 `ppn`，两者都要按 `size` 对齐
     #[inline]
-    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, _level: usize) {
-        *self.get_mut_entry(vpn) = PTE::from_ppn(ppn.0, flags.into());
-        flush_tlb(Some(vpn.into()))
+    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, size: MapPageSize) {
+        assert!(size.aligned(vpn, ppn));
+        let level = size.level();
+        let mut flags = PTEFlags::from(flags);
+        if is_kernel(vpn) {
+            // 和启动页表用一样的内存属性
+            flags |= PTEFlags::ATTR_INDX;
+        }
+        let pte = self.get_mut_entry(vpn, level);
+        if level == 0 {
+            *pte = PTE::from_ppn(ppn.0, flags);
+            flush_tlb(Some(vpn.into()));
+            return;
+        }
+        // 原来是下一级页表的话，整个换成块
+        let old = *pte;
+        *pte = PTE::from_ppn(ppn.0, flags - PTEFlags::NON_BLOCK);
+        if old.is_valid() && !old.is_huge() {
+            free_table(old.get_next_ptr(), level - 1);
+            crate::tlb_shootdown(None);
+        } else {
+            flush_tlb(Some(vpn.into()));
+        }
     }
 
+    /// 解除 `vpn` 这一个 4K 页的映射，它在块里的话先把块拆开
     #[inline]
     pub fn unmap(&self, vpn: VirtPage) {
-        *self.get_mut_entry(vpn) = PTE(0);
-        flush_tlb(Some(vpn.into()));
+        if self.find_pte(vpn).is_none() {
+            return;
+        }
+        *self.get_mut_entry(vpn, 0) = PTE(0);
+        crate::tlb_shootdown(Some(vpn.into()));
     }
 
+    /// 没有打开硬件维护 AF，清掉 AF 会触发 Access Flag 异常，这里一律当作没访问过
     #[inline]
-    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
-        let mut paddr = self.0;
-        for i in (0..3).rev() {
-            let value = (vaddr.0 >> (12 + 9 * i)) & 0x1ff;
-            let pte = &get_pte_list(paddr)[value];
-            // 如果当前页是大页 返回相关的位置
-            // vaddr.0 % (1 << (12 + 9 * i)) 是大页内偏移
-            if !pte.is_valid() {
-                return None;
-            }
-            paddr = pte.to_ppn().into()
+    pub fn test_and_clear_accessed(&self, _vpn: VirtPage) -> bool {
+        false
+    }
+
+    /// 同样没有硬件维护的脏位，都当作被写过
+    #[inline]
+    pub fn is_dirty(&self, _vpn: VirtPage) -> bool {
+        true
+    }
+
+    /// `vpn` 映射的权限，没有映射时返回 None
+    pub fn mapping_flags(&self, vpn: VirtPage) -> Option<MappingFlags> {
+        let flags = self.find_pte(vpn)?.0.flags();
+        let mut res = MappingFlags::R;
+        if !flags.contains(PTEFlags::AP_RO) {
+            res |= MappingFlags::W;
         }
-        Some(PhysAddr(paddr.0 | vaddr.0 % PAGE_SIZE))
+        if flags.contains(PTEFlags::AP_EL0) {
+            res |= MappingFlags::U;
+        }
+        // 用户页看 UXN，内核页看 PXN
+        let xn = if res.contains(MappingFlags::U) {
+            PTEFlags::UXN
+        } else {
+            PTEFlags::PXN
+        };
+        if !flags.contains(xn) {
+            res |= MappingFlags::X;
+        }
+        Some(res)
+    }
+
+    #[inline]
+    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        let (pte, level) = self.find_pte(vaddr.into())?;
+        // vaddr.0 % (PAGE_SIZE << (9 * level)) 是块内偏移
+        Some(PhysAddr(
+            pte.get_next_ptr().0 | vaddr.0 % (PAGE_SIZE << (9 * level)),
+        ))
     }
 }
 
+#[inline]
+fn is_kernel(vpn: VirtPage) -> bool {
+    VirtAddr::from(vpn).0 >= VIRT_ADDR_START
+}
+
+/// 把第 `level` 层的块拆成下一层的 512 个块或者页，属性不变
+fn split_huge(pte: &mut PTE, level: usize) {
+    let table = ArchInterface::frame_alloc_persist();
+    let base = pte.get_next_ptr().0;
+    let mut attrs = pte.0 & !0xffff_ffff_f000;
+    // 最底层的页描述符和表描述符一样是 0b11
+    if level == 1 {
+        attrs |= PTEFlags::NON_BLOCK.bits();
+    }
+    for (i, child) in get_pte_list(table.into()).iter_mut().enumerate() {
+        *child = PTE(base + (i << (12 + 9 * (level - 1))) | attrs);
+    }
+    *pte = PTE(table.to_addr() | 0b11);
+}
+
+/// 回收第 `level` 层的页表和它下面的所有页表，叶子指向的页帧由内核管理
+fn free_table(paddr: PhysAddr, level: usize) {
+    if level > 0 {
+        get_pte_list(paddr)
+            .iter()
+            .filter(|x| x.is_leaf())
+            .for_each(|x| free_table(x.get_next_ptr(), level - 1));
+    }
+    ArchInterface::frame_unalloc(paddr.into());
+}
+
 impl Drop for PageTable {
     fn drop(&mut self) {
-        for root_pte in get_pte_list(self.0).iter().filter(|x| x.is_leaf()) {
-            get_pte_list(root_pte.to_ppn().into())
-                .iter()
-                .filter(|x| x.is_leaf())
-                .for_each(|x| ArchInterface::frame_unalloc(x.to_ppn()));
-            ArchInterface::frame_unalloc(root_pte.to_ppn());
-        }
+        get_pte_list(self.0)
+            .iter()
+            .filter(|x| x.is_leaf())
+            .for_each(|x| free_table(x.get_next_ptr(), 1));
+        release_asid(self.0 .0);
         ArchInterface::frame_unalloc(self.0.into());
     }
 }
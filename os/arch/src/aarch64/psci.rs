@@ -110,12 +110,13 @@ pub fn system_off() -> ! {
 /// `target_cpu` contains a copy of the affinity fields of the MPIDR register.
 /// `entry_point` is the physical address of the secondary CPU's entry point.
 /// `arg` will be passed to the `X0` register of the secondary CPU.
-pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) {
+pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) -> bool {
     info!("Starting CPU {:x} ON ...", target_cpu);
     let res = psci_call(PSCI_0_2_FN64_CPU_ON, target_cpu, entry_point, arg);
-    if let Err(e) = res {
+    if let Err(e) = &res {
         error!("failed to boot CPU {:x} ({:?})", target_cpu, e);
     }
+    res.is_ok()
 }
 
 /// Power down the calling core. This call is intended for use in hotplug. A
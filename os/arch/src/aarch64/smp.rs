@@ -0,0 +1,106 @@
+use aarch64_cpu::{asm::barrier, registers::CPACR_EL1};
+use alloc::vec::Vec;
+use core::arch::asm;
+use fdt::Fdt;
+use tock_registers::interfaces::Writeable;
+
+use super::boot::{init_mmu, switch_to_el1};
+use super::{gic, psci, timer, trap};
+use crate::smp::{alloc_secondary_stack, secondary_init, wait_online};
+use crate::{hart_id, shutdown, ArchInterface, VirtAddr, STACK_SIZE, VIRT_ADDR_START};
+
+/// 设备树里 cpu 节点的 reg，也就是 MPIDR 的亲和性字段
+static mut CPU_MPIDRS: Vec<usize> = Vec::new();
+
+pub(crate) fn record_cpus(fdt: &Fdt) {
+    unsafe {
+        CPU_MPIDRS = fdt.cpus().map(|cpu| cpu.ids().first()).collect();
+    }
+}
+
+/// 从核入口，x0 是 cpu_on 传进来的栈顶（物理地址）
+///
+/// 页表直接复用主核建好的启动页表
+#[naked]
+unsafe extern "C" fn _secondary_start() -> ! {
+    core::arch::asm!("
+        mrs     x19, mpidr_el1
+        and     x19, x19, #0xffffff     // get current CPU id
+        mov     sp, x0
+
+        bl      {switch_to_el1}         // switch to EL1
+        bl      {init_mmu}              // setup MMU
+
+        mov     x8, {phys_virt_offset}  // set SP to the high address
+        add     sp, sp, x8
+
+        mov     x0, x19                 // call rust_secondary_main(cpu_id)
+        ldr     x8, ={entry}
+        blr     x8
+        b      .",
+        switch_to_el1 = sym switch_to_el1,
+        init_mmu = sym init_mmu,
+        phys_virt_offset = const VIRT_ADDR_START,
+        entry = sym rust_secondary_main,
+        options(noreturn),
+    )
+}
+
+extern "C" fn rust_secondary_main(cpu_id: usize) {
+    secondary_init(cpu_id);
+    trap::init();
+    gic::init_secondary();
+    timer::init();
+
+    // enable fp
+    CPACR_EL1.write(CPACR_EL1::FPEN::TrapNothing);
+    barrier::isb(barrier::SY);
+
+    info!("cpu {} started", cpu_id);
+
+    ArchInterface::main(cpu_id);
+    shutdown();
+}
+
+/// 从核打开 MMU 之前是不经过 cache 访问栈的，先把主核 cache 里的旧数据写回并作废
+fn clean_dcache_range(start: usize, end: usize) {
+    const CACHE_LINE_SIZE: usize = 64;
+    for addr in (start..end).step_by(CACHE_LINE_SIZE) {
+        unsafe { asm!("dc civac, {}", in(reg) addr) };
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// 最多启动 `cpu_num` 个从核，返回实际启动的个数
+pub fn start_secondary_cpus(cpu_num: usize) -> usize {
+    let boot_cpu = hart_id();
+    let entry = _secondary_start as usize & !VIRT_ADDR_START;
+    let mut started = 0;
+    for &mpidr in unsafe { CPU_MPIDRS.iter() } {
+        if started == cpu_num {
+            break;
+        }
+        if mpidr == boot_cpu || mpidr >= crate::MAX_CPU_NUM {
+            continue;
+        }
+        let stack_top = alloc_secondary_stack();
+        clean_dcache_range(stack_top - STACK_SIZE, stack_top);
+        if !psci::cpu_on(mpidr, entry, stack_top & !VIRT_ADDR_START) {
+            continue;
+        }
+        wait_online(mpidr);
+        started += 1;
+    }
+    started
+}
+
+/// 用 inner shareable 的 tlbi 广播给所有核，不需要额外的 IPI
+pub fn tlb_shootdown(vaddr: Option<VirtAddr>) {
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            asm!("tlbi vaale1is, {}; dsb ish; isb", in(reg) ((vaddr.0 >> 12) & 0xFFFF_FFFF_FFFF))
+        } else {
+            asm!("tlbi vmalle1is; dsb ish; isb")
+        }
+    }
+}
@@ -4,7 +4,10 @@ use aarch64_cpu::registers::{Writeable, ESR_EL1, FAR_EL1, VBAR_EL1};
 use tock_registers::interfaces::Readable;
 
 use crate::{
-    aarch64::{gic::handle_irq, timer::set_next_timer},
+    aarch64::{
+        gic::{handle_irq, TIMER_IRQ_NUM},
+        timer::set_next_timer,
+    },
     ArchInterface, TrapType,
 };
 
@@ -12,6 +15,11 @@ use super::Context;
 
 global_asm!(include_str!("trap.S"));
 
+/// 应急栈的栈顶，内核地址上的 data abort 换到这里处理
+#[no_mangle]
+#[percpu::def_percpu]
+static TRAP_STACK_TOP: usize = 0;
+
 #[repr(u8)]
 #[derive(Debug, PartialEq)]
 #[allow(dead_code)]
@@ -35,9 +43,18 @@ enum TrapSource {
 #[no_mangle]
 fn handle_exception(tf: &mut Context, kind: TrapKind, source: TrapSource) -> TrapType {
     if kind == TrapKind::Irq {
-        set_next_timer();
-        handle_irq(|_irq| {});
-        return TrapType::Time;
+        let mut trap_type = TrapType::SupervisorExternal;
+        handle_irq(|irq| {
+            if irq as usize == TIMER_IRQ_NUM {
+                set_next_timer();
+                crate::add_timer_tick();
+                trap_type = TrapType::Time;
+            } else {
+                crate::irq::dispatch(irq as usize);
+            }
+        });
+        ArchInterface::kernel_interrupt(tf, trap_type);
+        return trap_type;
     }
     if kind != TrapKind::Synchronous {
         panic!(
@@ -54,16 +71,23 @@ fn handle_exception(tf: &mut Context, kind: TrapKind, source: TrapSource) -> Tra
             TrapType::Breakpoint
         }
         Some(ESR_EL1::EC::Value::SVC64) => TrapType::UserEnvCall,
-        Some(ESR_EL1::EC::Value::DataAbortLowerEL)
-        | Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => {
+        Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => {
+            TrapType::InstructionPageFault(FAR_EL1.get() as _)
+        }
+        Some(ESR_EL1::EC::Value::DataAbortLowerEL) => {
             let iss = esr.read(ESR_EL1::ISS);
-            warn!(
+            debug!(
                 "EL0 Page Fault @ {:#x}, FAR={:#x}, ISS={:#x}",
                 tf.elr,
                 FAR_EL1.get(),
                 iss
             );
-            TrapType::InstructionPageFault(FAR_EL1.get() as _)
+            // ISS 的 WnR 位表示这次访问是写
+            if iss & (1 << 6) != 0 {
+                TrapType::StorePageFault(FAR_EL1.get() as _)
+            } else {
+                TrapType::LoadPageFault(FAR_EL1.get() as _)
+            }
         }
         Some(ESR_EL1::EC::Value::DataAbortCurrentEL)
         | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => {
@@ -100,6 +124,7 @@ pub fn init() {
 
 // 设置中断
 pub fn init_interrupt() {
+    TRAP_STACK_TOP.write_current(crate::smp::trap_stack_top());
     // unsafe {
     //     asm!("brk #0");
     // }
@@ -152,12 +177,9 @@ extern "C" fn user_restore(context: *mut Context) -> TrapKind {
     }
 }
 
-pub fn run_user_task(cx: &mut Context) -> Option<()> {
+pub fn run_user_task(cx: &mut Context) -> TrapType {
     let trap_kind = user_restore(cx);
-    match handle_exception(cx, trap_kind, TrapSource::LowerAArch64) {
-        TrapType::UserEnvCall => Some(()),
-        _ => None,
-    }
+    handle_exception(cx, trap_kind, TrapSource::LowerAArch64)
 }
 
 #[allow(dead_code)]
@@ -166,6 +188,18 @@ pub fn enable_irq() {
     unsafe { asm!("msr daifclr, #2") };
 }
 
+#[inline(always)]
+pub fn disable_irq() {
+    unsafe { asm!("msr daifset, #2") };
+}
+
+#[inline(always)]
+pub fn irq_enabled() -> bool {
+    let daif: usize;
+    unsafe { asm!("mrs {}, daif", out(reg) daif) };
+    daif & (1 << 7) == 0
+}
+
 #[inline(always)]
 pub fn enable_external_irq() {
     // unsafe {
@@ -371,10 +371,10 @@ impl Debug for VirtAddr {
 }
 impl PhysAddr {
     pub fn get_ref<T>(&self) -> &'static T {
-        unsafe { (self.0 as *const T).as_ref().unwrap() }
+        unsafe { self.get_ptr::<T>().as_ref().unwrap() }
     }
     pub fn get_mut<T>(&self) -> &'static mut T {
-        unsafe { (self.0 as *mut T).as_mut().unwrap() }
+        unsafe { self.get_mut_ptr::<T>().as_mut().unwrap() }
     }
 }
 impl PhysPage {
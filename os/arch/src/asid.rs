@@ -0,0 +1,141 @@
+//! 地址空间标识：riscv satp 的 ASID、aarch64 TTBR0 的 ASID、x86 的 PCID、LoongArch 的 ASID
+//!
+//! ASID 按代分配，用完之后代数加一，每个核在下一次切换页表时刷新整个 TLB，
+//! 地址空间在新的一代里第一次被切换进来时再重新分配
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use spin::Mutex;
+
+use crate::{cpu_online_mask, hart_id};
+
+/// 最多用多少个 ASID，由编译时的环境变量 ASID_LIMIT 决定，没有设置时是 256
+///
+/// 硬件支持得再多也只用这么多，同时活跃的地址空间很少超过这个数；调小之后换代更频繁，方便测试
+const ASID_LIMIT: usize = parse_asid_limit(option_env!("ASID_LIMIT"));
+
+const fn parse_asid_limit(limit: Option<&str>) -> usize {
+    let bytes = match limit {
+        Some(limit) if !limit.is_empty() => limit.as_bytes(),
+        _ => return 256,
+    };
+    let mut num = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "ASID_LIMIT must be a number");
+        num = num * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(num >= 2, "ASID_LIMIT must be at least 2");
+    num
+}
+
+/// 切换页表之后需要的 TLB 刷新
+pub(crate) enum AsidFlush {
+    /// TLB 里这个 ASID 的表项都还有效
+    None,
+    /// 只刷新这个 ASID 的表项
+    Asid,
+    /// 刷新整个 TLB
+    All,
+}
+
+struct AsidAllocator {
+    /// 硬件支持的 ASID 个数，0 号留给内核，不到 2 个就当作不支持
+    count: usize,
+    generation: usize,
+    /// 这一代里下一个没分配过的 ASID
+    next: usize,
+    /// 根页表的物理地址 -> (分配时的代数, ASID)
+    tables: BTreeMap<usize, (usize, usize)>,
+    /// 换代之后还没刷新过 TLB 的核
+    flush_pending: usize,
+    /// 不是当前页表时被修改过，切换进来之前要刷掉旧表项的页表
+    stale: BTreeSet<usize>,
+}
+
+static ASID_ALLOCATOR: Mutex<AsidAllocator> = Mutex::new(AsidAllocator {
+    count: 0,
+    generation: 0,
+    next: 1,
+    tables: BTreeMap::new(),
+    flush_pending: 0,
+    stale: BTreeSet::new(),
+});
+
+impl AsidAllocator {
+    /// 这一代里分到的 ASID
+    fn current(&self, root: usize) -> Option<usize> {
+        match self.tables.get(&root) {
+            Some(&(generation, asid)) if generation == self.generation => Some(asid),
+            _ => None,
+        }
+    }
+
+    fn alloc(&mut self, root: usize) -> usize {
+        if self.next == self.count {
+            // 用完了，换一代，旧的 ASID 在每个核刷新 TLB 之后才能再用
+            self.generation += 1;
+            self.next = 1;
+            self.tables.clear();
+            self.stale.clear();
+            self.flush_pending = cpu_online_mask();
+        }
+        let asid = self.next;
+        self.next += 1;
+        self.tables.insert(root, (self.generation, asid));
+        asid
+    }
+}
+
+/// 主核探测到硬件支持的 ASID 个数之后调用
+pub(crate) fn init_asid(count: usize) {
+    let count = count.min(ASID_LIMIT);
+    info!("asid: {} available", count);
+    ASID_ALLOCATOR.lock().count = count;
+}
+
+/// 可用的 ASID 个数和换过几代，不支持 ASID 时个数为 0 或 1
+pub fn asid_stats() -> (usize, usize) {
+    let allocator = ASID_ALLOCATOR.lock();
+    (allocator.count, allocator.generation)
+}
+
+/// 切换到根页表为 `root` 的地址空间，返回要用的 ASID 和切换之后需要的刷新
+pub(crate) fn switch_asid(root: usize) -> (usize, AsidFlush) {
+    let mut allocator = ASID_ALLOCATOR.lock();
+    if allocator.count < 2 {
+        return (0, AsidFlush::All);
+    }
+    let asid = match allocator.current(root) {
+        Some(asid) => asid,
+        None => allocator.alloc(root),
+    };
+    let stale = allocator.stale.remove(&root);
+    let cpu = 1 << hart_id();
+    if allocator.flush_pending & cpu != 0 {
+        allocator.flush_pending &= !cpu;
+        (asid, AsidFlush::All)
+    } else if stale {
+        (asid, AsidFlush::Asid)
+    } else {
+        (asid, AsidFlush::None)
+    }
+}
+
+/// 只有一个核的架构上修改了不是当前页表的 `root`，没法单独刷新它的某一页，
+/// 等它下次切换进来时再刷新它的整个 ASID
+#[allow(dead_code)]
+pub(crate) fn mark_stale(root: usize) {
+    let mut allocator = ASID_ALLOCATOR.lock();
+    // 不是这一代分配的 ASID 在换代时已经刷掉了
+    if allocator.current(root).is_some() {
+        allocator.stale.insert(root);
+    }
+}
+
+/// 页表被回收，它的 ASID 要等换代之后才会再分配出去
+pub(crate) fn release_asid(root: usize) {
+    let mut allocator = ASID_ALLOCATOR.lock();
+    allocator.tables.remove(&root);
+    allocator.stale.remove(&root);
+}
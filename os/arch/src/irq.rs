@@ -0,0 +1,77 @@
+//! 与架构无关的外部中断注册和分发
+//!
+//! 中断控制器由各个架构实现：riscv64 的 PLIC、aarch64 的 GICv2、
+//! x86_64 的 IO APIC 和 loongarch64 的 EIOINTC。中断号就是控制器上的编号。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::add_irq;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        use crate::riscv64::plic::set_enable;
+    } else if #[cfg(target_arch = "aarch64")] {
+        use crate::aarch64::gic::set_enable;
+    } else if #[cfg(target_arch = "x86_64")] {
+        use crate::x86_64::apic::set_enable;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        use crate::loongarch64::eiointc::set_enable;
+    }
+}
+
+/// 所有架构都不会超过这个数目
+pub const MAX_IRQ_COUNT: usize = 1024;
+
+pub type IrqHandler = fn();
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// 处理函数的地址，0 表示没有注册，中断里会在各个核上并发读取
+static IRQ_HANDLERS: [AtomicUsize; MAX_IRQ_COUNT] = [NO_HANDLER; MAX_IRQ_COUNT];
+
+/// 注册 `irq` 的处理函数并在中断控制器上打开它
+///
+/// 中断号超出范围或者已经注册过时返回 false
+pub fn register_irq_handler(irq: usize, handler: IrqHandler) -> bool {
+    let registered = IRQ_HANDLERS.get(irq).map_or(false, |slot| {
+        slot.compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    });
+    if registered {
+        enable(irq);
+    }
+    registered
+}
+
+#[inline]
+pub fn enable(irq: usize) {
+    set_enable(irq, true);
+}
+
+#[inline]
+pub fn disable(irq: usize) {
+    set_enable(irq, false);
+}
+
+/// 调用 `irq` 的处理函数并计数，没有处理函数时返回 false
+///
+/// 由各架构的中断入口在中断控制器上认领到中断之后调用
+pub fn dispatch(irq: usize) -> bool {
+    add_irq(irq);
+    let handler = IRQ_HANDLERS
+        .get(irq)
+        .map_or(0, |slot| slot.load(Ordering::Acquire));
+    match handler {
+        0 => {
+            warn!("unhandled IRQ {}", irq);
+            false
+        }
+        handler => {
+            // 只存过 IrqHandler 转换来的地址
+            let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
+            handler();
+            true
+        }
+    }
+}
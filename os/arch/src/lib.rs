@@ -15,12 +15,16 @@ extern crate log;
 
 mod addr;
 mod api;
+mod asid;
+pub mod irq;
+mod smp;
 // mod pte;
 // pub use pte::MappingFlags;
 #[cfg(target_arch = "riscv64")]
 mod riscv64;
 
 use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use alloc::vec::Vec;
 #[cfg(target_arch = "riscv64")]
@@ -46,26 +50,37 @@ pub use loongarch64::*;
 
 pub use addr::*;
 pub use api::*;
-
-// pub trait ContextOps {
-//     fn set_sp(&mut self, sp: usize);
-//     fn sp(&self) -> usize;
-//     fn set_ra(&mut self, ra: usize);
-//     fn ra(&self) -> usize;
-//     fn set_sepc(&mut self, sepc: usize);
-//     fn sepc(&self) -> usize;
-
-//     fn args(&self) -> [usize; 6];
-//     fn set_arg0(&mut self, ret: usize);
-//     fn set_arg1(&mut self, ret: usize);
-//     fn set_arg2(&mut self, ret: usize);
-
-//     fn syscall_number(&self) -> usize;
-//     fn syscall_ok(&mut self);
-
-//     fn set_ret(&mut self, ret: usize);
-//     fn set_tls(&mut self, tls: usize);
-// }
+pub use asid::asid_stats;
+pub use irq::register_irq_handler;
+pub use smp::{cpu_online_count, cpu_online_mask, handle_pending_ipi, hart_id, MAX_CPU_NUM};
+
+/// 用户上下文的统一访问接口，内核只通过它读写 [Context]
+pub trait ContextOps {
+    fn set_sp(&mut self, sp: usize);
+    fn sp(&self) -> usize;
+    /// 返回地址。x86_64 没有 ra 寄存器，读写的是 `[rsp]` 处的返回地址槽，
+    /// 只能在这个上下文所属的地址空间里、栈顶那一页已经映射时调用
+    fn set_ra(&mut self, ra: usize);
+    fn ra(&self) -> usize;
+    fn set_pc(&mut self, pc: usize);
+    fn pc(&self) -> usize;
+    fn set_tls(&mut self, tls: usize);
+    fn tls(&self) -> usize;
+
+    /// 系统调用号
+    fn syscall_number(&self) -> usize;
+    /// 系统调用的 6 个参数
+    fn args(&self) -> [usize; 6];
+    fn set_arg0(&mut self, arg: usize);
+    fn set_arg1(&mut self, arg: usize);
+    fn set_arg2(&mut self, arg: usize);
+
+    /// 系统调用返回值
+    fn set_ret(&mut self, ret: usize);
+    fn ret(&self) -> usize;
+    /// 跳过系统调用指令，x86_64 和 aarch64 陷入时已经指向下一条指令
+    fn syscall_ok(&mut self);
+}
 
 #[derive(Debug)]
 pub enum ContextArgs {
@@ -93,31 +108,72 @@ pub enum TrapType {
     IllegalInstruction(usize),
 }
 
+/// 一个页表项映射的大小，4K 以外的都是大页
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MapPageSize {
     Page4k,
     Page2m,
     Page1G,
 }
 
+impl MapPageSize {
+    /// 包含的 4K 页数
+    #[inline]
+    pub const fn pages(&self) -> usize {
+        1 << (9 * self.level())
+    }
+
+    /// 叶子页表项所在的层级，最底层的页表是第 0 层
+    #[inline]
+    pub const fn level(&self) -> usize {
+        match self {
+            MapPageSize::Page4k => 0,
+            MapPageSize::Page2m => 1,
+            MapPageSize::Page1G => 2,
+        }
+    }
+
+    /// `vpn` 和 `ppn` 都对齐到 `self` 才能用这种大小的页映射
+    #[inline]
+    pub const fn aligned(&self, vpn: VirtPage, ppn: PhysPage) -> bool {
+        (vpn.0 | ppn.0) % self.pages() == 0
+    }
+}
+
 const STACK_SIZE: usize = 0x80000;
 const CONTEXT_SIZE: usize = size_of::<Context>();
 
 #[link_section = ".bss.stack"]
 static mut BOOT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
-static mut INT_RECORDS: Vec<usize> = Vec::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个外部中断号收到的次数，所有核一起计数
+static INT_RECORDS: [AtomicUsize; irq::MAX_IRQ_COUNT] = [ZERO; irq::MAX_IRQ_COUNT];
+/// 时钟中断不占外部中断号，单独计数
+static TIMER_TICKS: AtomicUsize = AtomicUsize::new(0);
 
 pub fn add_irq(irq: usize) {
-    unsafe {
-        while INT_RECORDS.len() < 256 {
-            INT_RECORDS.push(0);
-        }
-        INT_RECORDS[irq] += 1;
+    if let Some(count) = INT_RECORDS.get(irq) {
+        count.fetch_add(1, Ordering::Relaxed);
     }
 }
 
+#[inline]
+pub fn add_timer_tick() {
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
 pub fn get_int_records() -> Vec<usize> {
-    unsafe { INT_RECORDS.clone() }
+    INT_RECORDS
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .collect()
+}
+
+pub fn timer_ticks() -> usize {
+    TIMER_TICKS.load(Ordering::Relaxed)
 }
 
 pub fn clear_bss() {
@@ -1,5 +1,13 @@
 pub const VIRT_ADDR_START: usize = 0x9000_0000_0000_0000;
+pub const VIRT_ADDR_START_MASK: usize = !VIRT_ADDR_START;
 pub const USER_ADDR_MAX: usize = 0x0000_ffff_ffff_ffff;
 pub const PAGE_SIZE: usize = 4096;
 pub const PAGE_ITEM_COUNT: usize = 512;
 pub const SIG_RETURN_ADDR: usize = 0x40_0000_0000;
+/// 内核在直接映射窗口（DMW）里，不经过页表，没法按页设置权限
+///
+/// DMW 把 `VIRT_ADDR_START` 开始的整段地址直接换算成物理地址，每个窗口只能设置
+/// 特权级和缓存属性，没有读写执行位，也不会缺页。所以这里的内核做不到 W^X，
+/// 内核栈下面也挖不出保护页，溢出检查和 `remap_test` 都只在其他架构上做。
+/// 要做到的话得把内核挪出 DMW、改由 TLB 映射，TLB refill 也要能处理内核地址
+pub const KERNEL_PAGED: bool = false;
@@ -1,6 +1,6 @@
 use core::ops::{Index, IndexMut};
 
-use crate::ContextArgs;
+use crate::{ContextArgs, ContextOps};
 
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[allow(missing_docs)]
@@ -29,21 +29,85 @@ impl Context {
     }
 }
 
-impl Context {
-    pub fn syscall_ok(&mut self) {
-        self.era += 4;
+impl ContextOps for Context {
+    #[inline]
+    fn set_sp(&mut self, sp: usize) {
+        self.regs[3] = sp;
+    }
+
+    #[inline]
+    fn sp(&self) -> usize {
+        self.regs[3]
+    }
+
+    #[inline]
+    fn set_ra(&mut self, ra: usize) {
+        self.regs[1] = ra;
+    }
+
+    #[inline]
+    fn ra(&self) -> usize {
+        self.regs[1]
+    }
+
+    #[inline]
+    fn set_pc(&mut self, pc: usize) {
+        self.era = pc;
+    }
+
+    #[inline]
+    fn pc(&self) -> usize {
+        self.era
+    }
+
+    #[inline]
+    fn set_tls(&mut self, tls: usize) {
+        self.regs[2] = tls;
+    }
+
+    #[inline]
+    fn tls(&self) -> usize {
+        self.regs[2]
+    }
+
+    #[inline]
+    fn syscall_number(&self) -> usize {
+        self.regs[11]
+    }
+
+    #[inline]
+    fn args(&self) -> [usize; 6] {
+        self.regs[4..10].try_into().expect("args slice force convert")
+    }
+
+    #[inline]
+    fn set_arg0(&mut self, arg: usize) {
+        self.regs[4] = arg;
     }
 
     #[inline]
-    pub fn args(&self) -> [usize; 6] {
-        [
-            self.regs[4],
-            self.regs[5],
-            self.regs[6],
-            self.regs[7],
-            self.regs[8],
-            self.regs[9],
-        ]
+    fn set_arg1(&mut self, arg: usize) {
+        self.regs[5] = arg;
+    }
+
+    #[inline]
+    fn set_arg2(&mut self, arg: usize) {
+        self.regs[6] = arg;
+    }
+
+    #[inline]
+    fn set_ret(&mut self, ret: usize) {
+        self.regs[4] = ret;
+    }
+
+    #[inline]
+    fn ret(&self) -> usize {
+        self.regs[4]
+    }
+
+    #[inline]
+    fn syscall_ok(&mut self) {
+        self.era += 4;
     }
 }
 
@@ -0,0 +1,83 @@
+//! LoongArch 扩展 IO 中断控制器 (EIOINTC)，通过 IOCSR 访问
+//!
+//! 256 个中断全部路由到 0 号核的 HWI0 引脚上
+
+use core::arch::asm;
+
+const IOCSR_MISC_FUNC: usize = 0x420;
+const IOCSR_MISC_FUNC_EXT_IOI_EN: u64 = 1 << 48;
+
+const EIOINTC_REG_IPMAP: usize = 0x14c0;
+const EIOINTC_REG_ENABLE: usize = 0x1600;
+const EIOINTC_REG_ISR: usize = 0x1800;
+const EIOINTC_REG_ROUTE: usize = 0x1c00;
+
+const EIOINTC_IRQS: usize = 256;
+
+/// ESTAT.IS 和 ECFG.LIE 里 HWI0 对应的位
+pub(crate) const HWI0_BIT: usize = 1 << 2;
+
+#[inline]
+unsafe fn iocsr_read_d(reg: usize) -> u64 {
+    let val: u64;
+    asm!("iocsrrd.d {}, {}", out(reg) val, in(reg) reg);
+    val
+}
+
+#[inline]
+unsafe fn iocsr_write_d(reg: usize, val: u64) {
+    asm!("iocsrwr.d {}, {}", in(reg) val, in(reg) reg);
+}
+
+#[inline]
+unsafe fn iocsr_write_b(reg: usize, val: u8) {
+    asm!("iocsrwr.b {}, {}", in(reg) val as usize, in(reg) reg);
+}
+
+pub(crate) fn init() {
+    unsafe {
+        iocsr_write_d(
+            IOCSR_MISC_FUNC,
+            iocsr_read_d(IOCSR_MISC_FUNC) | IOCSR_MISC_FUNC_EXT_IOI_EN,
+        );
+        // 每 32 个中断一组，全部送到 HWI0
+        for group in 0..8 {
+            iocsr_write_b(EIOINTC_REG_IPMAP + group, 1);
+        }
+        for irq in 0..EIOINTC_IRQS {
+            // 路由到 0 号核，先全部屏蔽
+            iocsr_write_b(EIOINTC_REG_ROUTE + irq, 1);
+        }
+        for word in 0..EIOINTC_IRQS / 64 {
+            iocsr_write_d(EIOINTC_REG_ENABLE + word * 8, 0);
+        }
+        // 打开 ECFG 里 HWI0 的局部中断使能
+        asm!("csrxchg {val}, {mask}, 0x4", val = inout(reg) HWI0_BIT => _, mask = in(reg) HWI0_BIT);
+    }
+}
+
+pub(crate) fn set_enable(irq: usize, enabled: bool) {
+    if irq >= EIOINTC_IRQS {
+        return;
+    }
+    let reg = EIOINTC_REG_ENABLE + irq / 64 * 8;
+    let bit = 1u64 << (irq % 64);
+    unsafe {
+        let val = iocsr_read_d(reg);
+        iocsr_write_d(reg, if enabled { val | bit } else { val & !bit });
+    }
+}
+
+/// 处理所有挂起的中断，写 1 清除 ISR 里对应的位
+pub(crate) fn handle_irq() {
+    for word in 0..EIOINTC_IRQS / 64 {
+        let reg = EIOINTC_REG_ISR + word * 8;
+        let mut pending = unsafe { iocsr_read_d(reg) };
+        while pending != 0 {
+            let bit = pending.trailing_zeros() as usize;
+            pending &= pending - 1;
+            unsafe { iocsr_write_d(reg, 1 << bit) };
+            crate::irq::dispatch(word * 64 + bit);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use core::arch::asm;
+
+/// 内核线程切换时保存的上下文，只包含 callee-saved 寄存器
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct KContext {
+    ksp: usize,
+    kra: usize,
+    kfp: usize,
+    /// s0 - s8
+    ks: [usize; 9],
+}
+
+impl KContext {
+    pub fn blank() -> Self {
+        Self::default()
+    }
+
+    /// 切换到该上下文时从 `entry` 开始执行，使用 `ksp` 作为内核栈
+    pub fn new(entry: usize, ksp: usize) -> Self {
+        Self {
+            ksp,
+            kra: entry,
+            ..Default::default()
+        }
+    }
+}
+
+/// 保存当前的内核上下文到 `from`，并切换到 `to`
+#[naked]
+pub unsafe extern "C" fn context_switch(from: *mut KContext, to: *const KContext) {
+    asm!(
+        // 保存当前上下文
+        "
+            st.d    $sp, $a0, 0*8
+            st.d    $ra, $a0, 1*8
+            st.d    $fp, $a0, 2*8
+            st.d    $s0, $a0, 3*8
+            st.d    $s1, $a0, 4*8
+            st.d    $s2, $a0, 5*8
+            st.d    $s3, $a0, 6*8
+            st.d    $s4, $a0, 7*8
+            st.d    $s5, $a0, 8*8
+            st.d    $s6, $a0, 9*8
+            st.d    $s7, $a0, 10*8
+            st.d    $s8, $a0, 11*8
+        ",
+        // 恢复下一个上下文
+        "
+            ld.d    $sp, $a1, 0*8
+            ld.d    $ra, $a1, 1*8
+            ld.d    $fp, $a1, 2*8
+            ld.d    $s0, $a1, 3*8
+            ld.d    $s1, $a1, 4*8
+            ld.d    $s2, $a1, 5*8
+            ld.d    $s3, $a1, 6*8
+            ld.d    $s4, $a1, 7*8
+            ld.d    $s5, $a1, 8*8
+            ld.d    $s6, $a1, 9*8
+            ld.d    $s7, $a1, 10*8
+            ld.d    $s8, $a1, 11*8
+            jirl    $zero, $ra, 0
+        ",
+        options(noreturn)
+    )
+}
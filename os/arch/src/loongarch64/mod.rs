@@ -2,6 +2,8 @@ mod boot;
 mod console;
 mod consts;
 mod context;
+pub(crate) mod eiointc;
+mod kcontext;
 mod page_table;
 mod sigtrx;
 mod timer;
@@ -10,17 +12,22 @@ mod trap;
 pub use console::{console_getchar, console_putchar};
 pub use consts::*;
 pub use context::Context;
+pub use kcontext::{context_switch, KContext};
 use loongarch64::register::euen;
 pub use page_table::*;
 pub use timer::{get_time, time_to_usec};
-pub use trap::{enable_external_irq, enable_irq, init_interrupt, run_user_task};
+pub use trap::{
+    disable_irq, enable_external_irq, enable_irq, init_interrupt, irq_enabled, run_user_task,
+};
 
-use crate::{clear_bss, ArchInterface};
+use crate::{clear_bss, ArchInterface, VirtAddr};
 
 pub fn rust_tmp_main(hart_id: usize) {
     clear_bss();
-    ArchInterface::init_logging();
+    crate::smp::primary_init(hart_id);
     allocator::init();
+    ArchInterface::init_logging();
+    page_table::detect_asid();
     trap::set_trap_vector_base();
     sigtrx::init();
 
@@ -46,3 +53,14 @@ pub fn shutdown() -> ! {
         unsafe { loongarch64::asm::idle() };
     }
 }
+
+/// 从核需要通过 IPI 邮箱唤醒，目前还没有实现，只运行在 0 号核上
+pub fn start_secondary_cpus(_cpu_num: usize) -> usize {
+    0
+}
+
+/// 只有一个核在运行，刷新本地 TLB 就够了
+#[inline]
+pub fn tlb_shootdown(vaddr: Option<VirtAddr>) {
+    flush_tlb(vaddr)
+}
@@ -1,8 +1,11 @@
-use alloc::sync::Arc;
+use core::mem::ManuallyDrop;
+
 use loongarch64::register::pgdl;
 
+use crate::asid::{mark_stale, release_asid, switch_asid, AsidFlush};
 use crate::{
-    ArchInterface, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage, PAGE_ITEM_COUNT, PAGE_SIZE,
+    ArchInterface, MapPageSize, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage,
+    PAGE_ITEM_COUNT, PAGE_SIZE,
 };
 
 use super::sigtrx::get_trx_mapping;
@@ -40,6 +43,12 @@ impl PTE {
     pub fn get_next_ptr(&self) -> PhysAddr {
         PhysAddr(self.0 & 0xffff_ffff_f000)
     }
+
+    /// 目录项里带 GH 位的是大页，否则是下一级页表的地址
+    #[inline]
+    pub const fn is_huge(&self) -> bool {
+        self.flags().contains(PTEFlags::GH)
+    }
 }
 
 impl From<MappingFlags> for PTEFlags {
@@ -100,17 +109,29 @@ pub fn get_pte_list(paddr: PhysAddr) -> &'static mut [PTE] {
 pub struct PageTable(pub(crate) PhysAddr);
 
 impl PageTable {
-    pub fn alloc() -> Arc<Self> {
+    pub fn alloc() -> Self {
         let addr = ArchInterface::frame_alloc_persist().into();
         let page_table = Self(addr);
         page_table.restore();
-        Arc::new(page_table)
+        page_table
+    }
+
+    /// 页表的根物理地址，内核用它来标识地址空间
+    #[inline]
+    pub const fn token(&self) -> usize {
+        self.0 .0
+    }
+
+    /// 借用 token 对应的页表，返回值 drop 时不会回收页表
+    #[inline]
+    pub fn from_token(token: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self(PhysAddr(token)))
     }
 
     #[inline]
     pub fn restore(&self) {
         let clear_l3 = |l3_ptr: &PTE| {
-            if !l3_ptr.is_valid() {
+            if !l3_ptr.is_valid() || l3_ptr.is_huge() {
                 return;
             }
             l3_ptr
@@ -122,7 +143,7 @@ impl PageTable {
             .slice_mut_with_len::<PTE>(0x100)
             .iter()
             .for_each(|l1_pte| {
-                if l1_pte.is_valid() {
+                if !l1_pte.is_valid() || l1_pte.is_huge() {
                     return;
                 }
                 l1_pte
@@ -138,83 +159,200 @@ impl PageTable {
 
     #[inline]
     pub fn change(&self) {
+        let (asid, flush) = switch_asid(self.0 .0);
         pgdl::set_base(self.0.addr());
-        flush_tlb(None);
+        unsafe {
+            core::arch::asm!("csrwr {}, 0x18", inout(reg) asid => _);
+            match flush {
+                AsidFlush::None => {}
+                AsidFlush::Asid => core::arch::asm!("invtlb 0x04, {}, $r0", in(reg) asid),
+                AsidFlush::All => flush_tlb(None),
+            }
+        }
     }
 
+    /// 内核在直接映射窗口里，不经过页表，没有要共享的映射
     #[inline]
-    pub fn get_mut_entry(&self, vpn: VirtPage) -> &mut PTE {
-        let l1_list = self.0.slice_mut_with_len::<PTE>(512);
-        let l1_index = (vpn.0 >> (9 * 2)) & 0x1ff;
+    pub fn share_kernel(&self) {}
+
+    /// 只能刷新当前 ASID 里的页，不是当前页表的话等它下次切换进来时刷新整个 ASID
+    fn flush_page(&self, vaddr: VirtAddr) {
+        let pgdl: usize;
+        unsafe { core::arch::asm!("csrrd {}, 0x19", out(reg) pgdl) };
+        if pgdl & !(PAGE_SIZE - 1) == self.0 .0 {
+            flush_tlb(Some(vaddr));
+        } else {
+            mark_stale(self.0 .0);
+        }
+    }
 
-        // l2 pte
-        let l2_pte = &mut l1_list[l1_index];
-        if !l2_pte.is_valid() {
-            *l2_pte = PTE(ArchInterface::frame_alloc_persist().to_addr());
+    /// 找到 `vpn` 在第 `level` 层的页表项，沿途缺少的页表会被分配，
+    /// 覆盖 `vpn` 的大页会被拆开
+    #[inline]
+    pub fn get_mut_entry(&self, vpn: VirtPage, level: usize) -> &mut PTE {
+        let mut pte_list = get_pte_list(self.0);
+        for i in (level + 1..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * i)) & 0x1ff];
+            if !pte.is_valid() {
+                *pte = PTE(ArchInterface::frame_alloc_persist().to_addr());
+            } else if pte.is_huge() {
+                split_huge(pte, i);
+            }
+            pte_list = get_pte_list(pte.get_next_ptr());
         }
-        let l2_list = l2_pte.get_next_ptr().slice_mut_with_len::<PTE>(512);
-        let l2_index = (vpn.0 >> 9) & 0x1ff;
+        &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff]
+    }
 
-        // l3 pte
-        let l3_pte = &mut l2_list[l2_index];
-        if !l3_pte.is_valid() {
-            *l3_pte = PTE(ArchInterface::frame_alloc_persist().to_addr());
+    /// 找到映射 `vpn` 的叶子页表项和它所在的层级
+    fn find_pte(&self, vpn: VirtPage) -> Option<(&mut PTE, usize)> {
+        let mut pte_list = get_pte_list(self.0);
+        for level in (0..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff];
+            if !pte.is_valid() {
+                return None;
+            }
+            if level == 0 || pte.is_huge() {
+                return Some((pte, level));
+            }
+            pte_list = get_pte_list(pte.get_next_ptr());
         }
-        let l3_list = l3_pte.get_next_ptr().slice_mut_with_len::<PTE>(512);
-        let l3_index = vpn.0 & 0x1ff;
-        &mut l3_list[l3_index]
+        None
     }
 
+    /// 用 `size` 大小的页把 `vpn` 映射到 `ppn`，两者都要按 `size` 对齐
     #[inline]
-    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, _level: usize) {
-        *self.get_mut_entry(vpn) = PTE::from_addr(ppn.into(), flags.into());
-        flush_tlb(Some(vpn.into()))
+    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, size: MapPageSize) {
+        assert!(size.aligned(vpn, ppn));
+        let level = size.level();
+        let pte = self.get_mut_entry(vpn, level);
+        if level == 0 {
+            *pte = PTE::from_addr(ppn.into(), flags.into());
+            self.flush_page(vpn.into());
+            return;
+        }
+        // 大页表项的第 12 位是 G，不能带上 NX
+        let old = *pte;
+        *pte = PTE::from_addr(
+            ppn.into(),
+            (PTEFlags::from(flags) - PTEFlags::NX) | PTEFlags::GH,
+        );
+        // 原来是下一级页表的话，整个换成大页
+        if old.is_valid() && !old.is_huge() {
+            free_table(old.get_next_ptr(), level - 1);
+            flush_tlb(None);
+        } else {
+            self.flush_page(vpn.into());
+        }
     }
 
+    /// 解除 `vpn` 这一个 4K 页的映射，它在大页里的话先把大页拆开
     #[inline]
     pub fn unmap(&self, vpn: VirtPage) {
-        *self.get_mut_entry(vpn) = PTE(0);
-        flush_tlb(Some(vpn.into()))
+        if self.find_pte(vpn).is_none() {
+            return;
+        }
+        *self.get_mut_entry(vpn, 0) = PTE(0);
+        self.flush_page(vpn.into())
     }
 
+    /// LoongArch 的页表项没有访问位，一律当作没访问过
     #[inline]
-    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
-        let mut paddr = self.0;
-        for i in (0..3).rev() {
-            let value = (vaddr.0 >> (12 + 9 * i)) & 0x1ff;
-            let pte = &get_pte_list(paddr)[value];
-            // 如果当前页是大页 返回相关的位置
-            // vaddr.0 % (1 << (12 + 9 * i)) 是大页内偏移
-            if !pte.is_valid() {
-                return None;
-            }
-            paddr = pte.addr()
+    pub fn test_and_clear_accessed(&self, _vpn: VirtPage) -> bool {
+        false
+    }
+
+    /// D 位在这里表示可写，不能用来判断页是否被写过，都当作被写过
+    #[inline]
+    pub fn is_dirty(&self, _vpn: VirtPage) -> bool {
+        true
+    }
+
+    /// `vpn` 映射的权限，没有映射时返回 None
+    pub fn mapping_flags(&self, vpn: VirtPage) -> Option<MappingFlags> {
+        let (pte, level) = self.find_pte(vpn)?;
+        let flags = pte.flags();
+        let mut res = MappingFlags::None;
+        if !flags.contains(PTEFlags::NR) {
+            res |= MappingFlags::R;
+        }
+        if flags.contains(PTEFlags::W) {
+            res |= MappingFlags::W;
+        }
+        // 大页表项的第 12 位是 G，映射时不带 NX
+        if level > 0 || !flags.contains(PTEFlags::NX) {
+            res |= MappingFlags::X;
         }
-        Some(PhysAddr(paddr.0 | vaddr.0 % PAGE_SIZE))
+        if flags.contains(PTEFlags::PLV_USER) {
+            res |= MappingFlags::U;
+        }
+        Some(res)
+    }
+
+    #[inline]
+    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        let (pte, level) = self.find_pte(vaddr.into())?;
+        // vaddr.0 % (PAGE_SIZE << (9 * level)) 是大页内偏移
+        Some(PhysAddr(
+            pte.get_next_ptr().0 | vaddr.0 % (PAGE_SIZE << (9 * level)),
+        ))
     }
 }
 
+/// 把第 `level` 层的大页拆成下一层的 512 个页，属性不变
+fn split_huge(pte: &mut PTE, level: usize) {
+    let table = ArchInterface::frame_alloc_persist();
+    let base = pte.get_next_ptr().0;
+    let mut attrs = pte.0 & !0xffff_ffff_f000;
+    if level == 1 {
+        attrs &= !PTEFlags::GH.bits();
+    }
+    for (i, child) in get_pte_list(table.into()).iter_mut().enumerate() {
+        *child = PTE(base + (i << (12 + 9 * (level - 1))) | attrs);
+    }
+    *pte = PTE(table.to_addr());
+}
+
+/// 回收第 `level` 层的页表和它下面的所有页表，叶子指向的页帧由内核管理
+fn free_table(paddr: PhysAddr, level: usize) {
+    if level > 0 {
+        get_pte_list(paddr)
+            .iter()
+            .filter(|x| x.is_valid() && !x.is_huge())
+            .for_each(|x| free_table(x.get_next_ptr(), level - 1));
+    }
+    ArchInterface::frame_unalloc(paddr.into());
+}
+
 impl Drop for PageTable {
     fn drop(&mut self) {
-        for root_pte in get_pte_list(self.0)[..0x100]
+        get_pte_list(self.0)[..0x100]
             .iter()
-            .filter(|x| x.is_valid())
-        {
-            get_pte_list(root_pte.addr())
-                .iter()
-                .filter(|x| x.is_valid())
-                .for_each(|x| ArchInterface::frame_unalloc(x.addr().into()));
-            ArchInterface::frame_unalloc(root_pte.addr().into());
-        }
+            .filter(|x| x.is_valid() && !x.is_huge())
+            .for_each(|x| free_table(x.get_next_ptr(), 1));
+        release_asid(self.0 .0);
         ArchInterface::frame_unalloc(self.0.into());
     }
 }
 
+/// CSR.ASID 的第 16 到 23 位是硬件实现的 ASID 位数
+pub(crate) fn detect_asid() {
+    let asid: usize;
+    unsafe { core::arch::asm!("csrrd {}, 0x18", out(reg) asid) };
+    crate::asid::init_asid(1 << ((asid >> 16) & 0xff));
+}
+
 #[inline]
 pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     if let Some(vaddr) = vaddr {
         unsafe {
-            core::arch::asm!("dbar 0; invtlb 0x05, $r0, {reg}", reg = in(reg) vaddr.0);
+            // 只刷新当前 ASID 里的这一页
+            core::arch::asm!(
+                "csrrd {asid}, 0x18",
+                "dbar 0",
+                "invtlb 0x05, {asid}, {reg}",
+                asid = out(reg) _,
+                reg = in(reg) vaddr.0,
+            );
         }
     } else {
         unsafe {
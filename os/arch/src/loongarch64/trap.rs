@@ -208,6 +208,16 @@ pub fn enable_irq() {
     crmd::set_ie(true);
 }
 
+#[inline(always)]
+pub fn disable_irq() {
+    crmd::set_ie(false);
+}
+
+#[inline(always)]
+pub fn irq_enabled() -> bool {
+    crmd::read().ie()
+}
+
 #[inline(always)]
 pub fn enable_external_irq() {
     // unsafe {
@@ -215,12 +225,9 @@ pub fn enable_external_irq() {
     // }
 }
 
-pub fn run_user_task(cx: &mut Context) -> Option<()> {
+pub fn run_user_task(cx: &mut Context) -> TrapType {
     user_restore(cx);
-    match loongarch64_trap_handler(cx) {
-        TrapType::UserEnvCall => Some(()),
-        _ => None,
-    }
+    loongarch64_trap_handler(cx)
 }
 
 #[naked]
@@ -345,9 +352,14 @@ fn loongarch64_trap_handler(tf: &mut Context) -> TrapType {
             error!("address not aligned: {:#x?}", tf);
             TrapType::Unknown
         }
+        Trap::Interrupt(_) if estat.is() & super::eiointc::HWI0_BIT != 0 => {
+            super::eiointc::handle_irq();
+            TrapType::SupervisorExternal
+        }
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
             info!("irq: {}", irq_num);
+            crate::add_timer_tick();
             TrapType::Time
         }
         Trap::Exception(Exception::Syscall) => TrapType::UserEnvCall,
@@ -356,6 +368,9 @@ fn loongarch64_trap_handler(tf: &mut Context) -> TrapType {
             TrapType::StorePageFault(badv::read().raw())
         }
         Trap::Exception(Exception::LoadPageFault) => TrapType::LoadPageFault(badv::read().raw()),
+        Trap::Exception(Exception::FetchPageFault) => {
+            TrapType::InstructionPageFault(badv::read().raw())
+        }
         _ => {
             panic!(
                 "Unhandled trap {:?} @ {:#x} BADV: {:#x}:\n{:#x?}",
@@ -1,5 +1,7 @@
 use riscv::register::sstatus;
 
+pub const PLIC_BASE: usize = 0x7000_0000;
+
 pub const CLOCK_FREQ: usize = 25000000;
 
 static DEVICE_TREE: &[u8] = include_bytes!("cv1811h-fdt.dtb");
@@ -1,3 +1,5 @@
+pub const PLIC_BASE: usize = 0x0c00_0000;
+
 pub const CLOCK_FREQ: usize = 403000000 / 62;
 
 pub fn init_device(hartid: usize, device_tree: usize) -> (usize, usize) {
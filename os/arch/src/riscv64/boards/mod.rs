@@ -10,6 +10,7 @@ cfg_if::cfg_if! {
         pub use cv1811h::*;
     } else {
         compile_error!("not support this board");
+        pub const PLIC_BASE: usize = 0x0c00_0000;
         pub const CLOCK_FREQ: usize = 12500000;
 
         pub fn init_device(hartid: usize, device_tree: usize) -> (usize, usize) {
@@ -1,5 +1,7 @@
 use riscv::register::sstatus;
 
+pub const PLIC_BASE: usize = 0x0c00_0000;
+
 pub const CLOCK_FREQ: usize = 12500000;
 
 pub fn init_device(hartid: usize, device_tree: usize) -> (usize, usize) {
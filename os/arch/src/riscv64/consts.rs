@@ -4,3 +4,5 @@ pub const USER_ADDR_MAX: usize = 0xbf_ffff_ffff;
 pub const PAGE_SIZE: usize = 4096;
 pub const PAGE_ITEM_COUNT: usize = 512;
 pub const SIG_RETURN_ADDR: usize = 0xFFFF_FFC1_0000_0000;
+/// 内核的线性映射也由页表翻译，可以按页设置权限和保护页
+pub const KERNEL_PAGED: bool = true;
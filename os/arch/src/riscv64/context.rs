@@ -3,9 +3,9 @@ use core::{
     ops::{Index, IndexMut},
 };
 
-use riscv::register::sstatus::{self, Sstatus};
+use riscv::register::sstatus::{self, Sstatus, SPP};
 
-use crate::ContextArgs;
+use crate::{ContextArgs, ContextOps};
 
 #[repr(C)]
 #[derive(Clone)]
@@ -62,90 +62,102 @@ impl Context {
     // 创建上下文信息
     #[inline]
     pub fn new() -> Self {
+        // 保存的 sstatus 中 SIE 必须为 0，否则恢复上下文到 sret 之间可能被中断
+        let sie = sstatus::read().sie();
+        unsafe { sstatus::clear_sie() };
+        let mut sstatus = sstatus::read();
+        if sie {
+            unsafe { sstatus::set_sie() };
+        }
+        // sret 之后回到用户态
+        sstatus.set_spp(SPP::User);
         Context {
             x: [0usize; 32],
-            sstatus: sstatus::read(),
+            sstatus,
             sepc: 0,
             fsx: [0; 2],
         }
     }
 }
 
-// impl ContextOps for Context {
-//     #[inline]
-//     fn set_sp(&mut self, sp: usize) {
-//         self.x[2] = sp;
-//     }
-
-//     #[inline]
-//     fn sp(&self) -> usize {
-//         self.x[2]
-//     }
-//     #[inline]
-//     fn set_ra(&mut self, ra: usize) {
-//         self.x[1] = ra;
-//     }
-
-//     #[inline]
-//     fn ra(&self) -> usize {
-//         self.x[1]
-//     }
-
-//     #[inline]
-//     fn set_sepc(&mut self, sepc: usize) {
-//         self.sepc = sepc;
-//     }
-
-//     #[inline]
-//     fn sepc(&self) -> usize {
-//         self.sepc
-//     }
-
-//     #[inline]
-//     fn syscall_number(&self) -> usize {
-//         self.x[17]
-//     }
-
-//     #[inline]
-//     fn args(&self) -> [usize; 6] {
-//         self.x[10..16].try_into().expect("args slice force convert")
-//     }
-
-//     #[inline]
-//     fn syscall_ok(&mut self) {
-//         self.sepc += 4;
-//     }
-
-//     fn set_ret(&mut self, ret: usize) {
-//         self.x[10] = ret;
-//     }
-
-//     fn set_arg0(&mut self, ret: usize) {
-//         self.x[10] = ret;
-//     }
-
-//     fn set_arg1(&mut self, ret: usize) {
-//         self.x[11] = ret;
-//     }
-
-//     fn set_arg2(&mut self, ret: usize) {
-//         self.x[12] = ret;
-//     }
-
-//     #[inline]
-//     fn set_tls(&mut self, tls: usize) {
-//         self.x[4] = tls;
-//     }
-// }
+impl ContextOps for Context {
+    #[inline]
+    fn set_sp(&mut self, sp: usize) {
+        self.x[2] = sp;
+    }
+
+    #[inline]
+    fn sp(&self) -> usize {
+        self.x[2]
+    }
+
+    #[inline]
+    fn set_ra(&mut self, ra: usize) {
+        self.x[1] = ra;
+    }
+
+    #[inline]
+    fn ra(&self) -> usize {
+        self.x[1]
+    }
+
+    #[inline]
+    fn set_pc(&mut self, pc: usize) {
+        self.sepc = pc;
+    }
+
+    #[inline]
+    fn pc(&self) -> usize {
+        self.sepc
+    }
+
+    #[inline]
+    fn set_tls(&mut self, tls: usize) {
+        self.x[4] = tls;
+    }
 
-impl Context {
     #[inline]
-    pub fn args(&self) -> [usize; 6] {
+    fn tls(&self) -> usize {
+        self.x[4]
+    }
+
+    #[inline]
+    fn syscall_number(&self) -> usize {
+        self.x[17]
+    }
+
+    #[inline]
+    fn args(&self) -> [usize; 6] {
         self.x[10..16].try_into().expect("args slice force convert")
     }
 
     #[inline]
-    pub fn syscall_ok(&mut self) {
+    fn set_arg0(&mut self, arg: usize) {
+        self.x[10] = arg;
+    }
+
+    #[inline]
+    fn set_arg1(&mut self, arg: usize) {
+        self.x[11] = arg;
+    }
+
+    #[inline]
+    fn set_arg2(&mut self, arg: usize) {
+        self.x[12] = arg;
+    }
+
+    #[inline]
+    fn set_ret(&mut self, ret: usize) {
+        self.x[10] = ret;
+    }
+
+    #[inline]
+    fn ret(&self) -> usize {
+        self.x[10]
+    }
+
+    #[inline]
+    fn syscall_ok(&mut self) {
         self.sepc += 4;
     }
 }
@@ -4,13 +4,14 @@ use crate::VIRT_ADDR_START;
 use crate::{PTEFlags, PAGE_ITEM_COUNT, PTE};
 
 #[link_section = ".data.prepage.entry"]
-static mut PAGE_TABLE: [PTE; PAGE_ITEM_COUNT] = {
+pub(super) static mut PAGE_TABLE: [PTE; PAGE_ITEM_COUNT] = {
     let mut arr: [PTE; PAGE_ITEM_COUNT] = [PTE::new(); PAGE_ITEM_COUNT];
     // 初始化页表信息
     // 0x00000000_80000000 -> 0x80000000 (1G)
     // 高半核
     // 0xffffffc0_00000000 -> 0x00000000 (1G)
     // 0xffffffc0_80000000 -> 0x80000000 (1G)
+    // 0xffffffc0_c0000000 -> 0xc0000000 (1G)
 
     // arr[0] = PTE::from_addr(0x0000_0000, PTEFlags::VRWX);
     // arr[1] = PTE::from_addr(0x4000_0000, PTEFlags::VRWX);
@@ -18,6 +19,7 @@ static mut PAGE_TABLE: [PTE; PAGE_ITEM_COUNT] = {
     arr[0x100] = PTE::from_addr(0x0000_0000, PTEFlags::ADGVRWX);
     arr[0x101] = PTE::from_addr(0x4000_0000, PTEFlags::ADGVRWX);
     arr[0x102] = PTE::from_addr(0x8000_0000, PTEFlags::ADGVRWX);
+    arr[0x103] = PTE::from_addr(0xc000_0000, PTEFlags::ADGVRWX);
     arr[0x106] = PTE::from_addr(0x8000_0000, PTEFlags::ADVRWX);
     arr
 };
@@ -65,12 +67,11 @@ unsafe extern "C" fn _start() -> ! {
 }
 
 pub fn switch_to_kernel_page_table() {
+    // 内核页表建好之后换到它上面，启动页表里没有保护页
+    let root = crate::kernel_root()
+        .unwrap_or_else(|| unsafe { PAGE_TABLE.as_ptr() as usize & !VIRT_ADDR_START });
     unsafe {
-        riscv::register::satp::set(
-            riscv::register::satp::Mode::Sv39,
-            0,
-            (PAGE_TABLE.as_ptr() as usize & !VIRT_ADDR_START) >> 12,
-        );
+        riscv::register::satp::set(riscv::register::satp::Mode::Sv39, 0, root >> 12);
         sfence_vma_all();
     }
 }
@@ -2,12 +2,12 @@ use core::arch::{asm, global_asm};
 
 use riscv::register::{
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval,
+    sie, sstatus, stval,
 };
 
-use crate::{add_irq, riscv64::context::Context, TrapType, VIRT_ADDR_START};
+use crate::{add_timer_tick, riscv64::context::Context, TrapType, VIRT_ADDR_START};
 
-use super::timer;
+use super::{plic, timer};
 
 global_asm!(
     r"
@@ -81,9 +81,15 @@ static KERNEL_RSP: usize = 0;
 #[percpu::def_percpu]
 static USER_RSP: usize = 0;
 
+/// 应急栈的栈顶，内核地址上缺页时换到这里
+#[no_mangle]
+#[percpu::def_percpu]
+static TRAP_STACK_TOP: usize = 0;
+
 // 设置中断
 pub fn init_interrupt() {
     crate::riscv64::page_table::sigtrx::init();
+    TRAP_STACK_TOP.write_current(crate::smp::trap_stack_top());
     // 输出内核信息
 
     unsafe {
@@ -96,6 +102,7 @@ pub fn init_interrupt() {
 
     // 初始化定时器
     timer::init();
+    plic::init();
 }
 
 // 内核中断回调
@@ -126,14 +133,17 @@ fn kernel_callback(context: &mut Context) -> TrapType {
         // 时钟中断
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             timer::set_next_timeout();
-            add_irq(5);
+            add_timer_tick();
             TrapType::Time
         }
         Trap::Exception(Exception::StorePageFault) => TrapType::StorePageFault(stval),
         Trap::Exception(Exception::InstructionPageFault) => TrapType::InstructionPageFault(stval),
         Trap::Exception(Exception::IllegalInstruction) => TrapType::IllegalInstruction(stval),
         Trap::Exception(Exception::LoadPageFault) => TrapType::LoadPageFault(stval),
-        Trap::Interrupt(Interrupt::SupervisorExternal) => TrapType::SupervisorExternal,
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            plic::handle_irq();
+            TrapType::SupervisorExternal
+        }
         _ => {
             error!(
                 "内核态中断发生: {:#x} {:?}  stval {:#x}  sepc: {:#x}",
@@ -159,8 +169,25 @@ pub unsafe extern "C" fn kernelvec() {
         
             csrrw   sp, sscratch, sp
             bnez    sp, uservec
+        ",
+        // 内核地址上的缺页只会是栈溢出或者内核的 bug，原来的栈可能已经压到保护页上了，
+        // 换到这个核的应急栈。sscratch 里还是原来的 sp，照样保存进上下文
+        r"
+            csrr    sp, scause
+            addi    sp, sp, -12
+            beqz    sp, 1f                  # instruction page fault
+            addi    sp, sp, -1
+            beqz    sp, 1f                  # load page fault
+            addi    sp, sp, -2
+            bnez    sp, 2f                  # 不是 store page fault
+        1:
+            csrr    sp, stval
+            bgez    sp, 2f                  # 用户地址
+            LOAD_PERCPU sp, TRAP_STACK_TOP
+            j       3f
+        2:
             csrr    sp, sscratch
-
+        3:
             addi    sp, sp, -{cx_size}
             
             SAVE_GENERAL_REGS
@@ -275,24 +302,31 @@ pub unsafe extern "C" fn uservec() {
     );
 }
 
-/// Return Some(()) if it was interrupt by syscall, otherwise None.
-pub fn run_user_task(context: &mut Context) -> Option<()> {
+/// 进入用户态运行，返回导致回到内核的陷入类型
+pub fn run_user_task(context: &mut Context) -> TrapType {
     user_restore(context);
-    match kernel_callback(context) {
-        TrapType::UserEnvCall => Some(()),
-        _ => None,
-    }
+    kernel_callback(context)
 }
 
-#[allow(dead_code)]
 #[inline(always)]
 pub fn enable_irq() {
     unsafe {
-        sie::set_sext();
-        sie::set_ssoft();
+        sstatus::set_sie();
     }
 }
 
+#[inline(always)]
+pub fn disable_irq() {
+    unsafe {
+        sstatus::clear_sie();
+    }
+}
+
+#[inline(always)]
+pub fn irq_enabled() -> bool {
+    sstatus::read().sie()
+}
+
 #[inline(always)]
 pub fn enable_external_irq() {
     unsafe {
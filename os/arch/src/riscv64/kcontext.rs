@@ -0,0 +1,68 @@
+use core::arch::asm;
+
+/// 内核线程切换时保存的上下文，只包含 callee-saved 寄存器
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct KContext {
+    ksp: usize,
+    kra: usize,
+    ks: [usize; 12],
+}
+
+impl KContext {
+    pub fn blank() -> Self {
+        Self::default()
+    }
+
+    /// 切换到该上下文时从 `entry` 开始执行，使用 `ksp` 作为内核栈
+    pub fn new(entry: usize, ksp: usize) -> Self {
+        Self {
+            ksp,
+            kra: entry,
+            ks: [0; 12],
+        }
+    }
+}
+
+/// 保存当前的内核上下文到 `from`，并切换到 `to`
+#[naked]
+pub unsafe extern "C" fn context_switch(from: *mut KContext, to: *const KContext) {
+    asm!(
+        // 保存当前上下文
+        "
+            sd      sp, 0*8(a0)
+            sd      ra, 1*8(a0)
+            sd      s0, 2*8(a0)
+            sd      s1, 3*8(a0)
+            sd      s2, 4*8(a0)
+            sd      s3, 5*8(a0)
+            sd      s4, 6*8(a0)
+            sd      s5, 7*8(a0)
+            sd      s6, 8*8(a0)
+            sd      s7, 9*8(a0)
+            sd      s8, 10*8(a0)
+            sd      s9, 11*8(a0)
+            sd      s10, 12*8(a0)
+            sd      s11, 13*8(a0)
+        ",
+        // 恢复下一个上下文
+        "
+            ld      sp, 0*8(a1)
+            ld      ra, 1*8(a1)
+            ld      s0, 2*8(a1)
+            ld      s1, 3*8(a1)
+            ld      s2, 4*8(a1)
+            ld      s3, 5*8(a1)
+            ld      s4, 6*8(a1)
+            ld      s5, 7*8(a1)
+            ld      s6, 8*8(a1)
+            ld      s7, 9*8(a1)
+            ld      s8, 10*8(a1)
+            ld      s9, 11*8(a1)
+            ld      s10, 12*8(a1)
+            ld      s11, 13*8(a1)
+            ret
+        ",
+        options(noreturn)
+    )
+}
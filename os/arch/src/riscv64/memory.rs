@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+use fdt::Fdt;
+
+use crate::{ArchInterface, VIRT_ADDR_START, VIRT_ADDR_START_MASK};
+
+/// 启动页表的线性映射只覆盖低 4G 物理地址，见 `entry.rs`
+const LINEAR_MAPPING_END: usize = 0x1_0000_0000;
+
+/// 从设备树里找出所有内存区域，去掉保留区域后报告给内核
+///
+/// 保留区域包括内核镜像、设备树本身、`/memreserve/` 和 `/reserved-memory`
+/// （OpenSBI 会把自己所在的内存放在这里）
+pub(crate) fn add_memory_regions(fdt: &Fdt, device_tree: usize) {
+    extern "C" {
+        fn _skernel();
+        fn ekernel();
+    }
+    let mut reserved: Vec<(usize, usize)> = Vec::new();
+    reserved.push((
+        _skernel as usize & VIRT_ADDR_START_MASK,
+        ekernel as usize & VIRT_ADDR_START_MASK,
+    ));
+    let dtb = device_tree & VIRT_ADDR_START_MASK;
+    reserved.push((dtb, dtb + fdt.total_size()));
+    fdt.memory_reservations().for_each(|x| {
+        reserved.push((x.address() as usize, x.address() as usize + x.size()));
+    });
+    if let Some(node) = fdt.find_node("/reserved-memory") {
+        node.children()
+            .filter_map(|child| child.reg())
+            .flatten()
+            .for_each(|x| {
+                let start = x.starting_address as usize;
+                reserved.push((start, start + x.size.unwrap_or(0)));
+            });
+    }
+    reserved.sort_unstable();
+
+    // 开了 NUMA 的时候会有多个 memory 节点
+    fdt.all_nodes()
+        .filter(|node| node.name.split('@').next() == Some("memory"))
+        .filter_map(|node| node.reg())
+        .flatten()
+        .for_each(|x| {
+            let start = x.starting_address as usize;
+            let end = start + x.size.unwrap_or(0);
+            info!("memory region {:#X} - {:#X}", start, end);
+            if end > LINEAR_MAPPING_END {
+                warn!(
+                    "memory above {:#X} is not mapped, ignored",
+                    LINEAR_MAPPING_END
+                );
+            }
+            let end = end.min(LINEAR_MAPPING_END);
+
+            let mut cur = start;
+            for &(l, r) in reserved.iter() {
+                if r <= cur || l >= end {
+                    continue;
+                }
+                if l > cur {
+                    ArchInterface::add_memory_region(cur | VIRT_ADDR_START, l | VIRT_ADDR_START);
+                }
+                cur = cur.max(r);
+            }
+            if cur < end {
+                ArchInterface::add_memory_region(cur | VIRT_ADDR_START, end | VIRT_ADDR_START);
+            }
+        });
+}
@@ -3,8 +3,12 @@ mod consts;
 mod context;
 mod entry;
 mod interrupt;
+mod kcontext;
+mod memory;
 mod page_table;
+pub(crate) mod plic;
 mod sbi;
+mod smp;
 mod timer;
 
 use alloc::vec::Vec;
@@ -13,9 +17,13 @@ pub use consts::*;
 pub use context::*;
 pub use entry::switch_to_kernel_page_table;
 use fdt::Fdt;
-pub use interrupt::{enable_external_irq, enable_irq, init_interrupt, run_user_task};
+pub use interrupt::{
+    disable_irq, enable_external_irq, enable_irq, init_interrupt, irq_enabled, run_user_task,
+};
+pub use kcontext::{context_switch, KContext};
 pub use page_table::*;
 pub use sbi::*;
+pub use smp::{start_secondary_cpus, tlb_shootdown};
 pub use timer::*;
 
 use riscv::register::sstatus;
@@ -25,39 +33,31 @@ use crate::ArchInterface;
 #[no_mangle]
 extern "C" fn rust_main(hartid: usize, device_tree: usize) {
 	
-	crate::clear_bss();
-	// Init allocator
-	// allocator::init();
+    crate::clear_bss();
+    // 日志和内核锁都会用到 hart_id，percpu 要最先初始化
+    crate::smp::primary_init(hartid);
+    // Init allocator
+    allocator::init();
     ArchInterface::init_logging();
-
-    percpu::init(1);
-    percpu::set_local_thread_pointer(hartid);
+    page_table::detect_asid();
 
     let (hartid, device_tree) = boards::init_device(hartid, device_tree);
 	info!("device tree place is {:#x}",device_tree);
     let mut dt_buf = Vec::new();
-    // if device_tree != 0 {
-    //     let fdt = unsafe { Fdt::from_ptr(device_tree as *const u8).unwrap() };
-
-    //     dt_buf.extend_from_slice(unsafe {
-    //         core::slice::from_raw_parts(device_tree as *const u8, fdt.total_size())
-    //     });
+    if device_tree != 0 {
+        // OpenSBI 给的是物理地址，启动页表的高半核映射覆盖了它
+        let device_tree = device_tree | VIRT_ADDR_START;
+        let fdt = unsafe { Fdt::from_ptr(device_tree as *const u8).unwrap() };
 
-    //     info!("There has {} CPU(s)", fdt.cpus().count());
+        dt_buf.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(device_tree as *const u8, fdt.total_size())
+        });
 
-    //     fdt.memory().regions().for_each(|x| {
-    //         info!(
-    //             "memory region {:#X} - {:#X}",
-    //             x.starting_address as usize,
-    //             x.starting_address as usize + x.size.unwrap()
-    //         );
+        info!("There has {} CPU(s)", fdt.cpus().count());
 
-    //         ArchInterface::add_memory_region(
-    //             x.starting_address as usize | VIRT_ADDR_START,
-    //             (x.starting_address as usize + x.size.unwrap()) | VIRT_ADDR_START,
-    //         );
-    //     });
-    // }
+        memory::add_memory_regions(&fdt, device_tree);
+        smp::record_harts(&fdt);
+    }
 
     ArchInterface::prepare_drivers();
 
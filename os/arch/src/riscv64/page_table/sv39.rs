@@ -1,11 +1,14 @@
-use core::arch::{asm, riscv64::sfence_vma};
+use core::arch::asm;
+use core::arch::riscv64::{sfence_vma_all, sfence_vma_asid, sfence_vma_vaddr};
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use alloc::sync::Arc;
 use bitflags::bitflags;
 
+use crate::asid::{release_asid, switch_asid, AsidFlush};
 use crate::{
-    sigtrx::get_trx_mapping, ArchInterface, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage,
-    PAGE_ITEM_COUNT, PAGE_SIZE, VIRT_ADDR_START,
+    sigtrx::get_trx_mapping, ArchInterface, MapPageSize, MappingFlags, PhysAddr, PhysPage,
+    VirtAddr, VirtPage, PAGE_ITEM_COUNT, PAGE_SIZE, VIRT_ADDR_START,
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -122,6 +125,7 @@ bitflags! {
         const ADUVRX = Self::A.bits() | Self::D.bits() | Self::U.bits() | Self::V.bits() | Self::R.bits() | Self::X.bits();
         const ADVRWX = Self::A.bits() | Self::D.bits() | Self::VRWX.bits();
         const ADGVRWX = Self::G.bits() | Self::ADVRWX.bits();
+        const ADGVRW = Self::ADGVRWX.bits() & !Self::X.bits();
     }
 }
 
@@ -159,6 +163,28 @@ pub fn get_pte_list(paddr: PhysAddr) -> &'static mut [PTE] {
     unsafe { core::slice::from_raw_parts_mut(paddr.get_mut_ptr::<PTE>(), PAGE_ITEM_COUNT) }
 }
 
+#[inline]
+pub fn flush_tlb(vaddr: Option<VirtAddr>) {
+    unsafe {
+        match vaddr {
+            // 不指定 ASID，所有地址空间里的这一页都刷掉
+            Some(vaddr) => sfence_vma_vaddr(vaddr.0),
+            None => sfence_vma_all(),
+        }
+    }
+}
+
+/// 内核页表的根物理地址，设置之后新页表的高半部分都从这里复制
+static KERNEL_ROOT: AtomicUsize = AtomicUsize::new(0);
+
+/// 内核页表建好之前是 None
+pub(crate) fn kernel_root() -> Option<usize> {
+    match KERNEL_ROOT.load(Ordering::Acquire) {
+        0 => None,
+        root => Some(root),
+    }
+}
+
 #[derive(Debug)]
 pub struct PageTable(pub(crate) PhysAddr);
 
@@ -173,135 +199,223 @@ impl PageTable {
     #[inline]
     pub fn restore(&self) {
         let arr = get_pte_list(self.0);
-        arr[0x100] = PTE::from_addr(0x0000_0000, PTEFlags::ADGVRWX);
-        arr[0x101] = PTE::from_addr(0x4000_0000, PTEFlags::ADGVRWX);
-        arr[0x102] = PTE::from_addr(0x8000_0000, PTEFlags::ADGVRWX);
-        arr[0x104] = PTE::from_addr(get_trx_mapping(), PTEFlags::V);
-        arr[0x106] = PTE::from_addr(0x8000_0000, PTEFlags::ADGVRWX);
         arr[0..0x100].fill(PTE::from_addr(0, PTEFlags::NONE));
+        if let Some(root) = kernel_root() {
+            arr[0x100..].copy_from_slice(&get_pte_list(PhysAddr(root))[0x100..]);
+            return;
+        }
+        // 还没有内核页表，线性映射先不给执行权限，内核代码段由内核自己映射
+        arr[0x100] = PTE::from_addr(0x0000_0000, PTEFlags::ADGVRW);
+        arr[0x101] = PTE::from_addr(0x4000_0000, PTEFlags::ADGVRW);
+        arr[0x102] = PTE::from_addr(0x8000_0000, PTEFlags::ADGVRW);
+        arr[0x103] = PTE::from_addr(0xc000_0000, PTEFlags::ADGVRW);
+        arr[0x104] = PTE::from_addr(get_trx_mapping(), PTEFlags::V);
+        arr[0x106] = PTE::from_addr(0x8000_0000, PTEFlags::ADGVRW);
+    }
+
+    /// 把这个页表作为内核页表，高半部分的 1G 大页拆成下一级页表，
+    /// 之后新建的页表都引用同一组下级页表，内核映射的修改对所有地址空间可见
+    pub fn share_kernel(&self) {
+        for pte in get_pte_list(self.0)[0x100..].iter_mut() {
+            if pte.is_huge() {
+                split_huge(pte, 2);
+            }
+        }
+        flush_tlb(None);
+        KERNEL_ROOT.store(self.0 .0, Ordering::Release);
     }
 
     #[inline]
     pub const fn get_satp(&self) -> usize {
         (8 << 60) | (self.0 .0 >> 12)
     }
-	#[inline]
-	pub fn from_satp(satp: usize) -> Self {
-        let root_ppn=PhysPage::from(satp & ((1usize << 44) - 1));
-		Self(PhysAddr::from(root_ppn))
+
+    /// 页表的根物理地址，内核用它来标识地址空间
+    #[inline]
+    pub const fn token(&self) -> usize {
+        self.0 .0
+    }
+
+    /// 借用 token 对应的页表，返回值 drop 时不会回收页表
+    #[inline]
+    pub fn from_token(token: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self(PhysAddr(token)))
     }
 
     #[inline]
     pub fn change(&self) {
+        let (asid, flush) = switch_asid(self.0 .0);
         unsafe {
-            asm!("csrw satp, {0}",  in(reg) self.get_satp());
-            riscv::asm::sfence_vma_all();
+            asm!("csrw satp, {0}", in(reg) self.get_satp() | asid << 44);
+            match flush {
+                AsidFlush::None => {}
+                AsidFlush::Asid => sfence_vma_asid(asid),
+                AsidFlush::All => sfence_vma_all(),
+            }
         }
     }
 
-    #[inline]
-    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, level: usize) {
-        // TODO: Add huge page support.
-		info!("go into page table map ppn {} vpn {}",ppn,vpn);
+    /// 找到 `vpn` 在第 `level` 层的页表项，沿途缺少的页表会被分配，
+    /// 覆盖 `vpn` 的更大的页会被拆开
+    fn get_entry_mut(&self, vpn: VirtPage, level: usize) -> &mut PTE {
         let mut pte_list = get_pte_list(self.0);
-        for i in (1..(level+1)).rev() {
-            let value = (vpn.0 >> (9 * (i-1))) & 0x1ff;
-            let pte = &mut pte_list[value];
-            if i == 0 {
-                break;
-            }
+        for i in (level + 1..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * i)) & 0x1ff];
             if !pte.is_valid() {
                 *pte = PTE::from_ppn(ArchInterface::frame_alloc_persist().0, PTEFlags::V);
+            } else if pte.is_huge() {
+                split_huge(pte, i);
             }
-            // page_table = PageTable(pte.to_ppn().into());
             pte_list = get_pte_list(pte.to_ppn().into());
         }
-        pte_list[vpn.0 & 0x1ff] = PTE::from_ppn(ppn.0, flags.into());
-        unsafe {
-            sfence_vma(vpn.to_addr(), 0);
-        }
+        &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff]
     }
 
+    /// 用 `size` 大小的页把 `vpn` 映射到 `ppn`，两者都要按 `size` 对齐
     #[inline]
-    pub fn unmap(&self, vpn: VirtPage) {
-        // TODO: Add huge page support.
-        let mut pte_list = get_pte_list(self.0);
-        for i in (1..3).rev() {
-            let value = (vpn.0 >> 9 * i) & 0x1ff;
-            let pte = &mut pte_list[value];
-            if !pte.is_valid() {
-                return;
+    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, size: MapPageSize) {
+        assert!(size.aligned(vpn, ppn));
+        let level = size.level();
+        let pte = self.get_entry_mut(vpn, level);
+        if level > 0 && pte.is_leaf() {
+            // 原来是下一级页表，整个换成大页
+            free_table(pte.to_ppn().into(), level - 1);
+            *pte = PTE::from_ppn(ppn.0, flags.into());
+            crate::tlb_shootdown(None);
+        } else {
+            // 替换已有的映射时，之前运行过这个地址空间的核上可能还缓存着旧的表项
+            let replaced = pte.is_valid();
+            *pte = PTE::from_ppn(ppn.0, flags.into());
+            if replaced {
+                crate::tlb_shootdown(Some(vpn.to_addr().into()));
+            } else {
+                flush_tlb(Some(vpn.to_addr().into()));
             }
-            pte_list = get_pte_list(pte.to_ppn().into());
         }
+    }
 
-        pte_list[vpn.0 & 0x1ff] = PTE::new();
-        unsafe {
-            sfence_vma(vpn.to_addr(), 0);
+    /// 解除 `vpn` 这一个 4K 页的映射，它在大页里的话先把大页拆开
+    #[inline]
+    pub fn unmap(&self, vpn: VirtPage) {
+        if self.find_pte(vpn).is_none() {
+            return;
         }
+        *self.get_entry_mut(vpn, 0) = PTE::new();
+        // 其他核可能还缓存着这个映射
+        crate::tlb_shootdown(Some(vpn.to_addr().into()));
     }
 
     #[inline]
     pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
-        let mut paddr = self.0;
-        for i in (0..3).rev() {
-            let value = (vaddr.0 >> 12 + 9 * i) & 0x1ff;
-            let pte = &get_pte_list(paddr)[value];
-            // 如果当前页是大页 返回相关的位置
-            // vaddr.0 % (1 << (12 + 9 * i)) 是大页内偏移
+        let (pte, level) = self.find_pte(vaddr.into())?;
+        // vaddr.0 % (PAGE_SIZE << (9 * level)) 是大页内偏移
+        Some(PhysAddr(
+            pte.to_ppn().0 << 12 | vaddr.0 % (PAGE_SIZE << (9 * level)),
+        ))
+    }
+    /// 找到映射 `vpn` 的叶子页表项和它所在的层级
+    fn find_pte(&self, vpn: VirtPage) -> Option<(&mut PTE, usize)> {
+        let mut pte_list = get_pte_list(self.0);
+        for level in (1..3).rev() {
+            let pte = &mut pte_list[(vpn.0 >> (9 * level)) & 0x1ff];
             if !pte.flags().contains(PTEFlags::V) {
                 return None;
             }
             if pte.is_huge() {
-                return Some(PhysAddr(
-                    pte.to_ppn().0 << 12 | vaddr.0 % (1 << (12 + 9 * i)),
-                ));
+                return Some((pte, level));
             }
-            paddr = pte.to_ppn().into()
+            pte_list = get_pte_list(pte.to_ppn().into());
         }
-        Some(PhysAddr(paddr.0 | vaddr.0 % PAGE_SIZE))
-    }
-    fn find_pte(&self, vpn: VirtPage) -> Option<&mut PTE> {
-        let idxs = vpn.indexes();
-        let mut ppn = self.0;
-        let mut result: Option<&mut PTE> = None;
-        for (i, idx) in idxs.iter().enumerate() {
-			let pa: PhysAddr = ppn.into();
-			let pte =&mut get_pte_list(pa)[*idx];
-            // let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
+        let pte = &mut pte_list[vpn.0 & 0x1ff];
+        if pte.flags().contains(PTEFlags::V) {
+            Some((pte, 0))
+        } else {
+            None
+        }
+    }
+    /// 清除 `vpn` 的访问位，返回清除之前是否被访问过
+    pub fn test_and_clear_accessed(&self, vpn: VirtPage) -> bool {
+        match self.find_pte(vpn) {
+            Some((pte, _)) if pte.flags().contains(PTEFlags::A) => {
+                pte.0 &= !(PTEFlags::A.bits() as usize);
+                flush_tlb(Some(vpn.to_addr().into()));
+                true
             }
-            if !pte.is_valid() {
-                return None;
+            _ => false,
+        }
+    }
+    /// `vpn` 映射之后是否被写过
+    pub fn is_dirty(&self, vpn: VirtPage) -> bool {
+        self.find_pte(vpn)
+            .map_or(false, |(pte, _)| pte.flags().contains(PTEFlags::D))
+    }
+    /// `vpn` 映射的权限，没有映射时返回 None
+    pub fn mapping_flags(&self, vpn: VirtPage) -> Option<MappingFlags> {
+        let flags = self.find_pte(vpn)?.0.flags();
+        let mut res = MappingFlags::None;
+        for (pte_flag, flag) in [
+            (PTEFlags::R, MappingFlags::R),
+            (PTEFlags::W, MappingFlags::W),
+            (PTEFlags::X, MappingFlags::X),
+            (PTEFlags::U, MappingFlags::U),
+            (PTEFlags::A, MappingFlags::A),
+            (PTEFlags::D, MappingFlags::D),
+        ] {
+            if flags.contains(pte_flag) {
+                res |= flag;
             }
-            ppn = PhysAddr::from(pte.to_ppn());
         }
-        result
+        Some(res)
     }
 	pub fn translate(&self, vpn: VirtPage) -> Option<PTE> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_pte(vpn).map(|(pte, _)| *pte)
     }
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
-            let aligned_pa: PhysAddr = pte.to_ppn().into();
-            let offset = va.page_offset();
-            let aligned_pa_usize: usize = aligned_pa.into();
-            (aligned_pa_usize + offset).into()
-        })
+        self.virt_to_phys(va)
+    }
+}
+
+/// 把第 `level` 层的大页拆成下一层的 512 个页，权限不变
+fn split_huge(pte: &mut PTE, level: usize) {
+    let table = ArchInterface::frame_alloc_persist();
+    for (i, child) in get_pte_list(table.into()).iter_mut().enumerate() {
+        *child = PTE(pte.0 + (i << (10 + 9 * (level - 1))));
     }
+    *pte = PTE::from_ppn(table.0, PTEFlags::V);
+}
+
+/// 回收第 `level` 层的页表和它下面的所有页表，叶子指向的页帧由内核管理
+fn free_table(paddr: PhysAddr, level: usize) {
+    if level > 0 {
+        get_pte_list(paddr)
+            .iter()
+            .filter(|x| x.is_leaf())
+            .for_each(|x| free_table(x.to_ppn().into(), level - 1));
+    }
+    ArchInterface::frame_unalloc(paddr.into());
 }
 
 impl Drop for PageTable {
     fn drop(&mut self) {
-        for root_pte in get_pte_list(self.0)[..0x100].iter().filter(|x| x.is_leaf()) {
-            get_pte_list(root_pte.to_ppn().into())
-                .iter()
-                .filter(|x| x.is_leaf())
-                .for_each(|x| ArchInterface::frame_unalloc(x.to_ppn()));
-            ArchInterface::frame_unalloc(root_pte.to_ppn());
-        }
+        get_pte_list(self.0)[..0x100]
+            .iter()
+            .filter(|x| x.is_leaf())
+            .for_each(|x| free_table(x.to_ppn().into(), 1));
+        release_asid(self.0 .0);
         ArchInterface::frame_unalloc(self.0.into());
     }
 }
+
+/// 往 satp 的 ASID 字段写全 1 再读回来，没有实现的位读出来是 0
+pub(crate) fn detect_asid() {
+    let satp: usize;
+    let written: usize;
+    unsafe {
+        asm!("csrr {}, satp", out(reg) satp);
+        asm!("csrw satp, {}", in(reg) satp | 0xffff << 44);
+        asm!("csrr {}, satp", out(reg) written);
+        asm!("csrw satp, {}", in(reg) satp);
+        sfence_vma_all();
+    }
+    crate::asid::init_asid(1 << ((written >> 44) & 0xffff).count_ones());
+}
@@ -1,3 +1,8 @@
+use riscv::register::sie;
+
+use super::boards::PLIC_BASE;
+use crate::VIRT_ADDR_START;
+
 #[allow(clippy::upper_case_acronyms)]
 pub struct PLIC {
     base_addr: usize,
@@ -122,3 +127,41 @@ impl PLIC {
         }
     }
 }
+
+/// 目前只有 hart 0 接收外部中断
+const BOOT_HART: usize = 0;
+
+fn plic() -> PLIC {
+    unsafe { PLIC::new(PLIC_BASE | VIRT_ADDR_START) }
+}
+
+pub(crate) fn init() {
+    let mut plic = plic();
+    plic.set_threshold(BOOT_HART, IntrTargetPriority::Supervisor, 0);
+    plic.set_threshold(BOOT_HART, IntrTargetPriority::Machine, 1);
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+pub(crate) fn set_enable(irq: usize, enabled: bool) {
+    let mut plic = plic();
+    if enabled {
+        plic.set_priority(irq, 1);
+        plic.enable(BOOT_HART, IntrTargetPriority::Supervisor, irq);
+    } else {
+        plic.disable(BOOT_HART, IntrTargetPriority::Supervisor, irq);
+    }
+}
+
+/// 从 PLIC 认领一个外部中断，分发之后再通知 PLIC 处理完成
+pub(crate) fn handle_irq() {
+    let mut plic = plic();
+    let irq = plic.claim(BOOT_HART, IntrTargetPriority::Supervisor);
+    // 0 表示没有待处理的中断
+    if irq == 0 {
+        return;
+    }
+    crate::irq::dispatch(irq as usize);
+    plic.complete(BOOT_HART, IntrTargetPriority::Supervisor, irq);
+}
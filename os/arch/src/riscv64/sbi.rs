@@ -14,6 +14,10 @@ const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
 const SBI_SHUTDOWN: usize = 8;
 
+// SBI v0.2 的 HSM 扩展，用来启动其他 hart
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_EXT_HSM_HART_START: usize = 0;
+
 // SBI 调用
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     let mut ret;
@@ -27,6 +31,20 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+// 带扩展号和功能号的 SBI 调用，返回 error
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> isize {
+    let mut error;
+    unsafe {
+        asm!("ecall",
+        in("a7") eid,
+        in("a6") fid,
+        inlateout("a0") arg0 => error,
+        inlateout("a1") arg1 => _,
+        in("a2") arg2);
+    }
+    error
+}
+
 /// 设置定时器
 #[inline]
 pub fn set_timer(time: usize) {
@@ -50,6 +68,23 @@ pub fn console_getchar() -> Option<u8> {
     }
 }
 
+/// 让 `hartid` 从物理地址 `start_addr` 开始执行，`opaque` 会放在它的 a1 里
+#[inline]
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> bool {
+    sbi_call_ext(SBI_EXT_HSM, SBI_EXT_HSM_HART_START, hartid, start_addr, opaque) == 0
+}
+
+/// 让 `hart_mask` 里的 hart 刷新 `[start, start + size)` 的 TLB
+#[inline]
+pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) {
+    sbi_call(
+        SBI_REMOTE_SFENCE_VMA,
+        &hart_mask as *const usize as usize,
+        start,
+        size,
+    );
+}
+
 /// 调用 SBI_SHUTDOWN 来关闭操作系统（直接退出 QEMU）
 #[inline]
 pub fn shutdown() -> ! {
@@ -0,0 +1,91 @@
+use alloc::vec::Vec;
+use fdt::Fdt;
+use riscv::register::sstatus;
+
+use super::entry::PAGE_TABLE;
+use super::sbi::{hart_start, remote_sfence_vma};
+use crate::smp::{alloc_secondary_stack, other_cpus_mask, secondary_init, wait_online};
+use crate::{flush_tlb, hart_id, shutdown, ArchInterface, VirtAddr, PAGE_SIZE, VIRT_ADDR_START};
+
+/// 设备树里的 hartid
+static mut HART_IDS: Vec<usize> = Vec::new();
+
+pub(crate) fn record_harts(fdt: &Fdt) {
+    unsafe {
+        HART_IDS = fdt.cpus().map(|cpu| cpu.ids().first()).collect();
+    }
+}
+
+/// 从核入口，a0 是 hartid，a1 是 hart_start 传进来的栈顶（高半核地址）
+#[naked]
+unsafe extern "C" fn _secondary_start() -> ! {
+    core::arch::asm!(
+        "
+            mv      sp, a1
+
+            la      t0, {page_table}
+            srli    t0, t0, 12
+            li      t1, 8 << 60
+            or      t0, t0, t1
+            csrw    satp, t0
+            sfence.vma
+
+            li      s0, {virt_addr_start}
+            la      a2, {entry}
+            or      a2, a2, s0
+            jalr    a2
+        ",
+        page_table = sym PAGE_TABLE,
+        virt_addr_start = const VIRT_ADDR_START,
+        entry = sym rust_secondary_main,
+        options(noreturn),
+    )
+}
+
+extern "C" fn rust_secondary_main(hartid: usize) {
+    secondary_init(hartid);
+    // SUM 之类的状态每个 hart 都要单独设置
+    super::boards::init_device(hartid, 0);
+    unsafe {
+        sstatus::set_fs(sstatus::FS::Dirty);
+    }
+    info!("hart {} started", hartid);
+
+    ArchInterface::main(hartid);
+    shutdown();
+}
+
+/// 最多启动 `cpu_num` 个从核，返回实际启动的个数
+pub fn start_secondary_cpus(cpu_num: usize) -> usize {
+    let boot_hart = hart_id();
+    let entry = _secondary_start as usize & !VIRT_ADDR_START;
+    let mut started = 0;
+    for &hartid in unsafe { HART_IDS.iter() } {
+        if started == cpu_num {
+            break;
+        }
+        if hartid == boot_hart || hartid >= crate::MAX_CPU_NUM {
+            continue;
+        }
+        if !hart_start(hartid, entry, alloc_secondary_stack()) {
+            warn!("failed to start hart {}", hartid);
+            continue;
+        }
+        wait_online(hartid);
+        started += 1;
+    }
+    started
+}
+
+/// 刷新本核的 TLB，并通过 SBI 让其他上线的核一起刷新
+pub fn tlb_shootdown(vaddr: Option<VirtAddr>) {
+    flush_tlb(vaddr);
+    let mask = other_cpus_mask();
+    if mask == 0 {
+        return;
+    }
+    match vaddr {
+        Some(vaddr) => remote_sfence_vma(mask, vaddr.0, PAGE_SIZE),
+        None => remote_sfence_vma(mask, 0, usize::MAX),
+    }
+}
@@ -0,0 +1,115 @@
+//! 多核启动的公共部分
+//!
+//! 各架构负责把从核带到高半核地址，然后调用 [secondary_init] 进入 `ArchInterface::main`
+
+use alloc::vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::STACK_SIZE;
+
+/// 支持的最多核数，由编译时的环境变量 SMP 决定，没有设置时是 4
+///
+/// 内核的 build.rs 按同一个变量给链接脚本里的 percpu 段预留空间
+pub const MAX_CPU_NUM: usize = parse_cpu_num(option_env!("SMP"));
+
+const fn parse_cpu_num(smp: Option<&str>) -> usize {
+    let bytes = match smp {
+        Some(smp) => smp.as_bytes(),
+        None => return 4,
+    };
+    let mut num = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "SMP must be a number");
+        num = num * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(num > 0 && num <= usize::BITS as usize, "SMP out of range");
+    num
+}
+
+#[percpu::def_percpu]
+static CPU_ID: usize = 0;
+
+/// 已经上线的核，每一位对应一个 cpu id
+static CPU_ONLINE_MASK: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前核的编号，可以作为 per-CPU 数组的下标
+#[inline]
+pub fn hart_id() -> usize {
+    CPU_ID.read_current()
+}
+
+/// 已经上线的核数
+pub fn cpu_online_count() -> usize {
+    cpu_online_mask().count_ones() as usize
+}
+
+/// 已经上线的核，第 i 位表示 i 号核
+pub fn cpu_online_mask() -> usize {
+    CPU_ONLINE_MASK.load(Ordering::Acquire)
+}
+
+/// 除当前核以外已经上线的核
+pub(crate) fn other_cpus_mask() -> usize {
+    CPU_ONLINE_MASK.load(Ordering::Acquire) & !(1 << hart_id())
+}
+
+/// 主核调用，把 percpu 数据复制给每个核，需要在任何 percpu 访问之前完成
+pub(crate) fn primary_init(cpu_id: usize) {
+    percpu::init(MAX_CPU_NUM);
+    secondary_init(cpu_id);
+}
+
+/// 设置当前核的 percpu 指针并标记为上线
+pub(crate) fn secondary_init(cpu_id: usize) {
+    assert!(cpu_id < MAX_CPU_NUM, "cpu id {} out of range", cpu_id);
+    percpu::set_local_thread_pointer(cpu_id);
+    CPU_ID.write_current(cpu_id);
+    CPU_ONLINE_MASK.fetch_or(1 << cpu_id, Ordering::Release);
+}
+
+/// 等待 `cpu_id` 上线，从核初始化完 percpu 才会继续往下启动
+pub(crate) fn wait_online(cpu_id: usize) {
+    while CPU_ONLINE_MASK.load(Ordering::Acquire) & (1 << cpu_id) == 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// 给从核分配的启动栈，从核永远不会退出，所以直接泄漏掉
+pub(crate) fn alloc_secondary_stack() -> usize {
+    let stack = vec![0u8; STACK_SIZE].leak();
+    stack.as_ptr() as usize + STACK_SIZE
+}
+
+/// 应急栈的大小，要够 panic 打印寄存器和回溯
+#[cfg(not(target_arch = "loongarch64"))]
+const TRAP_STACK_SIZE: usize = 0x4000;
+
+#[cfg(not(target_arch = "loongarch64"))]
+#[repr(C, align(16))]
+struct TrapStack([u8; TRAP_STACK_SIZE]);
+
+/// 内核栈溢出之后处理异常用的栈，每个核一个
+///
+/// 溢出时原来的栈已经压到保护页上，异常入口换到这里才能走到报错的地方
+#[cfg(not(target_arch = "loongarch64"))]
+static mut TRAP_STACKS: [TrapStack; MAX_CPU_NUM] = {
+    const EMPTY: TrapStack = TrapStack([0; TRAP_STACK_SIZE]);
+    [EMPTY; MAX_CPU_NUM]
+};
+
+/// 当前核应急栈的栈顶
+#[cfg(not(target_arch = "loongarch64"))]
+pub(crate) fn trap_stack_top() -> usize {
+    unsafe { core::ptr::addr_of!(TRAP_STACKS[hart_id()]) as usize + TRAP_STACK_SIZE }
+}
+
+/// 自旋等待时调用，处理需要当前核马上响应的 IPI
+///
+/// x86_64 的 TLB shootdown 要等所有核回应，关着中断等锁的核也要能回应
+#[inline]
+pub fn handle_pending_ipi() {
+    #[cfg(target_arch = "x86_64")]
+    crate::handle_shootdown();
+}
@@ -10,9 +10,13 @@ use self::vectors::*;
 use crate::VIRT_ADDR_START;
 
 pub(super) mod vectors {
+    /// IO APIC 的第 n 个引脚映射到 `IRQ_VECTOR_START + n` 号向量
+    pub const IRQ_VECTOR_START: u8 = 0x20;
+    pub const IRQ_VECTOR_END: u8 = APIC_TIMER_VECTOR - 1;
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -27,38 +31,20 @@ static mut LOCAL_APIC: Option<LocalApic> = None;
 static mut IS_X2APIC: bool = false;
 static IO_APIC: Once<MutexIrqSafe<IoApic>> = Once::new();
 
-/// Enables or disables the given IRQ.
-pub fn set_enable(vector: usize, enabled: bool) {
+/// Enables or disables the given IRQ (IO APIC pin).
+pub fn set_enable(irq: usize, enabled: bool) {
     // should not affect LAPIC interrupts
-    if vector < APIC_TIMER_VECTOR as _ {
+    if irq + (IRQ_VECTOR_START as usize) < APIC_TIMER_VECTOR as usize {
         unsafe {
             if enabled {
-                IO_APIC.get_unchecked().lock().enable_irq(vector as u8);
+                IO_APIC.get_unchecked().lock().enable_irq(irq as u8);
             } else {
-                IO_APIC.get_unchecked().lock().disable_irq(vector as u8);
+                IO_APIC.get_unchecked().lock().disable_irq(irq as u8);
             }
         }
     }
 }
 
-/// Registers an IRQ handler for the given IRQ.
-///
-/// It also enables the IRQ if the registration succeeds. It returns `false` if
-/// the registration failed.
-// pub fn register_handler(vector: usize, handler: crate::irq::IrqHandler) -> bool {
-//     crate::irq::register_handler_common(vector, handler)
-// }
-
-/// Dispatches the IRQ.
-///
-/// This function is called by the common interrupt handler. It looks
-/// up in the IRQ handler table and calls the corresponding handler. If
-/// necessary, it also acknowledges the interrupt controller after handling.
-// pub fn dispatch_irq(vector: usize) {
-//     crate::irq::dispatch_irq_common(vector);
-//     unsafe { local_apic().end_of_interrupt() };
-// }
-
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as LAPIC is per-cpu.
     unsafe { LOCAL_APIC.as_mut().unwrap() }
@@ -109,6 +95,13 @@ pub(super) fn init() {
     }
 
     info!("Initialize IO APIC...");
-    let io_apic = unsafe { IoApic::new(IO_APIC_BASE) };
+    let mut io_apic = unsafe { IoApic::new(IO_APIC_BASE | VIRT_ADDR_START as u64) };
+    // 所有引脚先屏蔽，注册处理函数时再打开
+    unsafe { io_apic.init(IRQ_VECTOR_START) };
     IO_APIC.call_once(|| MutexIrqSafe::new(io_apic));
 }
+
+/// AP 上的 LAPIC 寄存器和 BSP 是同一个地址，打开的是 AP 自己的 LAPIC
+pub(super) fn init_secondary() {
+    unsafe { local_apic().enable() };
+}
@@ -5,7 +5,7 @@ use core::{
 
 use x86_64::registers::rflags::RFlags;
 
-use crate::ContextArgs;
+use crate::{ContextArgs, ContextOps};
 
 use super::gdt::GdtStruct;
 
@@ -111,95 +111,99 @@ impl Context {
     }
 }
 
-impl Context {
+impl ContextOps for Context {
+    #[inline]
+    fn set_sp(&mut self, sp: usize) {
+        self.rsp = sp;
+    }
+
+    #[inline]
+    fn sp(&self) -> usize {
+        self.rsp
+    }
+
+    #[inline]
+    fn set_ra(&mut self, ra: usize) {
+        // x86_64 没有 ra 寄存器，call 把返回地址压在栈顶，这里写 [rsp] 处的返回地址槽
+        *self.ra_slot() = ra;
+    }
+
+    #[inline]
+    fn ra(&self) -> usize {
+        unsafe { (self.rsp as *const usize).read_volatile() }
+    }
+
+    #[inline]
+    fn set_pc(&mut self, pc: usize) {
+        self.rip = pc;
+    }
+
+    #[inline]
+    fn pc(&self) -> usize {
+        self.rip
+    }
+
+    #[inline]
+    fn set_tls(&mut self, tls: usize) {
+        self.fs_base = tls;
+    }
+
+    #[inline]
+    fn tls(&self) -> usize {
+        self.fs_base
+    }
+
+    #[inline]
+    fn syscall_number(&self) -> usize {
+        self.rax
+    }
+
     #[inline]
-    pub fn args(&self) -> [usize; 6] {
+    fn args(&self) -> [usize; 6] {
         [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
     }
 
     #[inline]
-    pub fn syscall_ok(&mut self) {
-        // self.sepc += 4;
+    fn set_arg0(&mut self, arg: usize) {
+        self.rdi = arg;
     }
-}
 
-// impl ContextOps for Context {
-//     #[inline]
-//     fn set_sp(&mut self, sp: usize) {
-//         self.rsp = sp;
-//     }
-
-//     #[inline]
-//     fn sp(&self) -> usize {
-//         self.rsp
-//     }
-//     #[inline]
-//     fn set_ra(&mut self, ra: usize) {
-//         warn!("set_ra in x86_64 is push return address to rsp, shoule be execute at end");
-//         self.rsp -= 8;
-//         unsafe {
-//             *(self.rsp as *mut usize) = ra;
-//         }
-//         // unimplemented!("set ra in x86_64 is not implemented")
-//     }
-
-//     #[inline]
-//     fn ra(&self) -> usize {
-//         unimplemented!("get ra in x86_64 is not implemented")
-//     }
-
-//     #[inline]
-//     fn set_sepc(&mut self, sepc: usize) {
-//         self.rip = sepc;
-//     }
-
-//     #[inline]
-//     fn sepc(&self) -> usize {
-//         self.rip
-//     }
-
-//     #[inline]
-//     fn syscall_number(&self) -> usize {
-//         self.rax
-//     }
-
-//     #[inline]
-//     fn args(&self) -> [usize; 6] {
-//         [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
-//     }
-
-//     #[inline]
-//     fn syscall_ok(&mut self) {
-//         // self.sepc += 4;
-//     }
-
-//     fn set_ret(&mut self, ret: usize) {
-//         self.rax = ret;
-//     }
-
-//     fn set_arg0(&mut self, ret: usize) {
-//         self.rdi = ret;
-//     }
-
-//     fn set_arg1(&mut self, ret: usize) {
-//         self.rsi = ret;
-//     }
-
-//     fn set_arg2(&mut self, ret: usize) {
-//         self.rdx = ret;
-//     }
-
-//     #[inline]
-//     fn set_tls(&mut self, tls: usize) {
-//         self.fs_base = tls;
-//     }
-// }
+    #[inline]
+    fn set_arg1(&mut self, arg: usize) {
+        self.rsi = arg;
+    }
+
+    #[inline]
+    fn set_arg2(&mut self, arg: usize) {
+        self.rdx = arg;
+    }
+
+    #[inline]
+    fn set_ret(&mut self, ret: usize) {
+        self.rax = ret;
+    }
+
+    #[inline]
+    fn ret(&self) -> usize {
+        self.rax
+    }
+
+    // syscall 指令陷入时 rip 已经指向下一条指令
+    #[inline]
+    fn syscall_ok(&mut self) {}
+}
 
 impl Context {
     #[inline]
     pub fn is_user(&self) -> bool {
         self.cs == GdtStruct::UCODE64_SELECTOR.0 as _
     }
+
+    /// 栈顶的返回地址槽，要求当前是这个上下文的地址空间
+    #[inline]
+    fn ra_slot(&self) -> &'static mut usize {
+        unsafe { &mut *(self.rsp as *mut usize) }
+    }
 }
 
 impl Index<ContextArgs> for Context {
@@ -208,7 +212,7 @@ impl Index<ContextArgs> for Context {
     fn index(&self, index: ContextArgs) -> &Self::Output {
         match index {
             ContextArgs::SEPC => &self.rip,
-            ContextArgs::RA => unimplemented!("Can't get return address in x86_64"),
+            ContextArgs::RA => self.ra_slot(),
             ContextArgs::ARG0 => &self.rdi,
             ContextArgs::ARG1 => &self.rsi,
             ContextArgs::ARG2 => &self.rdx,
@@ -224,12 +228,7 @@ impl IndexMut<ContextArgs> for Context {
     fn index_mut(&mut self, index: ContextArgs) -> &mut Self::Output {
         match index {
             ContextArgs::SEPC => &mut self.rip,
-            ContextArgs::RA => {
-                // set return address, at x86_64 is push return address to rsp, shoule be execute at end.
-                warn!("set_ra in x86_64 is push return address to rsp, shoule be execute at end");
-                self.rsp -= 8;
-                unsafe { (self.rsp as *mut usize).as_mut().unwrap() }
-            }
+            ContextArgs::RA => self.ra_slot(),
             ContextArgs::ARG0 => &mut self.rdi,
             ContextArgs::ARG1 => &mut self.rsi,
             ContextArgs::ARG2 => &mut self.rdx,
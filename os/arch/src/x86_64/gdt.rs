@@ -13,6 +13,9 @@ pub(super) static GDT: Once<GdtStruct> = Once::new();
 #[percpu::def_percpu]
 pub(super) static TSS: Once<TaskStateSegment> = Once::new();
 
+/// #DF 用的 IST 下标，内核栈溢出时 #PF 压不进异常帧，会变成 #DF
+pub(super) const DOUBLE_FAULT_IST_INDEX: usize = 0;
+
 /// A wrapper of the Global Descriptor Table (GDT) with maximum 16 entries.
 #[repr(align(16))]
 pub struct GdtStruct {
@@ -99,7 +102,12 @@ pub fn init() {
     unsafe {
         let tss = TSS.current_ref_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.call_once(|| TaskStateSegment::new());
+        tss.call_once(|| {
+            let mut tss = TaskStateSegment::new();
+            tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX] =
+                VirtAddr::new(crate::smp::trap_stack_top() as _);
+            tss
+        });
         gdt.call_once(|| GdtStruct::new(tss.get_unchecked()));
         let gdt = gdt.get_unchecked();
         gdt.load();
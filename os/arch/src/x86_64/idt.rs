@@ -1,6 +1,9 @@
 use spin::Once;
+use x86::irq::DOUBLE_FAULT_VECTOR;
 use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable};
 
+use super::gdt::DOUBLE_FAULT_IST_INDEX;
+
 const NUM_INT: usize = 256;
 
 pub(super) static IDT: Once<IdtStruct> = Once::new();
@@ -27,7 +30,11 @@ impl IdtStruct {
             )
         };
         for i in 0..NUM_INT {
-            entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            let opts = entries[i].set_handler_fn(unsafe { core::mem::transmute(ENTRIES[i]) });
+            if i == DOUBLE_FAULT_VECTOR as usize {
+                // #DF 换到 IST 里的应急栈上，原来的栈可能已经溢出了
+                unsafe { opts.set_stack_index(DOUBLE_FAULT_IST_INDEX as u16) };
+            }
         }
         idt
     }
@@ -43,11 +50,10 @@ impl IdtStruct {
     }
 }
 
+/// 所有核共用一个 IDT，每个核都要加载一次
 pub fn init() {
     info!("Initializing IDT...");
-    let idt = IdtStruct::new();
-    IDT.call_once(|| idt);
     unsafe {
-        IDT.get().unwrap().load();
+        IDT.call_once(IdtStruct::new).load();
     }
 }
@@ -12,7 +12,9 @@ use crate::x86_64::gdt::set_tss_kernel_sp;
 use crate::{x86_64::gdt::GdtStruct, Context, TrapType};
 use crate::{ArchInterface, CONTEXT_SIZE, SYSCALL_VECTOR};
 
-use super::apic::vectors::APIC_TIMER_VECTOR;
+use super::apic::vectors::{
+    APIC_TIMER_VECTOR, IRQ_VECTOR_END, IRQ_VECTOR_START, TLB_SHOOTDOWN_VECTOR,
+};
 use super::context::FxsaveArea;
 use super::time::ticks_to_nanos;
 
@@ -73,6 +75,10 @@ bitflags! {
 // 内核中断回调
 #[no_mangle]
 fn kernel_callback(context: &mut Context) {
+    handle_trap(context);
+}
+
+fn handle_trap(context: &mut Context) -> TrapType {
     let trap_type = match context.vector as u8 {
         PAGE_FAULT_VECTOR => {
             let pflags = PageFaultFlags::from_bits_truncate(context.rflags as _);
@@ -85,6 +91,16 @@ fn kernel_callback(context: &mut Context) {
                 TrapType::LoadPageFault(unsafe { cr2() })
             }
         }
+        DOUBLE_FAULT_VECTOR => {
+            // 内核栈溢出时 #PF 压不进异常帧才会变成 #DF，这时已经在 IST 的应急栈上了，
+            // cr2 还是溢出的地址，当作缺页交给内核报告。#DF 没法恢复
+            let addr = unsafe { cr2() };
+            ArchInterface::kernel_interrupt(context, TrapType::StorePageFault(addr));
+            panic!(
+                "#DF @ {:#x}, fault_vaddr={:#x}:\n{:#x?}",
+                context.rip, addr, context
+            );
+        }
         BREAKPOINT_VECTOR => {
             debug!("#BP @ {:#x} ", context.rip);
             TrapType::Breakpoint
@@ -98,8 +114,18 @@ fn kernel_callback(context: &mut Context) {
                 context
             );
         }
-        APIC_TIMER_VECTOR => TrapType::Time,
-        // IRQ_VECTOR_START..=IRQ_VECTOR_END => crate::trap::handle_irq_extern(tf.vector as _),
+        APIC_TIMER_VECTOR => {
+            crate::add_timer_tick();
+            TrapType::Time
+        }
+        TLB_SHOOTDOWN_VECTOR => {
+            super::smp::handle_shootdown();
+            TrapType::SupervisorExternal
+        }
+        IRQ_VECTOR_START..=IRQ_VECTOR_END => {
+            crate::irq::dispatch((context.vector as u8 - IRQ_VECTOR_START) as usize);
+            TrapType::SupervisorExternal
+        }
         _ => {
             panic!(
                 "Unhandled exception {} (error_code = {:#x}) @ {:#x}:\n{:#x?}",
@@ -109,6 +135,7 @@ fn kernel_callback(context: &mut Context) {
     };
     ArchInterface::kernel_interrupt(context, trap_type);
     unsafe { super::apic::local_apic().end_of_interrupt() };
+    trap_type
 }
 
 #[naked]
@@ -376,8 +403,8 @@ unsafe extern "C" fn syscall_entry() {
     )
 }
 
-/// Return Some(()) if it was interrupt by syscall, otherwise None.
-pub fn run_user_task(context: &mut Context) -> Option<()> {
+/// 进入用户态运行，返回导致回到内核的陷入类型
+pub fn run_user_task(context: &mut Context) -> TrapType {
     // TODO: set tss kernel sp just once, before task run.
     let cx_general_top = context as *mut Context as usize + CONTEXT_SIZE - size_of::<FxsaveArea>();
     set_tss_kernel_sp(cx_general_top);
@@ -387,11 +414,8 @@ pub fn run_user_task(context: &mut Context) -> Option<()> {
     context.fx_area.save();
 
     match context.vector {
-        SYSCALL_VECTOR => Some(()),
-        _ => {
-            kernel_callback(context);
-            None
-        }
+        SYSCALL_VECTOR => TrapType::UserEnvCall,
+        _ => handle_trap(context),
     }
 }
 
@@ -401,10 +425,14 @@ pub fn enable_irq() {
     unsafe { asm!("sti") }
 }
 
-pub fn close_irq() {
+pub fn disable_irq() {
     unsafe { asm!("cli") }
 }
 
+pub fn irq_enabled() -> bool {
+    x86_64::instructions::interrupts::are_enabled()
+}
+
 #[inline(always)]
 pub fn enable_external_irq() {
     // unsafe {
@@ -0,0 +1,64 @@
+use core::arch::asm;
+
+/// 内核线程切换时保存的上下文，只包含 callee-saved 寄存器
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub struct KContext {
+    rsp: usize,
+    rbx: usize,
+    rbp: usize,
+    r12: usize,
+    r13: usize,
+    r14: usize,
+    r15: usize,
+    rip: usize,
+}
+
+impl KContext {
+    pub fn blank() -> Self {
+        Self::default()
+    }
+
+    /// 切换到该上下文时从 `entry` 开始执行，使用 `ksp` 作为内核栈
+    pub fn new(entry: usize, ksp: usize) -> Self {
+        Self {
+            // 模拟 call 压入的返回地址，保证进入 entry 时栈是 16 字节对齐的
+            rsp: ksp - 8,
+            rip: entry,
+            ..Default::default()
+        }
+    }
+}
+
+/// 保存当前的内核上下文到 `from`，并切换到 `to`
+#[naked]
+pub unsafe extern "C" fn context_switch(from: *mut KContext, to: *const KContext) {
+    asm!(
+        // 保存当前上下文，返回地址已经在栈顶
+        "
+            mov     [rdi + 0 * 8], rsp
+            mov     [rdi + 1 * 8], rbx
+            mov     [rdi + 2 * 8], rbp
+            mov     [rdi + 3 * 8], r12
+            mov     [rdi + 4 * 8], r13
+            mov     [rdi + 5 * 8], r14
+            mov     [rdi + 6 * 8], r15
+            lea     rax, [rip + 2f]
+            mov     [rdi + 7 * 8], rax
+        ",
+        // 恢复下一个上下文
+        "
+            mov     rsp, [rsi + 0 * 8]
+            mov     rbx, [rsi + 1 * 8]
+            mov     rbp, [rsi + 2 * 8]
+            mov     r12, [rsi + 3 * 8]
+            mov     r13, [rsi + 4 * 8]
+            mov     r14, [rsi + 5 * 8]
+            mov     r15, [rsi + 6 * 8]
+            jmp     qword ptr [rsi + 7 * 8]
+        2:
+            ret
+        ",
+        options(noreturn)
+    )
+}
@@ -1,22 +1,27 @@
-mod apic;
+pub(crate) mod apic;
 mod consts;
 mod context;
 mod gdt;
 mod idt;
 mod interrupt;
+mod kcontext;
 mod multiboot;
 mod page_table;
 mod sigtrx;
+mod smp;
 mod time;
 mod uart;
 
 use ::multiboot::information::MemoryType;
 pub use consts::*;
 pub use context::Context;
+pub use kcontext::{context_switch, KContext};
 pub use interrupt::*;
 pub use multiboot::switch_to_kernel_page_table;
 pub use page_table::*;
 use raw_cpuid::CpuId;
+pub(crate) use smp::handle_shootdown;
+pub use smp::{start_secondary_cpus, tlb_shootdown};
 pub use uart::*;
 
 use x86::tlb;
@@ -30,9 +35,6 @@ use x86_64::{
 
 use crate::{x86_64::multiboot::use_multiboot, ArchInterface, VirtAddr};
 
-#[percpu::def_percpu]
-static CPU_ID: usize = 1;
-
 pub fn shutdown() -> ! {
     unsafe { PortWriteOnly::new(0x604).write(0x2000u16) };
 
@@ -41,44 +43,21 @@ pub fn shutdown() -> ! {
 
 fn rust_tmp_main(magic: usize, mboot_ptr: usize) {
     crate::clear_bss();
+    crate::smp::primary_init(0);
     idt::init();
     apic::init();
     sigtrx::init();
-    ArchInterface::init_logging();
     // Init allocator
     allocator::init();
-    percpu::init(1);
-    percpu::set_local_thread_pointer(0);
+    ArchInterface::init_logging();
     gdt::init();
     interrupt::init_syscall();
     time::init_early();
 
-    // enable avx extend instruction set and sse if support avx
-    // TIPS: QEMU not support avx, so we can't enable avx here
-    // IF you want to use avx in the qemu, you can use -cpu IvyBridge-v2 to
-    // select a cpu with avx support
-    CpuId::new().get_feature_info().map(|features| {
-        info!("is there a avx feature: {}", features.has_avx());
-        info!("is there a xsave feature: {}", features.has_xsave());
-        info!("cr4 has OSXSAVE feature: {:?}", Cr4::read());
-        if features.has_avx() && features.has_xsave() && Cr4::read().contains(Cr4Flags::OSXSAVE) {
-            unsafe {
-                XCr0::write(XCr0::read() | XCr0Flags::AVX | XCr0Flags::SSE | XCr0Flags::X87);
-            }
-        }
-    });
-
-    info!(
-        "TEST CPU ID: {}  ptr: {:#x}",
-        CPU_ID.read_current(),
-        unsafe { CPU_ID.current_ptr() } as usize
-    );
-    CPU_ID.write_current(345);
-    info!(
-        "TEST CPU ID: {}  ptr: {:#x}",
-        CPU_ID.read_current(),
-        unsafe { CPU_ID.current_ptr() } as usize
-    );
+    // 支持 PCID 的话切换地址空间时不用刷新整个 TLB
+    if init_cpu_features() {
+        crate::asid::init_asid(4096);
+    }
 
     info!("magic: {:#x}, mboot_ptr: {:#x}", magic, mboot_ptr);
 
@@ -94,9 +73,11 @@ fn rust_tmp_main(magic: usize, mboot_ptr: usize) {
                 .memory_regions()
                 .unwrap()
                 .filter(|x| x.memory_type() == MemoryType::Available)
+                // 低 1M 内存留给 BIOS 和 AP 启动代码
+                .filter(|x| x.base_address() >= 0x10_0000)
                 .for_each(|x| {
                     let start = x.base_address() as usize | VIRT_ADDR_START;
-                    let end = x.length() as usize | VIRT_ADDR_START;
+                    let end = (x.base_address() + x.length()) as usize | VIRT_ADDR_START;
                     crate::ArchInterface::add_memory_region(start, end);
                 });
         }
@@ -114,6 +95,37 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     if let Some(vaddr) = vaddr {
         unsafe { tlb::flush(vaddr.into()) }
     } else {
-        unsafe { tlb::flush_all() }
+        // 开启 PCID 之后重新加载 cr3 只刷新当前的 PCID，翻转 PGE 才能刷掉所有表项
+        let cr4 = Cr4::read_raw();
+        unsafe {
+            Cr4::write_raw(cr4 ^ Cr4Flags::PAGE_GLOBAL.bits());
+            Cr4::write_raw(cr4);
+        }
+    }
+}
+
+/// 每个核都要设置的 CPU 特性，返回是否打开了 PCID
+fn init_cpu_features() -> bool {
+    // enable avx extend instruction set and sse if support avx
+    // TIPS: QEMU not support avx, so we can't enable avx here
+    // IF you want to use avx in the qemu, you can use -cpu IvyBridge-v2 to
+    // select a cpu with avx support
+    CpuId::new().get_feature_info().map(|features| {
+        info!("is there a avx feature: {}", features.has_avx());
+        info!("is there a xsave feature: {}", features.has_xsave());
+        info!("cr4 has OSXSAVE feature: {:?}", Cr4::read());
+        if features.has_avx() && features.has_xsave() && Cr4::read().contains(Cr4Flags::OSXSAVE) {
+            unsafe {
+                XCr0::write(XCr0::read() | XCr0Flags::AVX | XCr0Flags::SSE | XCr0Flags::X87);
+            }
+        }
+    });
+
+    let has_pcid = CpuId::new()
+        .get_feature_info()
+        .map_or(false, |features| features.has_pcid());
+    if has_pcid {
+        unsafe { Cr4::update(|cr4| cr4.insert(Cr4Flags::PCID)) };
     }
+    has_pcid
 }
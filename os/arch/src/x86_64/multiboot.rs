@@ -64,6 +64,7 @@ global_asm!(
     mb_hdr_magic = const MULTIBOOT_HEADER_MAGIC,
     mb_hdr_flags = const MULTIBOOT_HEADER_FLAGS,
     entry = sym rust_tmp_main,
+    entry_secondary = sym super::smp::rust_secondary_main,
 
     offset = const VIRT_ADDR_START,
     boot_stack_size = const STACK_SIZE,
@@ -1,12 +1,13 @@
-use alloc::sync::Arc;
+use core::mem::ManuallyDrop;
+
 use x86::bits64::paging::{
-    pd_index, pdpt_index, pml4_index, pt_index, PDEntry, PDFlags, PDPTEntry, PDPTFlags, PML4Entry,
-    PML4Flags, PTEntry, PTFlags, PAGE_SIZE_ENTRIES,
+    PDEntry, PDFlags, PDPTEntry, PML4Entry, PTEntry, PTFlags, PAGE_SIZE_ENTRIES,
 };
 
+use crate::asid::{mark_stale, release_asid, switch_asid, AsidFlush};
 use crate::{
-    flush_tlb, ArchInterface, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage, PAGE_SIZE,
-    VIRT_ADDR_START,
+    flush_tlb, ArchInterface, MapPageSize, MappingFlags, PhysAddr, PhysPage, VirtAddr, VirtPage,
+    PAGE_SIZE, VIRT_ADDR_START,
 };
 
 impl From<MappingFlags> for PTFlags {
@@ -24,8 +25,9 @@ impl From<MappingFlags> for PTFlags {
         if flags.contains(MappingFlags::D) {
             res |= Self::D;
         }
-        if flags.contains(MappingFlags::X) {
-            res.remove(Self::XD);
+        // EFER.NXE 在启动时已经打开
+        if !flags.contains(MappingFlags::X) {
+            res |= Self::XD;
         }
         res
     }
@@ -35,11 +37,23 @@ impl From<MappingFlags> for PTFlags {
 pub struct PageTable(pub(crate) PhysAddr);
 
 impl PageTable {
-    pub fn alloc() -> Arc<Self> {
+    pub fn alloc() -> Self {
         let addr = ArchInterface::frame_alloc_persist().into();
         let page_table = Self(addr);
         page_table.restore();
-        Arc::new(page_table)
+        page_table
+    }
+
+    /// 页表的根物理地址，内核用它来标识地址空间
+    #[inline]
+    pub const fn token(&self) -> usize {
+        self.0 .0
+    }
+
+    /// 借用 token 对应的页表，返回值 drop 时不会回收页表
+    #[inline]
+    pub fn from_token(token: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self(PhysAddr(token)))
     }
 
     #[inline]
@@ -89,78 +103,210 @@ impl PageTable {
 
     #[inline]
     pub fn change(&self) {
+        let (pcid, flush) = switch_asid(self.0 .0);
+        // 第 63 位置 1 时保留这个 PCID 原有的表项
+        let keep = match flush {
+            AsidFlush::None => 1 << 63,
+            _ => 0,
+        };
         unsafe {
-            core::arch::asm!("mov     cr3, {}", in(reg) self.0.0);
+            core::arch::asm!("mov     cr3, {}", in(reg) self.0 .0 | pcid | keep);
+        }
+        if let AsidFlush::All = flush {
+            flush_tlb(None);
         }
     }
 
+    /// 内核映射都在所有页表共用的 PDPT 里，本来就是共享的
     #[inline]
-    pub fn get_entry(&self, vpn: VirtPage) -> &mut PTEntry {
-        let vaddr = vpn.to_addr().into();
+    pub fn share_kernel(&self) {}
 
-        let pml4 = self.0.slice_mut_with_len::<PML4Entry>(PAGE_SIZE_ENTRIES);
-        let pml4_index = pml4_index(vaddr);
-        if !pml4[pml4_index].is_present() {
-            pml4[pml4_index] = PML4Entry::new(
-                ArchInterface::frame_alloc_persist().to_addr().into(),
-                PML4Flags::P | PML4Flags::RW | PML4Flags::US,
-            );
+    /// invlpg 只对当前的 PCID 有效，不是当前页表的话等它下次切换进来时刷新整个 PCID
+    fn flush_page(&self, vaddr: VirtAddr) {
+        // 内核映射改了的话，所有 PCID 里缓存的都要刷掉
+        if vaddr.0 >= VIRT_ADDR_START {
+            flush_tlb(None);
+            return;
         }
-
-        let pdpt = PhysAddr::new(pml4[pml4_index].address().into())
-            .slice_mut_with_len::<PDPTEntry>(PAGE_SIZE_ENTRIES);
-        let pdpt_index = pdpt_index(vaddr);
-        if !pdpt[pdpt_index].is_present() {
-            pdpt[pdpt_index] = PDPTEntry::new(
-                ArchInterface::frame_alloc_persist().to_addr().into(),
-                PDPTFlags::P | PDPTFlags::RW | PDPTFlags::US,
-            );
+        let cr3: u64;
+        unsafe { core::arch::asm!("mov {}, cr3", out(reg) cr3) };
+        if cr3 & ADDRESS_MASK == self.0 .0 as u64 {
+            flush_tlb(Some(vaddr));
+        } else {
+            mark_stale(self.0 .0);
         }
+    }
 
-        let pd = PhysAddr::new(pdpt[pdpt_index].address().into())
-            .slice_mut_with_len::<PDEntry>(PAGE_SIZE_ENTRIES);
-        let pd_index = pd_index(vaddr);
-        if !pd[pd_index].is_present() {
-            pd[pd_index] = PDEntry::new(
-                ArchInterface::frame_alloc_persist().to_addr().into(),
-                PDFlags::P | PDFlags::RW | PDFlags::US,
-            );
+    /// 找到 `vpn` 在第 `level` 层的页表项，沿途缺少的页表会被分配，
+    /// 覆盖 `vpn` 的大页会被拆开
+    ///
+    /// 各级页表项的 P/RW/US/A/D 位位置相同，这里都当作 `PTEntry` 访问
+    pub fn get_entry(&self, vpn: VirtPage, level: usize) -> &mut PTEntry {
+        let mut table = self.0;
+        for i in (level + 1..4).rev() {
+            let entry = &mut get_table(table)[(vpn.0 >> (9 * i)) & 0x1ff];
+            if !entry.is_present() {
+                *entry = PTEntry::new(
+                    ArchInterface::frame_alloc_persist().to_addr().into(),
+                    PTFlags::P | PTFlags::RW | PTFlags::US,
+                );
+            } else if is_huge(entry) {
+                split_huge(entry, i);
+            }
+            table = PhysAddr::new(entry.address().as_usize());
         }
+        &mut get_table(table)[(vpn.0 >> (9 * level)) & 0x1ff]
+    }
 
-        let pte = PhysAddr::new(pd[pd_index].address().into())
-            .slice_mut_with_len::<PTEntry>(PAGE_SIZE_ENTRIES);
-        let pte_index = pt_index(vaddr);
-        &mut pte[pte_index]
+    /// 找到映射 `vpn` 的叶子页表项和它所在的层级
+    fn find_entry(&self, vpn: VirtPage) -> Option<(&mut PTEntry, usize)> {
+        let mut table = self.0;
+        for level in (0..4).rev() {
+            let entry = &mut get_table(table)[(vpn.0 >> (9 * level)) & 0x1ff];
+            if !entry.is_present() {
+                return None;
+            }
+            if level == 0 || is_huge(entry) {
+                return Some((entry, level));
+            }
+            table = PhysAddr::new(entry.address().as_usize());
+        }
+        None
     }
 
+    /// 用 `size` 大小的页把 `vpn` 映射到 `ppn`，两者都要按 `size` 对齐
     #[inline]
-    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, _level: usize) {
-        *self.get_entry(vpn) = PTEntry::new(ppn.to_addr().into(), flags.into());
-        flush_tlb(Some(vpn.into()))
+    pub fn map(&self, ppn: PhysPage, vpn: VirtPage, flags: MappingFlags, size: MapPageSize) {
+        assert!(size.aligned(vpn, ppn));
+        let level = size.level();
+        let entry = self.get_entry(vpn, level);
+        if level == 0 {
+            *entry = PTEntry::new(ppn.to_addr().into(), flags.into());
+            self.flush_page(vpn.into());
+            return;
+        }
+        // 原来是下一级页表的话，整个换成大页
+        let old = *entry;
+        *entry = PTEntry(PTEntry::new(ppn.to_addr().into(), flags.into()).0 | HUGE_PAGE);
+        if old.is_present() && !is_huge(&old) {
+            free_table(PhysAddr::new(old.address().as_usize()), level - 1);
+            flush_tlb(None);
+        } else {
+            self.flush_page(vpn.into());
+        }
     }
 
+    /// 解除 `vpn` 这一个 4K 页的映射，它在大页里的话先把大页拆开
     #[inline]
     pub fn unmap(&self, vpn: VirtPage) {
-        *self.get_entry(vpn) = PTEntry(0);
-        flush_tlb(Some(vpn.into()))
+        if self.find_entry(vpn).is_none() {
+            return;
+        }
+        *self.get_entry(vpn, 0) = PTEntry(0);
+        self.flush_page(vpn.into())
     }
 
+    /// 清除 `vpn` 的访问位，返回清除之前是否被访问过
     #[inline]
-    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
-        let pte = self.get_entry(vaddr.into());
-        if !pte.is_present() {
-            None
-        } else {
-            Some(PhysAddr::new(
-                pte.address().as_usize() + vaddr.0 % PAGE_SIZE,
-            ))
+    pub fn test_and_clear_accessed(&self, vpn: VirtPage) -> bool {
+        let pte = match self.find_entry(vpn) {
+            Some((pte, _)) if pte.is_accessed() => pte,
+            _ => return false,
+        };
+        pte.0 &= !PTFlags::A.bits();
+        self.flush_page(vpn.into());
+        true
+    }
+
+    /// `vpn` 映射之后是否被写过
+    #[inline]
+    pub fn is_dirty(&self, vpn: VirtPage) -> bool {
+        self.find_entry(vpn)
+            .map_or(false, |(pte, _)| pte.is_dirty())
+    }
+
+    /// `vpn` 映射的权限，没有映射时返回 None
+    pub fn mapping_flags(&self, vpn: VirtPage) -> Option<MappingFlags> {
+        let flags = PTFlags::from_bits_truncate(self.find_entry(vpn)?.0 .0);
+        let mut res = MappingFlags::R;
+        for (pt_flag, flag) in [
+            (PTFlags::RW, MappingFlags::W),
+            (PTFlags::US, MappingFlags::U),
+            (PTFlags::A, MappingFlags::A),
+            (PTFlags::D, MappingFlags::D),
+        ] {
+            if flags.contains(pt_flag) {
+                res |= flag;
+            }
+        }
+        if !flags.contains(PTFlags::XD) {
+            res |= MappingFlags::X;
         }
+        Some(res)
+    }
+
+    #[inline]
+    pub fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        let (pte, level) = self.find_entry(vaddr.into())?;
+        // vaddr.0 % (PAGE_SIZE << (9 * level)) 是大页内偏移
+        Some(PhysAddr::new(
+            pte.address().as_usize() + vaddr.0 % (PAGE_SIZE << (9 * level)),
+        ))
+    }
+}
+
+/// PD 和 PDPT 表项里的 PS 位，表示这一项直接映射 2M/1G 的大页
+const HUGE_PAGE: u64 = PDFlags::PS.bits();
+/// 页表项里物理地址所在的位
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+#[inline]
+fn get_table(paddr: PhysAddr) -> &'static mut [PTEntry] {
+    paddr.slice_mut_with_len::<PTEntry>(PAGE_SIZE_ENTRIES)
+}
+
+#[inline]
+fn is_huge(entry: &PTEntry) -> bool {
+    entry.0 & HUGE_PAGE != 0
+}
+
+/// 把第 `level` 层的大页拆成下一层的 512 个页，属性不变
+fn split_huge(entry: &mut PTEntry, level: usize) {
+    let table = ArchInterface::frame_alloc_persist();
+    let base = entry.address().as_u64();
+    let mut attrs = entry.0 & !ADDRESS_MASK;
+    // 最底层页表项的第 7 位是 PAT，不是 PS
+    if level == 1 {
+        attrs &= !HUGE_PAGE;
+    }
+    for (i, child) in get_table(table.into()).iter_mut().enumerate() {
+        *child = PTEntry((base + ((i as u64) << (12 + 9 * (level - 1)))) | attrs);
+    }
+    *entry = PTEntry::new(
+        table.to_addr().into(),
+        PTFlags::P | PTFlags::RW | PTFlags::US,
+    );
+}
+
+/// 回收第 `level` 层的页表和它下面的所有页表，叶子指向的页帧由内核管理
+fn free_table(paddr: PhysAddr, level: usize) {
+    if level > 0 {
+        get_table(paddr)
+            .iter()
+            .filter(|x| x.is_present() && !is_huge(x))
+            .for_each(|x| free_table(PhysAddr::new(x.address().as_usize()), level - 1));
     }
+    ArchInterface::frame_unalloc(paddr.into());
 }
 
 impl Drop for PageTable {
     fn drop(&mut self) {
-        self.restore();
+        // 高一半是所有页表共用的内核映射
+        get_table(self.0)[..0x100]
+            .iter()
+            .filter(|x| x.is_present())
+            .for_each(|x| free_table(PhysAddr::new(x.address().as_usize()), 2));
+        release_asid(self.0 .0);
         ArchInterface::frame_unalloc(self.0.into());
     }
 }
@@ -0,0 +1,149 @@
+//! AP 的启动和 TLB shootdown
+//!
+//! AP 从实模式开始执行，BSP 先把 ap_start.S 里的 trampoline 复制到低端内存，
+//! 再用 INIT-SIPI-SIPI 唤醒它，之后和 BSP 一样经过 multiboot.S 进入长模式
+
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::apic::{self, vectors::TLB_SHOOTDOWN_VECTOR};
+use super::{gdt, idt, init_cpu_features, interrupt, time};
+use crate::smp::{alloc_secondary_stack, other_cpus_mask, secondary_init};
+use crate::{
+    cpu_online_mask, flush_tlb, hart_id, shutdown, ArchInterface, VirtAddr, MAX_CPU_NUM, PAGE_SIZE,
+    VIRT_ADDR_START,
+};
+
+/// trampoline 放在第 6 页，SIPI 的向量就是这个页号
+const START_PAGE_IDX: u8 = 6;
+const START_PAGE_PADDR: usize = START_PAGE_IDX as usize * PAGE_SIZE;
+
+/// 没有解析 ACPI，不知道有几个核，等这么久还没上线就认为这个核不存在
+const AP_BOOT_TIMEOUT_US: u64 = 100_000;
+
+global_asm!(
+    include_str!("ap_start.S"),
+    start_page_paddr = const START_PAGE_PADDR,
+);
+
+extern "C" {
+    fn ap_start();
+    fn ap_end();
+    fn ap_entry32();
+}
+
+/// 复制 trampoline，并在页末尾填上 cpu id、栈顶和 32 位入口的物理地址
+unsafe fn setup_start_page(cpu_id: usize, stack_top: usize) {
+    const SLOTS: usize = PAGE_SIZE / 8;
+    let page = (START_PAGE_PADDR | VIRT_ADDR_START) as *mut u64;
+    core::ptr::copy_nonoverlapping(
+        ap_start as usize as *const u8,
+        page as *mut u8,
+        ap_end as usize - ap_start as usize,
+    );
+    let slots = core::slice::from_raw_parts_mut(page, SLOTS);
+    slots[SLOTS - 3] = cpu_id as u64;
+    slots[SLOTS - 2] = (stack_top & !VIRT_ADDR_START) as u64;
+    slots[SLOTS - 1] = (ap_entry32 as usize & !VIRT_ADDR_START) as u64;
+}
+
+pub(super) extern "C" fn rust_secondary_main(cpu_id: usize) -> ! {
+    secondary_init(cpu_id);
+    idt::init();
+    gdt::init();
+    apic::init_secondary();
+    interrupt::init_syscall();
+    time::init_timer();
+    init_cpu_features();
+
+    info!("cpu {} started", cpu_id);
+
+    ArchInterface::main(cpu_id);
+    shutdown()
+}
+
+fn wait_online_timeout(cpu_id: usize) -> bool {
+    for _ in 0..AP_BOOT_TIMEOUT_US / 10 {
+        if cpu_online_mask() & (1 << cpu_id) != 0 {
+            return true;
+        }
+        time::busy_wait_us(10);
+    }
+    false
+}
+
+/// 最多启动 `cpu_num` 个 AP，返回实际启动的个数
+///
+/// QEMU 按顺序给 CPU 分配 APIC ID，这里直接把 APIC ID 当作 cpu id，
+/// 有一个核没有上线就不再继续，免得它晚些醒来时用到被改过的 trampoline
+pub fn start_secondary_cpus(cpu_num: usize) -> usize {
+    let mut started = 0;
+    for cpu_id in 1..=cpu_num.min(MAX_CPU_NUM - 1) {
+        let stack_top = alloc_secondary_stack();
+        // trampoline 在 32 位模式下设置栈
+        assert!(stack_top & !VIRT_ADDR_START <= u32::MAX as usize);
+        unsafe { setup_start_page(cpu_id, stack_top) };
+        let apic_id = apic::raw_apic_id(cpu_id as u8);
+        let lapic = apic::local_apic();
+        unsafe {
+            lapic.send_init_ipi(apic_id);
+            time::busy_wait_us(10_000);
+            lapic.send_sipi(START_PAGE_IDX, apic_id);
+            time::busy_wait_us(200);
+            lapic.send_sipi(START_PAGE_IDX, apic_id);
+        }
+        if !wait_online_timeout(cpu_id) {
+            break;
+        }
+        started += 1;
+    }
+    started
+}
+
+/// 同一时间只有一个核在发起 shootdown
+static SHOOTDOWN_LOCK: Mutex<()> = Mutex::new(());
+/// 要刷新的地址，0 表示整个 TLB
+static SHOOTDOWN_ADDR: AtomicUsize = AtomicUsize::new(0);
+/// 还没有刷新完的核
+static SHOOTDOWN_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// 处理发给当前核的 shootdown，由 IPI 调用，等待的时候也会调用
+pub(crate) fn handle_shootdown() {
+    let bit = 1 << hart_id();
+    if SHOOTDOWN_PENDING.load(Ordering::Acquire) & bit == 0 {
+        return;
+    }
+    match SHOOTDOWN_ADDR.load(Ordering::Acquire) {
+        0 => flush_tlb(None),
+        vaddr => flush_tlb(Some(VirtAddr::from(vaddr))),
+    }
+    SHOOTDOWN_PENDING.fetch_and(!bit, Ordering::AcqRel);
+}
+
+/// 刷新本核的 TLB，再发 IPI 让其他上线的核一起刷新，等它们都刷完才返回
+///
+/// 调用时可能关着中断，两个核同时发起时靠等待中的 [handle_shootdown] 避免互相等死
+pub fn tlb_shootdown(vaddr: Option<VirtAddr>) {
+    flush_tlb(vaddr);
+    let mask = other_cpus_mask();
+    if mask == 0 {
+        return;
+    }
+    let _guard = loop {
+        if let Some(guard) = SHOOTDOWN_LOCK.try_lock() {
+            break guard;
+        }
+        handle_shootdown();
+        core::hint::spin_loop();
+    };
+    SHOOTDOWN_ADDR.store(vaddr.map_or(0, |vaddr| vaddr.0), Ordering::Release);
+    SHOOTDOWN_PENDING.store(mask, Ordering::Release);
+    let lapic = apic::local_apic();
+    for cpu_id in (0..MAX_CPU_NUM).filter(|cpu_id| mask & (1 << cpu_id) != 0) {
+        unsafe { lapic.send_ipi(TLB_SHOOTDOWN_VECTOR, apic::raw_apic_id(cpu_id as u8)) };
+    }
+    while SHOOTDOWN_PENDING.load(Ordering::Acquire) != 0 {
+        core::hint::spin_loop();
+    }
+}
@@ -25,6 +25,11 @@ pub(super) fn init_early() {
     unsafe { INIT_TICK = core::arch::x86_64::_rdtsc() };
     debug!("INIT_TICK: {}", unsafe { INIT_TICK });
 
+    init_timer();
+}
+
+/// 每个核都有自己的 LAPIC 定时器
+pub(super) fn init_timer() {
     unsafe {
         use x2apic::lapic::{TimerDivide, TimerMode};
         let lapic = super::apic::local_apic();
@@ -37,3 +42,12 @@ pub(super) fn init_early() {
         // set_oneshot_timer(2000);
     }
 }
+
+/// 用 TSC 忙等 `us` 微秒，启动 AP 时用
+pub(super) fn busy_wait_us(us: u64) {
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    let ticks = us * unsafe { CPU_FREQ_MHZ };
+    while unsafe { core::arch::x86_64::_rdtsc() } - start < ticks {
+        core::hint::spin_loop();
+    }
+}
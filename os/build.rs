@@ -44,6 +44,9 @@ fn main() {
     println!("cargo:rerun-if-env-changed=CARGO_CFG_DRIVER");
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=linker.lds.S");
+    println!("cargo:rerun-if-env-changed=FS_IMG");
+    println!("cargo:rerun-if-env-changed=SMP");
+    println!("cargo:rerun-if-env-changed=INIT");
 }
 
 fn gen_linker_script(platform: &str) -> Result<()> {
@@ -69,9 +72,13 @@ fn gen_linker_script(platform: &str) -> Result<()> {
     //     &env::var("CARGO_CFG_KERNEL_BASE").expect("can't find KERNEL_BASE cfg"),
     // );
     let ld_content = ld_content.replace("%KERNEL_BASE%", kernel_base);
-    let ld_content = ld_content.replace("%SMP%", "1");
+    // 给每个核预留 percpu 区域，和 arch::MAX_CPU_NUM 一样由 SMP 决定，默认 4
+    let smp = env::var("SMP").unwrap_or_else(|_| "4".to_string());
+    let ld_content = ld_content.replace("%SMP%", &smp);
 
     std::fs::write(&fname, ld_content)?;
+    let ld_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join(&fname);
+    println!("cargo:rustc-link-arg=-T{}", ld_path.display());
     println!("cargo:rerun-if-env-changed=CARGO_CFG_KERNEL_BASE");
     Ok(())
 }
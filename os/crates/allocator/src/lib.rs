@@ -2,19 +2,188 @@
 
 extern crate alloc;
 
+mod slab;
+
 use buddy_system_allocator::LockedHeap;
-use log::info;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use log::{error, info};
+use slab::{SlabCache, SLAB_PAGES};
+use spin::{Mutex, Once};
+
+pub use slab::{CacheStats, SLAB_SIZE};
 
 // 堆大小
 pub const HEAP_SIZE: usize = 0x0180_0000;
 
+const PAGE_SIZE: usize = 0x1000;
+
+/// 静态堆不够时一次至少从页帧分配器拿这么多页
+const HEAP_GROW_PAGES: usize = 64;
+
+/// slab 缓存的对象大小，更大的直接在堆上分配
+const SIZE_CLASSES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
 // 堆空间
 #[link_section = ".bss.heap"]
 static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 
+/// 内核提供的物理页，slab 和堆都从这里扩充
+pub trait PageProvider: Sync {
+    /// 分配 `pages` 个连续的页，按 `pages` 对齐，返回内核可以直接访问的地址。
+    /// 拿不到时返回 None，不能睡眠，当前核已经持有分配器的锁时也不能等待
+    fn alloc_pages(&self, pages: usize) -> Option<usize>;
+    /// 释放 `alloc_pages` 得到的页，暂时不能释放时返回 false
+    fn dealloc_pages(&self, addr: usize, pages: usize) -> bool;
+}
+
+static PAGE_PROVIDER: Once<&'static dyn PageProvider> = Once::new();
+
+/// 页帧分配器准备好之后由内核注册
+pub fn set_page_provider(provider: &'static dyn PageProvider) {
+    PAGE_PROVIDER.call_once(|| provider);
+}
+
+/// 静态堆和扩充进来的页的使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub total: usize,
+    pub used: usize,
+}
+
+/// 小对象按大小放进 slab 缓存，大对象和拿不到页时的 slab 放在伙伴系统的堆上
+struct KernelAllocator {
+    caches: Mutex<[SlabCache; SIZE_CLASSES.len()]>,
+    heap: LockedHeap<30>,
+}
+
 /// 堆内存分配器
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap<30> = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelAllocator = KernelAllocator {
+    caches: Mutex::new([
+        SlabCache::new(8),
+        SlabCache::new(16),
+        SlabCache::new(32),
+        SlabCache::new(64),
+        SlabCache::new(128),
+        SlabCache::new(256),
+        SlabCache::new(512),
+        SlabCache::new(1024),
+        SlabCache::new(2048),
+    ]),
+    heap: LockedHeap::empty(),
+};
+
+/// 对象所在的缓存，对象按自己的大小对齐，所以对齐要求也要满足
+fn size_class(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SIZE_CLASSES.iter().position(|&class| class >= size)
+}
+
+fn slab_layout() -> Layout {
+    Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap()
+}
+
+fn alloc_pages(pages: usize) -> Option<usize> {
+    PAGE_PROVIDER.get()?.alloc_pages(pages)
+}
+
+impl KernelAllocator {
+    fn alloc_object(&self, class: usize) -> *mut u8 {
+        let ptr = self.caches.lock()[class].alloc();
+        if !ptr.is_null() {
+            return ptr;
+        }
+        // 不能拿着锁去要页，页帧分配器自己也会分配内存
+        let (addr, from_pages) = match alloc_pages(SLAB_PAGES) {
+            Some(addr) => (addr, true),
+            None => match self.alloc_heap(slab_layout()) {
+                ptr if ptr.is_null() => return ptr,
+                ptr => (ptr as usize, false),
+            },
+        };
+        let mut caches = self.caches.lock();
+        unsafe { caches[class].add_slab(addr, from_pages) };
+        caches[class].alloc()
+    }
+
+    unsafe fn dealloc_object(&self, class: usize, ptr: *mut u8) {
+        let (addr, from_pages) = match self.caches.lock()[class].dealloc(ptr) {
+            Some(slab) => slab,
+            None => return,
+        };
+        if !from_pages {
+            self.heap
+                .lock()
+                .dealloc(NonNull::new_unchecked(addr as *mut u8), slab_layout());
+        } else if !PAGE_PROVIDER.get().unwrap().dealloc_pages(addr, SLAB_PAGES) {
+            // 页帧分配器正忙，先放回缓存里
+            self.caches.lock()[class].add_slab(addr, true);
+        }
+    }
+
+    fn alloc_heap(&self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.heap.lock().alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // 静态堆用完了就从页帧分配器拿一段加进来，这部分不再还回去
+        let size = layout.size().max(layout.align()).next_power_of_two();
+        let pages = (size / PAGE_SIZE).max(HEAP_GROW_PAGES);
+        let addr = match alloc_pages(pages) {
+            Some(addr) => addr,
+            None => return null_mut(),
+        };
+        let mut heap = self.heap.lock();
+        unsafe { heap.add_to_heap(addr, addr + pages * PAGE_SIZE) };
+        heap.alloc(layout).map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    /// 内存耗尽时打印各个缓存的使用情况，之后由内核的 alloc_error_handler 决定怎么处理
+    fn report_oom(&self, layout: Layout) {
+        error!("kernel heap: out of memory when allocating {:?}", layout);
+        error!("kernel heap: {:?}", heap_stats());
+        for stats in cache_stats().iter().filter(|stats| stats.slabs != 0) {
+            error!("kernel heap: {:?}", stats);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = match size_class(&layout) {
+            Some(class) => self.alloc_object(class),
+            None => self.alloc_heap(layout),
+        };
+        if ptr.is_null() {
+            self.report_oom(layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match size_class(&layout) {
+            Some(class) => self.dealloc_object(class, ptr),
+            None => self
+                .heap
+                .lock()
+                .dealloc(NonNull::new_unchecked(ptr), layout),
+        }
+    }
+}
+
+/// 每个 slab 缓存的统计信息，按对象大小从小到大排列
+pub fn cache_stats() -> [CacheStats; SIZE_CLASSES.len()] {
+    let caches = HEAP_ALLOCATOR.caches.lock();
+    core::array::from_fn(|i| caches[i].stats())
+}
+
+pub fn heap_stats() -> HeapStats {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    HeapStats {
+        total: heap.stats_total_bytes(),
+        used: heap.stats_alloc_actual(),
+    }
+}
 
 /// 初始化堆内存分配器
 pub fn init() {
@@ -25,6 +194,7 @@ pub fn init() {
             HEAP.as_ptr() as usize + HEAP_SIZE
         );
         HEAP_ALLOCATOR
+            .heap
             .lock()
             .init(HEAP.as_mut_ptr() as usize, HEAP_SIZE);
     }
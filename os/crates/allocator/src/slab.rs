@@ -0,0 +1,174 @@
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+/// 每个 slab 占的页数
+pub const SLAB_PAGES: usize = 4;
+/// slab 按自己的大小对齐，从对象的地址就能找到所在 slab 的头
+pub const SLAB_SIZE: usize = SLAB_PAGES * crate::PAGE_SIZE;
+
+/// 空闲对象的开头存着下一个空闲对象
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// 放在每个 slab 的开头
+struct SlabHeader {
+    prev: *mut SlabHeader,
+    next: *mut SlabHeader,
+    free: *mut FreeObject,
+    in_use: usize,
+    /// 来自页帧分配器，否则是从静态堆上分出来的
+    from_pages: bool,
+}
+
+/// 一个缓存的统计信息
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    /// 对象大小
+    pub object_size: usize,
+    pub slabs: usize,
+    /// 正在使用的对象个数
+    pub in_use: usize,
+    /// 所有 slab 一共能放的对象个数
+    pub capacity: usize,
+}
+
+/// 同一种大小的对象的缓存
+pub struct SlabCache {
+    object_size: usize,
+    /// 还有空闲对象的 slab，满的 slab 不在链表里
+    partial: *mut SlabHeader,
+    slabs: usize,
+    /// 完全空闲的 slab 个数
+    empty: usize,
+    in_use: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    /// `object_size` 是 2 的幂，对象按自己的大小对齐
+    pub const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            partial: null_mut(),
+            slabs: 0,
+            empty: 0,
+            in_use: 0,
+        }
+    }
+
+    /// 第一个对象跳过 slab 头
+    fn first_offset(&self) -> usize {
+        (size_of::<SlabHeader>() + self.object_size - 1) / self.object_size * self.object_size
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (SLAB_SIZE - self.first_offset()) / self.object_size
+    }
+
+    /// 没有空闲对象时返回空指针
+    pub fn alloc(&mut self) -> *mut u8 {
+        let slab = self.partial;
+        if slab.is_null() {
+            return null_mut();
+        }
+        unsafe {
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            if (*slab).in_use == 0 {
+                self.empty -= 1;
+            }
+            (*slab).in_use += 1;
+            if (*slab).free.is_null() {
+                self.unlink(slab);
+            }
+            self.in_use += 1;
+            object as *mut u8
+        }
+    }
+
+    /// 回收一个对象，多出来的空 slab 从缓存里摘下来，返回它的地址和是否来自页帧分配器
+    ///
+    /// # Safety
+    ///
+    /// `ptr` 必须是这个缓存分配出去的
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8) -> Option<(usize, bool)> {
+        let slab = (ptr as usize & !(SLAB_SIZE - 1)) as *mut SlabHeader;
+        let object = ptr as *mut FreeObject;
+        if (*slab).free.is_null() {
+            self.push(slab);
+        }
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        self.in_use -= 1;
+        if (*slab).in_use != 0 {
+            return None;
+        }
+        // 留一块空的 slab，免得在边界上反复申请释放
+        if self.empty == 0 {
+            self.empty += 1;
+            return None;
+        }
+        self.unlink(slab);
+        self.slabs -= 1;
+        Some((slab as usize, (*slab).from_pages))
+    }
+
+    /// 加入一块新的 slab
+    ///
+    /// # Safety
+    ///
+    /// `addr` 按 [SLAB_SIZE] 对齐，并且 [SLAB_SIZE] 大小的内存都归这个缓存
+    pub unsafe fn add_slab(&mut self, addr: usize, from_pages: bool) {
+        let slab = addr as *mut SlabHeader;
+        let mut free = null_mut();
+        // 倒着串起来，先分配低地址的对象
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (addr + self.first_offset() + i * self.object_size) as *mut FreeObject;
+            (*object).next = free;
+            free = object;
+        }
+        slab.write(SlabHeader {
+            prev: null_mut(),
+            next: null_mut(),
+            free,
+            in_use: 0,
+            from_pages,
+        });
+        self.push(slab);
+        self.slabs += 1;
+        self.empty += 1;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            object_size: self.object_size,
+            slabs: self.slabs,
+            in_use: self.in_use,
+            capacity: self.slabs * self.objects_per_slab(),
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut SlabHeader) {
+        (*slab).prev = null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut SlabHeader) {
+        let (prev, next) = ((*slab).prev, (*slab).next);
+        if prev.is_null() {
+            self.partial = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+    }
+}
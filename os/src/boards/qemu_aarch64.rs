@@ -0,0 +1,10 @@
+pub const MMIO: &[(usize, usize)] = &[
+    (0x0800_0000, 0x2_0000), // GIC in virt machine
+    (0x0900_0000, 0x1000),   // PL011 UART in virt machine
+    (0x0a00_0000, 0x4000),   // VIRTIO MMIO in virt machine
+];
+
+pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+pub type CharDeviceImpl = crate::drivers::chardev::ArchConsole;
+
+pub fn device_init() {}
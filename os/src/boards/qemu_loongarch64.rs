@@ -0,0 +1,8 @@
+pub const MMIO: &[(usize, usize)] = &[
+    (0x1fe0_01e0, 0x1000), // UART0 in virt machine
+];
+
+pub type BlockDeviceImpl = crate::drivers::block::RamDisk;
+pub type CharDeviceImpl = crate::drivers::chardev::ArchConsole;
+
+pub fn device_init() {}
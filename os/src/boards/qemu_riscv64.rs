@@ -0,0 +1,24 @@
+pub const MMIO: &[(usize, usize)] = &[
+    (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x2000000, 0x10000),     // core local interrupter (CLINT)
+    (0xc000000, 0x210000),    // VIRT_PLIC in virt machine
+    (0x10000000, 0x9000),     // VIRT_UART0 with GPU  in virt machine
+];
+
+pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
+
+pub const VIRT_UART: usize = arch::VIRT_ADDR_START+0x1000_0000;
+#[allow(unused)]
+pub const VIRTGPU_XRES: u32 = 1280;
+#[allow(unused)]
+pub const VIRTGPU_YRES: u32 = 800;
+
+use crate::drivers::chardev::{CharDevice, UART};
+
+/// uart 的中断号，virtio 设备的中断号从设备树里读
+const UART_IRQ: usize = 10;
+
+pub fn device_init() {
+    arch::register_irq_handler(UART_IRQ, || UART.handle_irq());
+}
@@ -0,0 +1,9 @@
+pub const MMIO: &[(usize, usize)] = &[
+    (0xfec0_0000, 0x1000), // IO APIC
+    (0xfee0_0000, 0x1000), // Local APIC
+];
+
+pub type BlockDeviceImpl = crate::drivers::block::RamDisk;
+pub type CharDeviceImpl = crate::drivers::chardev::ArchConsole;
+
+pub fn device_init() {}
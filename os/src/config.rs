@@ -1,12 +1,17 @@
 #[allow(unused)]
 
-pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// 用户栈用到哪一页才分配哪一页，这里只是地址空间的大小，busybox 这类程序会在栈上放大数组
+pub const USER_STACK_SIZE: usize = 0x10_0000;
+/// 用户堆紧跟在 ELF 后面，最多能长到这么大，再往上是用户栈
+pub const USER_HEAP_MAX: usize = 0x400_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_HEAP_SIZE: usize = 0x100_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
+/// 不指定地址的 mmap 从这里往上找空闲的地址，在 framebuffer 的映射之上
+pub const MMAP_BASE: usize = 0x2000_0000;
+pub const MMAP_TOP: usize = 0x20_0000_0000;
+/// 位置无关的程序（ET_DYN）装载到这里，和静态链接的用户程序的基址一样
+pub const ELF_DYN_BASE: usize = 0x10000;
 
-pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
-pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
-
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+pub use crate::board::MMIO;
@@ -1,5 +1,11 @@
+#[cfg(any(target_arch = "x86_64", target_arch = "loongarch64"))]
+mod ram_disk;
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 mod virtio_blk;
 
+#[cfg(any(target_arch = "x86_64", target_arch = "loongarch64"))]
+pub use ram_disk::RamDisk;
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 pub use virtio_blk::VirtIOBlock;
 
 use crate::board::BlockDeviceImpl;
@@ -7,8 +13,28 @@ use alloc::sync::Arc;
 use easy_fs::BlockDevice;
 use lazy_static::*;
 
+type Disks = (Arc<dyn BlockDevice>, Option<Arc<dyn BlockDevice>>);
+
 lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// 根文件系统和交换区所在的盘要一起探测才分得清
+    static ref DISKS: Disks = disks();
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = DISKS.0.clone();
+    /// 交换区所在的块设备，没有第二块盘时不开启交换
+    pub static ref SWAP_DEVICE: Option<Arc<dyn BlockDevice>> = DISKS.1.clone();
+}
+
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+fn disks() -> Disks {
+    let (root, swap) = BlockDeviceImpl::probe();
+    (
+        Arc::new(root),
+        swap.map(|dev| Arc::new(dev) as Arc<dyn BlockDevice>),
+    )
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "loongarch64"))]
+fn disks() -> Disks {
+    (Arc::new(BlockDeviceImpl::new()), None)
 }
 
 #[allow(unused)]
@@ -0,0 +1,34 @@
+use super::BlockDevice;
+use crate::sync::UPIntrFreeCell;
+use core::ptr::addr_of_mut;
+
+const BLOCK_SZ: usize = 512;
+const IMG_LEN: usize = include_bytes!(env!("FS_IMG")).len();
+
+/// 编译时嵌入内核的文件系统镜像，用于没有 virtio-blk 的平台
+static mut RAM_DISK: [u8; IMG_LEN] = *include_bytes!(env!("FS_IMG"));
+
+pub struct RamDisk(UPIntrFreeCell<&'static mut [u8]>);
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let start = block_id * BLOCK_SZ;
+        self.0.exclusive_session(|disk| {
+            buf.copy_from_slice(&disk[start..start + buf.len()]);
+        });
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let start = block_id * BLOCK_SZ;
+        self.0.exclusive_session(|disk| {
+            disk[start..start + buf.len()].copy_from_slice(buf);
+        });
+    }
+    /// 内存里的磁盘不会产生中断
+    fn handle_irq(&self) {}
+}
+
+impl RamDisk {
+    pub fn new() -> Self {
+        unsafe { Self(UPIntrFreeCell::new(&mut *addr_of_mut!(RAM_DISK))) }
+    }
+}
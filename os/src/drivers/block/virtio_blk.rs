@@ -1,22 +1,23 @@
-use super::BlockDevice;
+use super::{BlockDevice, BLOCK_DEVICE};
 use crate::drivers::bus::virtio::VirtioHal;
+use crate::drivers::probe::{virtio_device_at, VirtioKind};
 use crate::sync::{Condvar, UPIntrFreeCell};
 use crate::task::schedule;
 use crate::DEV_NON_BLOCKING_ACCESS;
 use alloc::collections::BTreeMap;
+use easy_fs::{SuperBlock, BLOCK_SZ};
 use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
 
-#[allow(unused)]
-const VIRTIO0: usize = arch::VIRT_ADDR_START+0x10008000;
-
 pub struct VirtIOBlock {
     virtio_blk: UPIntrFreeCell<VirtIOBlk<'static, VirtioHal>>,
     condvars: BTreeMap<u16, Condvar>,
+    /// 没有注册中断的设备只能轮询
+    polling: bool,
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+        let nb = !self.polling && *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
             let mut resp = BlkResp::default();
             let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
@@ -37,7 +38,7 @@ impl BlockDevice for VirtIOBlock {
         }
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+        let nb = !self.polling && *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
             let mut resp = BlkResp::default();
             let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
@@ -67,10 +68,42 @@ impl BlockDevice for VirtIOBlock {
 }
 
 impl VirtIOBlock {
-    pub fn new() -> Self {
+    /// 带 easy-fs 超级块的盘是根文件系统，剩下的第一块用作交换区。
+    /// 设备树里 virtio-mmio 的顺序在各个平台上不一样，不能靠顺序区分
+    ///
+    /// 换页时可能持有锁，所以交换区总是轮询
+    pub fn probe() -> (Self, Option<Self>) {
+        let mut root = None;
+        let mut swap = None;
+        for dev in (0..).map_while(|i| virtio_device_at(VirtioKind::Block, i)) {
+            let blk = Self::from_mmio(dev.base, true);
+            if root.is_none() && blk.has_easy_fs() {
+                root = Some((blk, dev.irq));
+            } else if swap.is_none() {
+                swap = Some(blk);
+            }
+        }
+        let (mut root, irq) = root.expect("no easy-fs disk in device tree");
+        if let Some(irq) = irq {
+            arch::register_irq_handler(irq, || BLOCK_DEVICE.handle_irq());
+            root.polling = false;
+        }
+        (root, swap)
+    }
+
+    /// 直接读 0 号块，不能经过块缓存，缓存只按块号区分设备
+    fn has_easy_fs(&self) -> bool {
+        let mut buf = [0u32; BLOCK_SZ / 4];
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, BLOCK_SZ) };
+        self.read_block(0, bytes);
+        unsafe { &*(buf.as_ptr() as *const SuperBlock) }.is_valid()
+    }
+
+    fn from_mmio(base: usize, polling: bool) -> Self {
         let virtio_blk = unsafe {
             UPIntrFreeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
+                VirtIOBlk::<VirtioHal>::new(&mut *(base as *mut VirtIOHeader)).unwrap(),
             )
         };
         let mut condvars = BTreeMap::new();
@@ -82,6 +115,7 @@ impl VirtIOBlock {
         Self {
             virtio_blk,
             condvars,
+            polling,
         }
     }
 }
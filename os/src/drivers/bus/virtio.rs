@@ -1,6 +1,5 @@
 use crate::mm::{
-    frame_alloc_more, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPage,
-    StepByOne, VirtAddr,
+    frame_alloc_more, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPage, VirtAddr,
 };
 use crate::sync::UPIntrFreeCell;
 use alloc::vec::Vec;
@@ -12,6 +11,11 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(Vec::new()) };
 }
 
+/// virtio 设备 DMA 占用的页数
+pub fn dma_pages() -> usize {
+    QUEUE_FRAMES.exclusive_access().len()
+}
+
 pub struct VirtioHal;
 
 impl Hal for VirtioHal {
@@ -26,12 +30,11 @@ impl Hal for VirtioHal {
     }
 
     fn dma_dealloc(pa: usize, pages: usize) -> i32 {
-        let pa = PhysAddr::from(pa);
-        let mut ppn_base: PhysPage = pa.into();
-        for _ in 0..pages {
-            frame_dealloc(ppn_base);
-            ppn_base.step();
-        }
+        let start: PhysPage = PhysAddr::from(pa).into();
+        // 页帧归 QUEUE_FRAMES 里的 FrameTracker 管，从里面移除时才回收
+        QUEUE_FRAMES
+            .exclusive_access()
+            .retain(|frame| frame.ppn < start || frame.ppn >= start + pages);
         0
     }
 
@@ -0,0 +1,27 @@
+use super::CharDevice;
+use crate::task::suspend_current_and_run_next;
+
+/// 直接使用 arch 提供的串口读写，没有中断，读不到字符时让出 CPU
+pub struct ArchConsole;
+
+impl ArchConsole {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CharDevice for ArchConsole {
+    fn init(&self) {}
+    fn read(&self) -> u8 {
+        loop {
+            if let Some(ch) = arch::console_getchar() {
+                return ch;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+    fn write(&self, ch: u8) {
+        arch::console_putchar(ch);
+    }
+    fn handle_irq(&self) {}
+}
@@ -1,8 +1,10 @@
+mod arch_console;
 mod ns16550a;
 
 use crate::board::CharDeviceImpl;
 use alloc::sync::Arc;
 use lazy_static::*;
+pub use arch_console::ArchConsole;
 pub use ns16550a::NS16550a;
 
 pub trait CharDevice {
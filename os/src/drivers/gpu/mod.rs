@@ -1,4 +1,5 @@
 use crate::drivers::bus::virtio::VirtioHal;
+use crate::drivers::probe::{virtio_device, VirtioKind};
 use crate::sync::UPIntrFreeCell;
 use alloc::{sync::Arc, vec::Vec};
 use log::info;
@@ -6,7 +7,6 @@ use core::any::Any;
 use embedded_graphics::pixelcolor::Rgb888;
 use tinybmp::Bmp;
 use virtio_drivers::{VirtIOGpu, VirtIOHeader};
-const VIRTIO7: usize = arch::VIRT_ADDR_START+0x10007000;
 pub trait GpuDevice: Send + Sync + Any {
     fn update_cursor(&self);
     fn get_framebuffer(&self) -> &mut [u8];
@@ -24,9 +24,12 @@ pub struct VirtIOGpuWrapper {
 static BMP_DATA: &[u8] = include_bytes!("../../assert/mouse.bmp");
 impl VirtIOGpuWrapper {
     pub fn new() -> Self {
+        let base = virtio_device(VirtioKind::Gpu)
+            .expect("no virtio-gpu device in device tree")
+            .base;
         unsafe {
             let mut virtio =
-                VirtIOGpu::<VirtioHal>::new(&mut *(VIRTIO7 as *mut VirtIOHeader)).unwrap();
+                VirtIOGpu::<VirtioHal>::new(&mut *(base as *mut VirtIOHeader)).unwrap();
             let fbuffer = virtio.setup_framebuffer().unwrap();
             let len = fbuffer.len();
             let ptr = fbuffer.as_mut_ptr();
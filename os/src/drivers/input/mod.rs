@@ -1,4 +1,5 @@
 use crate::drivers::bus::virtio::VirtioHal;
+use crate::drivers::probe::{virtio_device, VirtioKind};
 use crate::sync::{Condvar, UPIntrFreeCell};
 use crate::task::schedule;
 use alloc::collections::VecDeque;
@@ -6,9 +7,6 @@ use alloc::sync::Arc;
 use core::any::Any;
 use virtio_drivers::{VirtIOHeader, VirtIOInput};
 
-const VIRTIO5: usize = arch::VIRT_ADDR_START+0x10005000;
-const VIRTIO6: usize = arch::VIRT_ADDR_START+0x10006000;
-
 struct VirtIOInputInner {
     virtio_input: VirtIOInput<'static, VirtioHal>,
     events: VecDeque<u64>,
@@ -26,12 +24,15 @@ pub trait InputDevice: Send + Sync + Any {
 }
 
 lazy_static::lazy_static!(
-    pub static ref KEYBOARD_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO5));
-    pub static ref MOUSE_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO6));
+    pub static ref KEYBOARD_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VirtioKind::Keyboard));
+    pub static ref MOUSE_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VirtioKind::Mouse));
 );
 
 impl VirtIOInputWrapper {
-    pub fn new(addr: usize) -> Self {
+    pub fn new(kind: VirtioKind) -> Self {
+        let addr = virtio_device(kind)
+            .expect("no virtio-input device in device tree")
+            .base;
         let inner = VirtIOInputInner {
             virtio_input: unsafe {
                 VirtIOInput::<VirtioHal>::new(&mut *(addr as *mut VirtIOHeader)).unwrap()
@@ -4,9 +4,9 @@ pub mod chardev;
 pub mod gpu;
 pub mod input;
 pub mod net;
-pub mod plic;
+pub mod probe;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{BLOCK_DEVICE, SWAP_DEVICE};
 pub use bus::*;
 pub use gpu::*;
 pub use input::*;
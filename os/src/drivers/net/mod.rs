@@ -1,13 +1,12 @@
 use core::any::Any;
 
+use crate::drivers::probe::{virtio_device, VirtioKind};
 use crate::drivers::virtio::VirtioHal;
 use crate::sync::UPIntrFreeCell;
 use alloc::sync::Arc;
 use lazy_static::*;
 use virtio_drivers::{VirtIOHeader, VirtIONet};
 
-const VIRTIO8: usize = arch::VIRT_ADDR_START+0x10004000;
-
 lazy_static! {
     pub static ref NET_DEVICE: Arc<dyn NetDevice> = Arc::new(VirtIONetWrapper::new());
 }
@@ -37,8 +36,11 @@ impl NetDevice for VirtIONetWrapper {
 
 impl VirtIONetWrapper {
     pub fn new() -> Self {
+        let base = virtio_device(VirtioKind::Net)
+            .expect("no virtio-net device in device tree")
+            .base;
         unsafe {
-            let virtio = VirtIONet::<VirtioHal>::new(&mut *(VIRTIO8 as *mut VirtIOHeader))
+            let virtio = VirtIONet::<VirtioHal>::new(&mut *(base as *mut VirtIOHeader))
                 .expect("can't create net device by virtio");
             VirtIONetWrapper(UPIntrFreeCell::new(virtio))
         }
@@ -0,0 +1,123 @@
+//! 根据设备树探测 virtio-mmio 设备，取代写死的 MMIO 地址和中断号
+
+use crate::drivers::input::{InputDevice, KEYBOARD_DEVICE, MOUSE_DEVICE};
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
+use easy_fs::BlockDevice;
+use fdt::node::FdtNode;
+use lazy_static::*;
+use log::info;
+use virtio_drivers::{DeviceType, VirtIOHeader};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VirtioKind {
+    Block,
+    Net,
+    Gpu,
+    Keyboard,
+    Mouse,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct VirtioMmio {
+    pub kind: VirtioKind,
+    /// MMIO 寄存器的内核虚拟地址
+    pub base: usize,
+    pub irq: Option<usize>,
+}
+
+lazy_static! {
+    static ref VIRTIO_DEVICES: UPIntrFreeCell<Vec<VirtioMmio>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+/// 找到的第一个 `kind` 类型的设备，中断只注册给这一个
+pub fn virtio_device(kind: VirtioKind) -> Option<VirtioMmio> {
+    virtio_device_at(kind, 0)
+}
+
+/// 第 `index` 个 `kind` 类型的设备，按设备树里的顺序，
+/// 不一定和 QEMU 命令行里 -device 的顺序一致，aarch64 上正好相反
+pub fn virtio_device_at(kind: VirtioKind, index: usize) -> Option<VirtioMmio> {
+    VIRTIO_DEVICES
+        .exclusive_access()
+        .iter()
+        .filter(|dev| dev.kind == kind)
+        .nth(index)
+        .copied()
+}
+
+pub fn probe_fdt_node(node: &FdtNode) {
+    let is_virtio = node
+        .compatible()
+        .map_or(false, |c| c.all().any(|s| s == "virtio,mmio"));
+    if !is_virtio {
+        return;
+    }
+    let base = match node.reg().and_then(|mut reg| reg.next()) {
+        Some(reg) => reg.starting_address as usize | arch::VIRT_ADDR_START,
+        None => return,
+    };
+    let header = unsafe { &*(base as *const VirtIOHeader) };
+    // 没有插设备的槽位 device id 为 0，verify 不会通过
+    if !header.verify() {
+        return;
+    }
+    let kind = match header.device_type() {
+        DeviceType::Block => VirtioKind::Block,
+        DeviceType::Network => VirtioKind::Net,
+        DeviceType::GPU => VirtioKind::Gpu,
+        DeviceType::Input if input_is_pointer(base) => VirtioKind::Mouse,
+        DeviceType::Input => VirtioKind::Keyboard,
+        _ => return,
+    };
+    let irq = node.property("interrupts").and_then(|p| parse_irq(p.value));
+    info!("virtio {:?} at {:#x}, irq {:?}", kind, base, irq);
+
+    let first = virtio_device(kind).is_none();
+    VIRTIO_DEVICES
+        .exclusive_access()
+        .push(VirtioMmio { kind, base, irq });
+    if let (true, Some(irq), Some(handler)) = (first, irq, irq_handler_of(kind)) {
+        arch::register_irq_handler(irq, handler);
+    }
+}
+
+/// riscv 的 PLIC 用一个 cell 表示中断号，aarch64 的 GIC 用三个 cell：类型、编号、触发方式
+fn parse_irq(value: &[u8]) -> Option<usize> {
+    let cell = |i: usize| -> Option<usize> {
+        let bytes = value.get(i * 4..i * 4 + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    match value.len() {
+        4 => cell(0),
+        // SPI 从 32 开始编号，PPI 从 16 开始
+        12 => Some(cell(1)? + if cell(0)? == 0 { 32 } else { 16 }),
+        _ => None,
+    }
+}
+
+/// 查询 virtio-input 支持的事件类型，能报告坐标事件的当作鼠标
+fn input_is_pointer(base: usize) -> bool {
+    const CONFIG: usize = 0x100;
+    const EV_BITS: u8 = 0x11;
+    const EV_REL: u8 = 0x02;
+    const EV_ABS: u8 = 0x03;
+    let select = (base + CONFIG) as *mut u8;
+    let subsel = (base + CONFIG + 1) as *mut u8;
+    let size = (base + CONFIG + 2) as *const u8;
+    [EV_REL, EV_ABS].iter().any(|&ev| unsafe {
+        select.write_volatile(EV_BITS);
+        subsel.write_volatile(ev);
+        size.read_volatile() != 0
+    })
+}
+
+fn irq_handler_of(kind: VirtioKind) -> Option<fn()> {
+    match kind {
+        VirtioKind::Keyboard => Some(|| KEYBOARD_DEVICE.handle_irq()),
+        VirtioKind::Mouse => Some(|| MOUSE_DEVICE.handle_irq()),
+        // 网卡和显卡都是轮询的，块设备要先认出哪块是根文件系统，由驱动自己注册
+        VirtioKind::Block | VirtioKind::Net | VirtioKind::Gpu => None,
+    }
+}
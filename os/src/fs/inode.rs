@@ -1,11 +1,13 @@
-use super::File;
+use super::{File, FileType};
 use crate::drivers::BLOCK_DEVICE;
-use crate::mm::UserBuffer;
+use crate::mm::{frame_alloc_tracker, FrameTracker, UserBuffer};
 use crate::sync::UPIntrFreeCell;
-use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
+use easy_fs::{EasyFileSystem, Inode, BLOCK_SZ};
 use lazy_static::*;
 
 pub struct OSInode {
@@ -41,6 +43,52 @@ impl OSInode {
         }
         v
     }
+    pub fn inode(&self) -> Arc<Inode> {
+        self.inner.exclusive_access().inode.clone()
+    }
+    /// 读写位置
+    pub fn offset(&self) -> usize {
+        self.inner.exclusive_access().offset
+    }
+    pub fn set_offset(&self, offset: usize) {
+        self.inner.exclusive_access().offset = offset;
+    }
+}
+
+lazy_static! {
+    /// 被映射的文件页，按 (文件, 页在文件里的偏移) 索引，没有映射之后自动失效
+    static ref PAGE_CACHE: UPIntrFreeCell<BTreeMap<((usize, usize), usize), Weak<FrameTracker>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+/// 页缓存里 `inode` 从 `offset` 开始的一页，`offset` 页对齐
+pub fn cached_page(inode: &Inode, offset: usize) -> Option<Arc<FrameTracker>> {
+    PAGE_CACHE
+        .exclusive_access()
+        .get(&(inode.id(), offset))
+        .and_then(Weak::upgrade)
+}
+
+/// 把 `inode` 从 `offset` 开始的一页读进页缓存，没有空闲的页帧时返回 None
+///
+/// 同一个文件的页被所有映射共用，共享映射互相能立即看到对方的写入；
+/// 内容只在 munmap、exec 和进程退出时写回，和 read/write 之间不保证一致。
+/// 读文件可能会让出 CPU，调用时不能拿着进程的锁
+pub fn load_page(inode: &Inode, offset: usize) -> Option<Arc<FrameTracker>> {
+    if let Some(page) = cached_page(inode, offset) {
+        return Some(page);
+    }
+    let frame = Arc::new(frame_alloc_tracker()?);
+    inode.read_at(offset, frame.ppn.get_bytes_array());
+    let key = (inode.id(), offset);
+    let mut cache = PAGE_CACHE.exclusive_access();
+    // 读文件的时候别人可能已经把这一页放进来了
+    if let Some(page) = cache.get(&key).and_then(Weak::upgrade) {
+        return Some(page);
+    }
+    cache.retain(|_, page| page.strong_count() > 0);
+    cache.insert(key, Arc::downgrade(&frame));
+    Some(frame)
 }
 
 lazy_static! {
@@ -50,6 +98,68 @@ lazy_static! {
     };
 }
 
+/// easy-fs 里 inode 在磁盘上的位置是唯一的，用来当 inode 号
+pub fn inode_number(inode: &Inode) -> usize {
+    let (block_id, block_offset) = inode.id();
+    block_id * BLOCK_SZ + block_offset
+}
+
+/// 打开的根目录，easy-fs 只有这一个目录
+pub struct RootDir {
+    /// 下一个要读的目录项
+    pos: UPIntrFreeCell<usize>,
+}
+
+impl RootDir {
+    pub fn new() -> Self {
+        Self {
+            pos: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+    pub fn pos(&self) -> usize {
+        *self.pos.exclusive_access()
+    }
+    pub fn set_pos(&self, pos: usize) {
+        *self.pos.exclusive_access() = pos;
+    }
+    pub fn ino(&self) -> usize {
+        inode_number(&ROOT_INODE)
+    }
+    /// 目录里的文件名和对应的 inode 号，不包括 `.` 和 `..`
+    pub fn entries(&self) -> Vec<(String, usize)> {
+        ROOT_INODE
+            .ls()
+            .into_iter()
+            .filter_map(|name| {
+                let ino = inode_number(&ROOT_INODE.find(&name)?);
+                Some((name, ino))
+            })
+            .collect()
+    }
+}
+
+impl File for RootDir {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// 目录项只能通过 [RootDir::entries] 读
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn file_type(&self) -> FileType {
+        FileType::Directory
+    }
+    fn as_dir(&self) -> Option<&RootDir> {
+        Some(self)
+    }
+}
+
 pub fn list_apps() {
     println!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
@@ -136,4 +246,10 @@ impl File for OSInode {
         }
         total_write_size
     }
+    fn file_type(&self) -> FileType {
+        FileType::Regular
+    }
+    fn as_os_inode(&self) -> Option<&OSInode> {
+        Some(self)
+    }
 }
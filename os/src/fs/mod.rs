@@ -4,13 +4,34 @@ mod stdio;
 
 use crate::mm::UserBuffer;
 
+/// 文件的类型，stat 的时候用
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    CharDevice,
+    Fifo,
+    Socket,
+}
+
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    fn file_type(&self) -> FileType;
+    /// 磁盘上的文件才能被 mmap
+    fn as_os_inode(&self) -> Option<&OSInode> {
+        None
+    }
+    /// 打开的目录可以读出目录项
+    fn as_dir(&self) -> Option<&RootDir> {
+        None
+    }
 }
 
-pub use inode::{list_apps, open_file, OpenFlags};
+pub use inode::{
+    cached_page, inode_number, list_apps, load_page, open_file, OSInode, OpenFlags, RootDir,
+};
 pub use pipe::make_pipe;
 pub use stdio::{Stdin, Stdout};
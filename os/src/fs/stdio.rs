@@ -1,4 +1,4 @@
-use super::File;
+use super::{File, FileType};
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
 use crate::mm::UserBuffer;
@@ -13,8 +13,11 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
+    /// 每次只读一个字符，缓冲区再大也一样
     fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
+        if user_buf.len() == 0 {
+            return 0;
+        }
         //println!("before UART.read() in Stdin::read()");
         let ch = UART.read();
         unsafe {
@@ -25,6 +28,9 @@ impl File for Stdin {
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn file_type(&self) -> FileType {
+        FileType::CharDevice
+    }
 }
 
 impl File for Stdout {
@@ -43,4 +49,7 @@ impl File for Stdout {
         }
         user_buf.len()
     }
+    fn file_type(&self) -> FileType {
+        FileType::CharDevice
+    }
 }
@@ -1,5 +1,9 @@
 use arch::{shutdown,VIRT_ADDR_START};
-use crate::task::current_kstack_top;
+use crate::sync::holding_locks;
+use crate::task::{
+    current_add_signal, current_kstack_top, current_task, exit_current_and_run_next, SignalFlags,
+};
+use core::alloc::Layout;
 use core::arch::asm;
 use core::panic::PanicInfo;
 use log::*;
@@ -23,17 +27,47 @@ fn panic(info: &PanicInfo) -> ! {
     shutdown()
 }
 
+/// 内核堆分配失败，堆已经打印过各个缓存的使用情况
+///
+/// 没有拿着锁的话结束当前进程，它的内存还回来之后内核还能继续运行；
+/// 拿着锁的话锁再也放不开了，只能 panic
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    if !holding_locks() && current_task().is_some() {
+        error!(
+            "[kernel] out of memory when allocating {:?}, killing the current process",
+            layout
+        );
+        current_add_signal(SignalFlags::SIGKILL);
+        exit_current_and_run_next(-9);
+        unreachable!();
+    }
+    panic!("out of memory when allocating {:?}", layout);
+}
+
 unsafe fn backtrace() {
     let mut fp: usize;
     let stop = current_kstack_top();
+    #[cfg(target_arch = "riscv64")]
     asm!("mv {}, s0", out(reg) fp);
+    #[cfg(target_arch = "aarch64")]
+    asm!("mov {}, x29", out(reg) fp);
+    #[cfg(target_arch = "x86_64")]
+    asm!("mov {}, rbp", out(reg) fp);
+    #[cfg(target_arch = "loongarch64")]
+    asm!("move {}, $fp", out(reg) fp);
     println!("---START BACKTRACE---");
     for i in 0..10 {
-        if fp == stop || fp == VIRT_ADDR_START{
+        if fp == 0 || fp == stop || fp == VIRT_ADDR_START{
             break;
         }
-        println!("#{}:fp={:#x}:ra={:#x}", i , fp, *( (fp - 8) as *const usize) | VIRT_ADDR_START );
-        fp = *((fp - 16) as *const usize);
+        // riscv/loongarch 的 fp 指向栈帧顶部，aarch64/x86_64 的 fp 指向保存的上一个 fp
+        #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+        let (ra, next_fp) = (*((fp - 8) as *const usize), *((fp - 16) as *const usize));
+        #[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+        let (ra, next_fp) = (*((fp + 8) as *const usize), *(fp as *const usize));
+        println!("#{}:fp={:#x}:ra={:#x}", i , fp, ra | VIRT_ADDR_START );
+        fp = next_fp;
     }
     println!("---END   BACKTRACE---");
 }
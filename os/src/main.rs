@@ -4,6 +4,7 @@
 #![feature(alloc_error_handler)]
 
 //use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE, INPUT_CONDVAR};
+#[cfg(target_arch = "riscv64")]
 use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE};
 use arch::shutdown;
 use arch::{
@@ -17,7 +18,10 @@ extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
-#[path = "boards/qemu.rs"]
+#[cfg_attr(target_arch = "riscv64", path = "boards/qemu_riscv64.rs")]
+#[cfg_attr(target_arch = "aarch64", path = "boards/qemu_aarch64.rs")]
+#[cfg_attr(target_arch = "x86_64", path = "boards/qemu_x86_64.rs")]
+#[cfg_attr(target_arch = "loongarch64", path = "boards/qemu_loongarch64.rs")]
 mod board;
 
 #[macro_use]
@@ -37,9 +41,13 @@ mod trap;
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
 
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::*;
 use sync::UPIntrFreeCell;
 
+/// 主核是否已经完成内核初始化
+static BOOT_DONE: AtomicBool = AtomicBool::new(false);
+
 lazy_static! {
     pub static ref DEV_NON_BLOCKING_ACCESS: UPIntrFreeCell<bool> =
         unsafe { UPIntrFreeCell::new(false) };
@@ -50,65 +58,76 @@ struct ArchInterfaceImpl;
 #[crate_interface::impl_interface]
 impl ArchInterface for ArchInterfaceImpl {
 	fn init_logging() {
-		mm::init();
 		UART.init();
         let str = include_str!("logo.txt");
         println!("{}", str);
 		stdout_init(Some("info"));
 		info!("hello, rCore turtorial");
-		mm::init_kernel_space();
 		info!("finish init logging");
     }
 	fn kernel_interrupt(ctx: &mut Context, trap_type: TrapType)
 	{
-		// println!("[kernel] kernel interrupt");
-		// println!("KERN: init trap");
-		// trap::init();
-		// trap::enable_timer_interrupt();
-		// timer::set_next_trigger();
-		match trap_type {
-            TrapType::StorePageFault(addr)
-            | TrapType::InstructionPageFault(addr)
-            | TrapType::LoadPageFault(addr) => {
-                
-            }
-            TrapType::IllegalInstruction(addr) => {
-                
-            }
-            TrapType::SupervisorExternal => {
-            }
-            _ => {
-            }
-        };
+		trap::kernel_interrupt(ctx, trap_type);
 	}
 	fn add_memory_region(start: usize, end: usize)
 	{
-
+		mm::add_frame_region(start, end);
 	}
 	fn main(hartid: usize)
 	{
+		// 从核只在主核初始化完成之后才会被启动
+		if BOOT_DONE.load(Ordering::Acquire) {
+			println!("[kernel] hart {} start", hartid);
+			mm::init();
+			trap::init();
+			task::run_tasks();
+			panic!("Unreachable in secondary main!");
+		}
 		println!("[kernel] main start");
-		println!("KERN: init gpu");
-		let _gpu = GPU_DEVICE.clone();
-		println!("KERN: init keyboard");
-		let _keyboard = KEYBOARD_DEVICE.clone();
-		println!("KERN: init mouse");
-		let _mouse = MOUSE_DEVICE.clone();
+		mm::init();
+		#[cfg(not(target_arch = "loongarch64"))]
+		mm::remap_test();
+		#[cfg(target_arch = "riscv64")]
+		{
+			use crate::drivers::probe::{virtio_device, VirtioKind};
+			if virtio_device(VirtioKind::Gpu).is_some() {
+				println!("KERN: init gpu");
+				let _gpu = GPU_DEVICE.clone();
+			}
+			if virtio_device(VirtioKind::Keyboard).is_some() {
+				println!("KERN: init keyboard");
+				let _keyboard = KEYBOARD_DEVICE.clone();
+			}
+			if virtio_device(VirtioKind::Mouse).is_some() {
+				println!("KERN: init mouse");
+				let _mouse = MOUSE_DEVICE.clone();
+			}
+		}
 		trap::init();
 		// trap::enable_timer_interrupt();
 		// timer::set_next_trigger();
 		// board::device_init();
 		fs::list_apps();
 		info!("finish list apps");
+		mm::swap::init();
 		task::add_initproc();
 		info!("finish add init proc");
-		*DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
+		// 只有 riscv 上的块设备接了中断
+		#[cfg(target_arch = "riscv64")]
+		{
+			*DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
+		}
+		BOOT_DONE.store(true, Ordering::Release);
+		let started = arch::start_secondary_cpus(arch::MAX_CPU_NUM - 1);
+		info!("{} secondary cpu(s) started", started);
 		task::run_tasks();
 		panic!("Unreachable in rust_main!");
 	}
 	fn frame_alloc_persist() -> PhysPage
 	{
-		mm::frame_alloc().unwrap()
+		let ppn = mm::frame_alloc().unwrap();
+		ppn.get_bytes_array().fill(0);
+		ppn
 	}
 	fn frame_unalloc(ppn: PhysPage)
 	{
@@ -121,6 +140,6 @@ impl ArchInterface for ArchInterfaceImpl {
 	}
 	fn try_to_add_device(fdtNode: &FdtNode)
 	{
-		// println!("[kernel] try to add device");
+		drivers::probe::probe_fdt_node(fdtNode);
 	}
 }
\ No newline at end of file
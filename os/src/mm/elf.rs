@@ -0,0 +1,313 @@
+//! 装载 ELF 之后还要用到的程序头信息，以及按 Linux 的约定构造初始的用户栈
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use arch::USER_ADDR_MAX;
+use core::mem::size_of;
+use xmas_elf::header;
+use xmas_elf::program::{ProgramHeader, ProgramHeader64, Type};
+use xmas_elf::ElfFile;
+
+use super::MemorySet;
+use crate::config::{PAGE_SIZE, USER_STACK_SIZE};
+
+/// xmas_elf 没有单独列出 PT_GNU_STACK
+const PT_GNU_STACK: u32 = 0x6474_e551;
+
+// 辅助向量的类型
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_BASE: usize = 7;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// PT_TLS 段，主线程的 TLS 块按它初始化
+pub struct TlsTemplate {
+    /// 需要从文件里复制的部分，剩下的填 0
+    init: Vec<u8>,
+    mem_size: usize,
+    align: usize,
+}
+
+/// 初始化用户栈和上下文要用到的信息，地址都已经加上了装载偏移
+pub struct ElfInfo {
+    /// 开始执行的地址，有解释器的话是解释器的入口
+    pub entry: usize,
+    /// 程序自己的入口
+    pub program_entry: usize,
+    /// 程序头表在用户地址空间里的地址
+    pub phdr: usize,
+    pub phent: usize,
+    pub phnum: usize,
+    /// 解释器的装载偏移，没有解释器时是 0
+    pub interp_base: usize,
+    pub tls: Option<TlsTemplate>,
+    /// PT_GNU_STACK 要求用户栈可执行
+    pub exec_stack: bool,
+}
+
+impl ElfInfo {
+    pub(super) fn new(elf: &ElfFile, bias: usize) -> Self {
+        let mut info = Self {
+            entry: elf.header.pt2.entry_point() as usize + bias,
+            program_entry: elf.header.pt2.entry_point() as usize + bias,
+            phdr: 0,
+            phent: elf.header.pt2.ph_entry_size() as usize,
+            phnum: elf.header.pt2.ph_count() as usize,
+            interp_base: 0,
+            tls: None,
+            exec_stack: false,
+        };
+        let ph_offset = elf.header.pt2.ph_offset();
+        for ph in elf.program_iter() {
+            match ph.get_type() {
+                Ok(Type::Phdr) => info.phdr = ph.virtual_addr() as usize + bias,
+                // 没有 PT_PHDR 的话找包含程序头表的那个段
+                Ok(Type::Load)
+                    if info.phdr == 0
+                        && ph.offset() <= ph_offset
+                        && ph_offset < ph.offset() + ph.file_size() =>
+                {
+                    info.phdr = (ph.virtual_addr() + ph_offset - ph.offset()) as usize + bias;
+                }
+                Ok(Type::Tls) => {
+                    // elf_supported 已经检查过段在文件范围内
+                    let start = ph.offset() as usize;
+                    info.tls = Some(TlsTemplate {
+                        init: elf.input[start..start + ph.file_size() as usize].to_vec(),
+                        mem_size: ph.mem_size() as usize,
+                        align: (ph.align() as usize).max(1),
+                    });
+                }
+                Ok(Type::OsSpecific(PT_GNU_STACK)) => info.exec_stack = ph.flags().is_execute(),
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+/// 当前架构的 e_machine
+#[cfg(target_arch = "riscv64")]
+const EM_CURRENT: u16 = 243;
+#[cfg(target_arch = "aarch64")]
+const EM_CURRENT: u16 = 183;
+#[cfg(target_arch = "x86_64")]
+const EM_CURRENT: u16 = 62;
+#[cfg(target_arch = "loongarch64")]
+const EM_CURRENT: u16 = 258;
+
+/// 是不是这个内核能装载的 ELF：64 位、小端、当前架构的可执行文件或者 ET_DYN，
+/// 并且程序头表和装载时用到的段都在文件范围内
+pub fn elf_supported(elf_data: &[u8]) -> bool {
+    let elf = match ElfFile::new(elf_data) {
+        Ok(elf) => elf,
+        Err(_) => return false,
+    };
+    let machine = u16::from_le_bytes([elf_data[18], elf_data[19]]);
+    let supported = matches!(elf.header.pt1.class(), header::Class::SixtyFour)
+        && matches!(elf.header.pt1.data(), header::Data::LittleEndian)
+        && machine == EM_CURRENT
+        && matches!(
+            elf.header.pt2.type_().as_type(),
+            header::Type::Executable | header::Type::SharedObject
+        );
+    if !supported {
+        return false;
+    }
+    // xmas_elf 取程序头和段内容时都不检查范围，超出文件就会 panic
+    let pt2 = &elf.header.pt2;
+    let phdrs_valid = usize::from(pt2.ph_entry_size()) == size_of::<ProgramHeader64>()
+        && in_file(
+            elf_data,
+            pt2.ph_offset(),
+            u64::from(pt2.ph_entry_size()) * u64::from(pt2.ph_count()),
+        );
+    phdrs_valid
+        && pt2.entry_point() <= USER_ADDR_MAX as u64
+        && elf
+            .program_iter()
+            .any(|ph| matches!(ph.get_type(), Ok(Type::Load)))
+        && elf.program_iter().all(|ph| match ph.get_type() {
+            Ok(Type::Load) | Ok(Type::Tls) | Ok(Type::Interp) => segment_valid(elf_data, &ph),
+            _ => true,
+        })
+}
+
+/// 文件里从 `offset` 开始的 `size` 个字节没有超出文件
+fn in_file(elf_data: &[u8], offset: u64, size: u64) -> bool {
+    offset
+        .checked_add(size)
+        .map_or(false, |end| end <= elf_data.len() as u64)
+}
+
+/// 文件里的部分在文件范围内、不比内存里的大，内存里的范围在用户地址空间里，
+/// 这样装载时加上偏移、按页对齐都不会溢出
+fn segment_valid(elf_data: &[u8], ph: &ProgramHeader) -> bool {
+    in_file(elf_data, ph.offset(), ph.file_size())
+        && ph.file_size() <= ph.mem_size()
+        && ph
+            .virtual_addr()
+            .checked_add(ph.mem_size())
+            .map_or(false, |end| end <= USER_ADDR_MAX as u64)
+}
+
+/// 位置无关的程序和动态链接器都是 ET_DYN，装载时要加上偏移
+pub(super) fn is_dyn(elf: &ElfFile) -> bool {
+    matches!(elf.header.pt2.type_().as_type(), header::Type::SharedObject)
+}
+
+/// PT_LOAD 段覆盖的地址范围，起点向下对齐到页
+pub(super) fn load_range(elf: &ElfFile) -> (usize, usize) {
+    let (start, end) = elf
+        .program_iter()
+        .filter(|ph| matches!(ph.get_type(), Ok(Type::Load)))
+        .map(|ph| (ph.virtual_addr(), ph.virtual_addr() + ph.mem_size()))
+        .fold((u64::MAX, 0), |(start, end), (l, r)| {
+            (start.min(l), end.max(r))
+        });
+    ((start.min(end) as usize) & !(PAGE_SIZE - 1), end as usize)
+}
+
+/// PT_INTERP 指定的解释器路径，静态链接的程序返回 None
+pub fn elf_interpreter(elf_data: &[u8]) -> Option<String> {
+    let elf = ElfFile::new(elf_data).ok()?;
+    let ph = elf
+        .program_iter()
+        .find(|ph| matches!(ph.get_type(), Ok(Type::Interp)))?;
+    let start = ph.offset() as usize;
+    let path = elf_data.get(start..start + ph.file_size() as usize)?;
+    // 结尾带着 \0
+    let path = path.split(|&ch| ch == 0).next().unwrap_or(path);
+    core::str::from_utf8(path).ok().map(String::from)
+}
+
+/// 构造好的初始用户栈
+pub struct InitialStack {
+    /// 指向 argc，16 字节对齐
+    pub sp: usize,
+    /// argv 数组的地址
+    pub argv: usize,
+    /// 主线程的 TLS 指针，程序没有 PT_TLS 段时是 None
+    pub tls: Option<usize>,
+}
+
+/// 从栈顶往下依次压入数据
+struct StackWriter<'a> {
+    memory_set: &'a mut MemorySet,
+    sp: usize,
+}
+
+impl StackWriter<'_> {
+    /// 压入 `data`，起始地址按 `align` 对齐，返回这个地址
+    fn push(&mut self, data: &[u8], align: usize) -> Option<usize> {
+        self.sp = self.sp.checked_sub(data.len())? & !(align - 1);
+        self.memory_set
+            .copy_to_user(self.sp, data)
+            .then_some(self.sp)
+    }
+
+    fn push_strings(&mut self, strings: &[String]) -> Option<Vec<usize>> {
+        let mut ptrs = Vec::new();
+        for string in strings {
+            self.push(&[0], 1)?;
+            ptrs.push(self.push(string.as_bytes(), 1)?);
+        }
+        ptrs.push(0);
+        Some(ptrs)
+    }
+
+    /// 按各个架构的 TLS 布局放好主线程的 TLS 块，返回线程指针
+    fn push_tls(&mut self, tls: &TlsTemplate) -> Option<usize> {
+        // 比整个用户栈还大的肯定放不下，不用先按它的大小分配内存
+        if tls.mem_size > USER_STACK_SIZE || tls.align > USER_STACK_SIZE {
+            return None;
+        }
+        let align = tls.align.max(size_of::<usize>());
+        let mut block = tls.init.clone();
+        block.resize(tls.mem_size, 0);
+        if cfg!(target_arch = "x86_64") {
+            // TLS 块在线程指针下面，线程指针指向的第一个字是它自己
+            block.resize((tls.mem_size + align - 1) & !(align - 1), 0);
+            let tp = self.push(&[0; size_of::<usize>()], align)?;
+            self.push(&block, align)?;
+            self.memory_set
+                .copy_to_user(tp, &tp.to_ne_bytes())
+                .then_some(tp)
+        } else if cfg!(target_arch = "aarch64") {
+            // 线程指针指向 16 字节的 TCB，TLS 块跟在后面
+            let mut data = vec![0; (16 + align - 1) & !(align - 1)];
+            data.extend_from_slice(&block);
+            self.push(&data, align)
+        } else {
+            // riscv64 和 LoongArch 的线程指针直接指向 TLS 块
+            self.push(&block, align)
+        }
+    }
+}
+
+/// 从栈顶 `top` 往下构造 Linux 约定的初始用户栈，栈放不下时返回 None
+///
+/// 从高到低依次是 TLS 块、参数和环境变量字符串、AT_RANDOM 的 16 个字节，
+/// 然后是辅助向量、envp、argv 和 argc
+pub fn init_user_stack(
+    memory_set: &mut MemorySet,
+    top: usize,
+    info: &ElfInfo,
+    args: &[String],
+    envs: &[String],
+) -> Option<InitialStack> {
+    let mut stack = StackWriter {
+        memory_set,
+        sp: top,
+    };
+    let tls = match &info.tls {
+        Some(tls) => Some(stack.push_tls(tls)?),
+        None => None,
+    };
+    let argv = stack.push_strings(args)?;
+    let envp = stack.push_strings(envs)?;
+    let random = stack.push(&random_bytes(), 16)?;
+    let auxv = [
+        (AT_PHDR, info.phdr),
+        (AT_PHENT, info.phent),
+        (AT_PHNUM, info.phnum),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_BASE, info.interp_base),
+        (AT_ENTRY, info.program_entry),
+        (AT_RANDOM, random),
+        (AT_NULL, 0),
+    ];
+    let mut words = vec![args.len()];
+    words.extend_from_slice(&argv);
+    words.extend_from_slice(&envp);
+    for (key, value) in auxv {
+        words.push(key);
+        words.push(value);
+    }
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+    let sp = stack.push(&bytes, 16)?;
+    Some(InitialStack {
+        sp,
+        argv: sp + size_of::<usize>(),
+        tls,
+    })
+}
+
+/// 内核没有熵源，用时间打散一下，只给用户态的栈保护之类的用途当种子
+fn random_bytes() -> [u8; 16] {
+    let mut state = crate::timer::get_time() as u64 | 1;
+    let mut bytes = [0; 16];
+    for chunk in bytes.chunks_mut(8) {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        chunk.copy_from_slice(&state.to_ne_bytes());
+    }
+    bytes
+}
@@ -1,8 +1,8 @@
 use super::{PhysAddr, PhysPage};
-use crate::config::MEMORY_END;
 use crate::sync::UPIntrFreeCell;
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
-use log::{info,error};
+use log::info;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
 
@@ -37,77 +37,143 @@ trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPage>;
     fn alloc_more(&mut self, pages: usize) -> Option<Vec<PhysPage>>;
+    /// 分配 `pages` 个连续的页，起始页号按 `pages` 对齐，`pages` 是 2 的幂
+    fn alloc_aligned(&mut self, pages: usize) -> Option<PhysPage>;
     fn dealloc(&mut self, ppn: PhysPage);
+    /// 还能分配的页数
+    fn free_count(&self) -> usize;
 }
 
-pub struct StackFrameAllocator {
-    current: usize,
-    end: usize,
-    recycled: Vec<usize>,
+/// 最大的块有 2^(MAX_ORDER - 1) 页，也就是 1G
+const MAX_ORDER: usize = 19;
+
+/// 伙伴系统页帧分配器，所有的块都按自己的大小对齐
+pub struct BuddyFrameAllocator {
+    /// 第 k 项是所有空闲的 2^k 页的块的起始页号
+    free_lists: Vec<BTreeSet<usize>>,
+    /// 加入分配器的总页数
+    total: usize,
+    /// 空闲的总页数
+    free: usize,
 }
 
-impl StackFrameAllocator {
-    pub fn init(&mut self, l: PhysPage, r: PhysPage) {
-        self.current = usize::from(l);
-        self.end = usize::from(r);
-        // println!("last {} Physical Frames.", self.end - self.current);
+impl BuddyFrameAllocator {
+    /// 加入一段可用的物理页 [l, r)
+    pub fn add_region(&mut self, l: PhysPage, r: PhysPage) {
+        let (l, r) = (usize::from(l), usize::from(r));
+        if l >= r {
+            return;
+        }
+        info!("frame allocator: add ppn {:#x} - {:#x}", l, r);
+        self.total += r - l;
+        self.free_range(l, r);
+    }
+    /// 把 [l, r) 拆成尽量大的对齐的块放回去
+    fn free_range(&mut self, mut l: usize, r: usize) {
+        while l < r {
+            let order = (0..MAX_ORDER)
+                .rev()
+                .find(|&k| l % (1 << k) == 0 && l + (1 << k) <= r)
+                .unwrap();
+            self.free_block(l, order);
+            l += 1 << order;
+        }
+    }
+    /// 分配一个 2^order 页的块，从更大的块拆出来时多余的部分逐级放回去
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let k = (order..MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let start = self.free_lists[k].pop_first().unwrap();
+        for i in order..k {
+            self.free_lists[i].insert(start + (1 << i));
+        }
+        self.free -= 1 << order;
+        Some(start)
+    }
+    /// 回收一个 2^order 页的块，伙伴也空闲的话合并成更大的块
+    fn free_block(&mut self, mut start: usize, mut order: usize) {
+        self.free += 1 << order;
+        while order + 1 < MAX_ORDER && self.free_lists[order].remove(&(start ^ (1 << order))) {
+            start &= !(1 << order);
+            order += 1;
+        }
+        self.free_lists[order].insert(start);
+    }
+    /// 每一阶空闲块的个数，和 /proc/buddyinfo 一样
+    fn free_blocks(&self) -> Vec<usize> {
+        self.free_lists.iter().map(|list| list.len()).collect()
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
-            end: 0,
-            recycled: Vec::new(),
+            free_lists: (0..MAX_ORDER).map(|_| BTreeSet::new()).collect(),
+            total: 0,
+            free: 0,
         }
     }
     fn alloc(&mut self) -> Option<PhysPage> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some((ppn & (arch::VIRT_ADDR_START_MASK >> 12)).into())
-        } else if self.current == self.end {
-            None
-        } else {
-            self.current += 1;
-            Some(((self.current - 1) & (arch::VIRT_ADDR_START_MASK >> 12)).into())
-        }
+        self.alloc_block(0).map(PhysPage::from)
     }
     fn alloc_more(&mut self, pages: usize) -> Option<Vec<PhysPage>> {
-        if self.current + pages >= self.end {
-            None
-        } else {
-            self.current += pages;
-            let arr: Vec<usize> = (1..pages + 1).collect();
-            let v = arr.iter().map(|x| (self.current - x).into()).collect();
-            Some(v)
-        }
+        let order = pages.next_power_of_two().trailing_zeros() as usize;
+        let start = self.alloc_block(order)?;
+        self.free_range(start + pages, start + (1 << order));
+        // 和以前一样从高到低排列，最后一个是最低的一页
+        Some((0..pages).rev().map(|i| (start + i).into()).collect())
+    }
+    fn alloc_aligned(&mut self, pages: usize) -> Option<PhysPage> {
+        assert!(pages.is_power_of_two());
+        self.alloc_block(pages.trailing_zeros() as usize)
+            .map(PhysPage::from)
     }
     fn dealloc(&mut self, ppn: PhysPage) {
-		error!("dealloc ppn {}",ppn);
         let ppn = usize::from(ppn) & ((arch::VIRT_ADDR_START_MASK) >> 12);
         // validity check
-        if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
+        if (0..MAX_ORDER).any(|k| self.free_lists[k].contains(&(ppn & !((1 << k) - 1)))) {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
-        // recycle
-        self.recycled.push(ppn);
+        self.free_block(ppn, 0);
+    }
+    fn free_count(&self) -> usize {
+        self.free
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     pub static ref FRAME_ALLOCATOR: UPIntrFreeCell<FrameAllocatorImpl> =
         unsafe { UPIntrFreeCell::new(FrameAllocatorImpl::new()) };
+    /// 所有物理内存区域合起来覆盖的物理地址范围 [start, end)
+    static ref MEMORY_SPAN: UPIntrFreeCell<(usize, usize)> =
+        unsafe { UPIntrFreeCell::new((usize::MAX, 0)) };
 }
 
-pub fn init_frame_allocator() {
+/// 把 HAL 报告的物理内存 [start, end) 交给页帧分配器，内核镜像所在的部分会被跳过
+pub fn add_frame_region(start: usize, end: usize) {
     extern "C" {
+        fn _skernel();
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from((ekernel as usize) & arch::VIRT_ADDR_START_MASK).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
-    );
+    let start = start & arch::VIRT_ADDR_START_MASK;
+    let end = end & arch::VIRT_ADDR_START_MASK;
+    let kernel_start = (_skernel as usize) & arch::VIRT_ADDR_START_MASK;
+    let kernel_end = (ekernel as usize) & arch::VIRT_ADDR_START_MASK;
+    {
+        let mut span = MEMORY_SPAN.exclusive_access();
+        *span = (span.0.min(start), span.1.max(end));
+    }
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    for (l, r) in [(start, end.min(kernel_start)), (start.max(kernel_end), end)] {
+        if l < r {
+            allocator.add_region(PhysAddr::from(l).ceil(), PhysAddr::from(r).floor());
+        }
+    }
+}
+
+/// 内核页表按这个范围建立线性映射
+pub fn memory_span() -> (usize, usize) {
+    *MEMORY_SPAN.exclusive_access()
 }
 
 pub fn frame_alloc() -> Option<PhysPage> {
@@ -118,6 +184,11 @@ pub fn frame_alloc() -> Option<PhysPage> {
 		/*here we need a page that will not auto free to satisify the hal level requirement*/
 }
 
+/// 分配一个清零的页帧，`FrameTracker` drop 时自动回收
+pub fn frame_alloc_tracker() -> Option<FrameTracker> {
+    frame_alloc().map(FrameTracker::new)
+}
+
 pub fn frame_alloc_more(num: usize) -> Option<Vec<FrameTracker>> {
     FRAME_ALLOCATOR
         .exclusive_access()
@@ -125,6 +196,59 @@ pub fn frame_alloc_more(num: usize) -> Option<Vec<FrameTracker>> {
         .map(|x| x.iter().map(|&t| FrameTracker::new(t)).collect())
 }
 
+/// 分配物理上连续、按 `pages` 页对齐的清零页帧，用来映射大页
+pub fn frame_alloc_aligned(pages: usize) -> Option<Vec<FrameTracker>> {
+    let start = FRAME_ALLOCATOR.exclusive_access().alloc_aligned(pages)?;
+    Some((0..pages).map(|i| FrameTracker::new(start + i)).collect())
+}
+
+pub fn frame_free_count() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free_count()
+}
+
+/// 页帧的使用情况，单位都是页
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    /// 每一阶空闲块的个数
+    pub free_blocks: Vec<usize>,
+}
+
+pub fn frame_stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.exclusive_access();
+    FrameStats {
+        total: allocator.total,
+        free: allocator.free_count(),
+        free_blocks: allocator.free_blocks(),
+    }
+}
+
+/// 内核堆的 slab 从这里拿页帧
+///
+/// 页帧分配器修改空闲链表时自己也会分配内存，这时当前核已经拿着锁，直接失败，让堆换别的办法；
+/// 锁在别的核手里的话等它放开
+pub struct HeapPageProvider;
+
+impl allocator::PageProvider for HeapPageProvider {
+    fn alloc_pages(&self, pages: usize) -> Option<usize> {
+        let ppn = FRAME_ALLOCATOR
+            .exclusive_access_unless_held()?
+            .alloc_aligned(pages)?;
+        Some(PhysAddr::from(ppn).get_mut_ptr::<u8>() as usize)
+    }
+    fn dealloc_pages(&self, addr: usize, pages: usize) -> bool {
+        let mut allocator = match FRAME_ALLOCATOR.exclusive_access_unless_held() {
+            Some(allocator) => allocator,
+            None => return false,
+        };
+        let start = PhysAddr::from(addr & arch::VIRT_ADDR_START_MASK).floor();
+        for i in 0..pages {
+            allocator.dealloc(start + i);
+        }
+        true
+    }
+}
+
 pub fn frame_dealloc(ppn: PhysPage) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
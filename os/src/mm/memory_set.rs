@@ -1,16 +1,24 @@
-use super::{frame_alloc, FrameTracker};
-use super::{PageTable, PTE ,MappingFlags};
-use super::{PhysAddr, PhysPage, VirtAddr, VirtPage};
+use super::elf::{elf_supported, is_dyn, load_range, ElfInfo};
+use super::frame_allocator::memory_span;
+use super::swap::{swap_alloc, swap_enabled, SwapSlot, SWAP_WATERMARK};
+use super::{frame_alloc_aligned, frame_alloc_tracker, frame_free_count, FrameTracker};
+use super::{MapPageSize, MappingFlags, PageTable};
+use super::{PhysPage, VirtAddr, VirtPage};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
+use crate::config::{ELF_DYN_BASE, MMAP_BASE, MMAP_TOP, MMIO, PAGE_SIZE, USER_HEAP_MAX};
+use crate::fs::cached_page;
 use crate::sync::UPIntrFreeCell;
+use crate::task::binfmt::ENOEXEC;
+use crate::task::swap_out_other;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::arch::asm;
+use arch::VIRT_ADDR_START;
+use core::ops::Deref;
+use easy_fs::Inode;
 use lazy_static::*;
-use riscv::register::satp;
 use log::{info,error};
+use xmas_elf::ElfFile;
 
 extern "C" {
     fn stext();
@@ -22,7 +30,6 @@ extern "C" {
     fn sbss_with_stack();
     fn _ebss();
     fn ekernel();
-    fn strampoline();
 }
 
 lazy_static! {
@@ -37,6 +44,18 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// clock 算法的指针，指向下一次开始扫描的候选页
+    clock_hand: usize,
+    /// 不需要读交换区的缺页次数
+    minor_faults: usize,
+    /// 需要从交换区读回的缺页次数
+    major_faults: usize,
+    /// 堆底，紧跟在 ELF 的最后一个段后面
+    heap_bottom: usize,
+    /// 堆顶，sbrk 之后不一定页对齐
+    brk: usize,
+    /// ELF 的 PT_GNU_STACK 要求用户栈可执行
+    exec_stack: bool,
 }
 
 impl MemorySet {
@@ -44,10 +63,16 @@ impl MemorySet {
         Self {
             page_table: PageTable::alloc(),
             areas: Vec::new(),
+            clock_hand: 0,
+            minor_faults: 0,
+            major_faults: 0,
+            heap_bottom: 0,
+            brk: 0,
+            exec_stack: false,
         }
     }
     pub fn token(&self) -> usize {
-        self.page_table.get_satp()
+        self.page_table.token()
     }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
@@ -61,6 +86,201 @@ impl MemorySet {
             None,
         );
     }
+    /// 第一次访问时才分配清零的页帧
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        self.push(
+            MapArea::new(start_va, end_va, MapType::Lazy, permission),
+            None,
+        );
+    }
+    /// mmap 创建的区域，`start_va` 和 `end_va` 都是页对齐的，页帧不够时返回 false
+    pub fn insert_mmap_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        backing: MmapBacking,
+    ) -> bool {
+        match backing {
+            MmapBacking::Anonymous => self.insert_lazy_area(start_va, end_va, permission),
+            MmapBacking::SharedAnonymous => {
+                let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+                map_area.shared = true;
+                self.reclaim(usize::from(end_va.ceil()) - usize::from(start_va.floor()));
+                if !map_area.map(&mut self.page_table) {
+                    return false;
+                }
+                self.areas.push(map_area);
+            }
+            MmapBacking::File {
+                inode,
+                offset,
+                len,
+                shared,
+            } => {
+                let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, permission);
+                map_area.shared = shared;
+                map_area.mapped_file = Some(MappedFile {
+                    inode,
+                    offset,
+                    len,
+                    start_va: start_va.into(),
+                });
+                self.push(map_area, None);
+            }
+        }
+        true
+    }
+    /// 在 mmap 的地址范围里找一段 `pages` 页的空闲地址
+    pub fn find_free_area(&self, pages: usize) -> Option<VirtAddr> {
+        let mut ranges: Vec<(VirtPage, VirtPage)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .collect();
+        ranges.sort();
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        for (l, r) in ranges {
+            if r <= start {
+                continue;
+            }
+            if start + pages <= l {
+                break;
+            }
+            start = r;
+        }
+        if start + pages <= VirtAddr::from(MMAP_TOP).floor() {
+            Some(start.into())
+        } else {
+            None
+        }
+    }
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+    /// 把堆顶移动到 `new_brk`，堆的页在第一次访问时才分配
+    ///
+    /// 不能低于堆底、超过堆的上限，也不能盖住 mmap 放在这里的区域，失败时返回 false
+    pub fn set_brk(&mut self, new_brk: usize) -> bool {
+        if new_brk < self.heap_bottom || new_brk > self.heap_bottom + USER_HEAP_MAX {
+            return false;
+        }
+        let old_end = VirtAddr::from(self.brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if new_end > old_end {
+            if !self.is_free(old_end, new_end) {
+                return false;
+            }
+            // 尽量接在原来的堆区域后面，不让区域越来越多
+            let heap_perm = MapPermission::R | MapPermission::W | MapPermission::U;
+            match self.areas.iter_mut().find(|area| {
+                area.vpn_range.get_end() == old_end
+                    && area.map_type == MapType::Lazy
+                    && area.map_perm == heap_perm
+                    && area.file.is_none()
+                    && area.mapped_file.is_none()
+                    && !area.shared
+            }) {
+                Some(area) => area.vpn_range = VPNRange::new(area.vpn_range.get_start(), new_end),
+                None => self.insert_lazy_area(old_end.into(), new_end.into(), heap_perm),
+            }
+        } else if new_end < old_end {
+            self.remove_range(new_end, old_end);
+        }
+        self.brk = new_brk;
+        true
+    }
+    /// `[start, end)` 里有没有已经映射的区域
+    pub fn is_free(&self, start: VirtPage, end: VirtPage) -> bool {
+        self.areas
+            .iter()
+            .all(|area| area.vpn_range.get_end() <= start || end <= area.vpn_range.get_start())
+    }
+    /// 把跨过 `start` 或 `end` 的区域拆开，之后每个区域要么完全在范围内，要么完全在范围外
+    fn split_at_range(&mut self, start: VirtPage, end: VirtPage) {
+        for at in [start, end] {
+            if let Some(idx) = self
+                .areas
+                .iter()
+                .position(|area| area.vpn_range.get_start() < at && at < area.vpn_range.get_end())
+            {
+                let tail = self.areas[idx].split_off(at);
+                self.areas.push(tail);
+            }
+        }
+    }
+    /// 解除 `[start, end)` 里的所有映射，区域只有一部分在范围内时会被拆开
+    pub fn remove_range(&mut self, start: VirtPage, end: VirtPage) {
+        self.split_at_range(start, end);
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            let inside = start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end;
+            if inside {
+                area.unmap(page_table);
+            }
+            !inside
+        });
+    }
+    /// 修改 `[start, end)` 的权限，范围里有没映射的页时返回 false
+    pub fn protect_range(
+        &mut self,
+        start: VirtPage,
+        end: VirtPage,
+        permission: MapPermission,
+    ) -> bool {
+        let mapped: usize = self
+            .areas
+            .iter()
+            .map(|area| {
+                let l = area.vpn_range.get_start().max(start);
+                let r = area.vpn_range.get_end().min(end);
+                if l < r {
+                    usize::from(r) - usize::from(l)
+                } else {
+                    0
+                }
+            })
+            .sum();
+        if mapped != usize::from(end) - usize::from(start) {
+            return false;
+        }
+        self.split_at_range(start, end);
+        for area in self.areas.iter_mut() {
+            if start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end {
+                area.set_permission(&self.page_table, permission);
+            }
+        }
+        true
+    }
+    /// `[start, end)` 里共享文件映射的页，解除映射之前用来写回文件
+    pub fn shared_file_pages(&self, start: VirtPage, end: VirtPage) -> Vec<FilePage> {
+        let mut pages = Vec::new();
+        for area in self.areas.iter() {
+            let file = match &area.mapped_file {
+                Some(file) if area.shared => file,
+                _ => continue,
+            };
+            for (&vpn, frame) in area.data_frames.range(start..end) {
+                let page_va: usize = VirtAddr::from(vpn).into();
+                let offset = page_va - file.start_va;
+                if offset >= file.len {
+                    continue;
+                }
+                pages.push(FilePage {
+                    inode: file.inode.clone(),
+                    offset: file.offset + offset,
+                    len: (file.len - offset).min(PAGE_SIZE),
+                    frame: frame.0.clone(),
+                });
+            }
+        }
+        pages
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPage) {
         if let Some((idx, area)) = self
             .areas
@@ -76,29 +296,25 @@ impl MemorySet {
     /// Assuming that there are no conflicts in the virtual address
     /// space.
     pub fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+        if !map_area.map(&mut self.page_table) {
+            panic!(
+                "out of memory when mapping {:?}",
+                map_area.vpn_range.get_start()
+            );
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
     }
-    /// Mention that trampoline is not collected by areas.
-    fn map_trampoline(&mut self) {
-        self.page_table.map(
-            PhysAddr::from((strampoline as usize)&arch::VIRT_ADDR_START_MASK).into(),
-			VirtAddr::from(TRAMPOLINE).into(),
-            MappingFlags::R | MappingFlags::X,
-			3
-        );
-    }
-    /// Without kernel stacks.
+    /// 内核地址空间，不含内核栈
+    ///
+    /// 代码段只读可执行，只读数据段只读，其余的线性映射可读写、不可执行
     pub fn new_kernel() -> Self {
         let mut memory_set = Self::new_bare();
-		/* we have already init a page table for this*/
-        // map trampoline
-        memory_set.map_trampoline();
-
-        // // map kernel sections
+        if !arch::KERNEL_PAGED {
+            return memory_set;
+        }
         println!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         println!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
         println!(".data [{:#x}, {:#x})", _sdata as usize, _edata as usize);
@@ -106,145 +322,324 @@ impl MemorySet {
             ".bss [{:#x}, {:#x})",
             sbss_with_stack as usize, _ebss as usize
         );
-        println!("mapping .text section");
-        
-		// memory_set.push(
-        //     MapArea::new(
-        //         (stext as usize).into(),
-        //         (etext as usize).into(),
-        //         MapType::Identical,
-        //         MapPermission::R | MapPermission::X,
-        //     ),
-        //     None,
-        // );
-        // println!("mapping .rodata section");
-        // memory_set.push(
-        //     MapArea::new(
-        //         (srodata as usize).into(),
-        //         (erodata as usize).into(),
-        //         MapType::Identical,
-        //         MapPermission::R,
-        //     ),
-        //     None,
-        // );
-        // println!("mapping .data section");
-        // memory_set.push(
-        //     MapArea::new(
-        //         (_sdata as usize).into(),
-        //         (_edata as usize).into(),
-        //         MapType::Identical,
-        //         MapPermission::R | MapPermission::W,
-        //     ),
-        //     None,
-        // );
-        // println!("mapping .bss section");
-        // memory_set.push(
-        //     MapArea::new(
-        //         (sbss_with_stack as usize).into(),
-        //         (_ebss as usize).into(),
-        //         MapType::Identical,
-        //         MapPermission::R | MapPermission::W,
-        //     ),
-        //     None,
-        // );
-        // println!("mapping physical memory");
-        // memory_set.push(
-        //     MapArea::new(
-        //         (ekernel as usize).into(),
-        //         MEMORY_END.into(),
-        //         MapType::Identical,
-        //         MapPermission::R | MapPermission::W,
-        //     ),
-        //     None,
-        // );
-        // println!("mapping memory-mapped registers");
-        // for pair in MMIO {
-        //     memory_set.push(
-        //         MapArea::new(
-        //             (*pair).0.into(),
-        //             ((*pair).0 + (*pair).1).into(),
-        //             MapType::Identical,
-        //             MapPermission::R | MapPermission::W,
-        //         ),
-        //         None,
-        //     );
-        // }
+        // 按 1G 对齐，覆盖启动页表里物理内存所在的大页
+        let huge = MapPageSize::Page1G.pages() * PAGE_SIZE;
+        let (mem_start, mem_end) = memory_span();
+        let mem_start = mem_start / huge * huge;
+        let mem_end = (mem_end + huge - 1) / huge * huge;
+        let kernel_end = (ekernel as usize + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let rw = MapPermission::R | MapPermission::W;
+        println!("mapping kernel sections and physical memory");
+        for (start, end, permission) in [
+            (mem_start | VIRT_ADDR_START, stext as usize, rw),
+            (
+                stext as usize,
+                etext as usize,
+                MapPermission::R | MapPermission::X,
+            ),
+            (srodata as usize, erodata as usize, MapPermission::R),
+            // .data、.bss 和每个核的数据
+            (erodata as usize, kernel_end, rw),
+            (kernel_end, mem_end | VIRT_ADDR_START, rw),
+        ] {
+            if start < end {
+                memory_set.push(
+                    MapArea::new(start.into(), end.into(), MapType::Identical, permission),
+                    None,
+                );
+            }
+        }
+        println!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            // 和物理内存重叠的部分上面已经映射过了
+            if start < mem_end && start + len > mem_start {
+                continue;
+            }
+            memory_set.push(
+                MapArea::new(
+                    (start | VIRT_ADDR_START).into(),
+                    ((start + len) | VIRT_ADDR_START).into(),
+                    MapType::Identical,
+                    rw,
+                ),
+                None,
+            );
+        }
+        memory_set.page_table.share_kernel();
         memory_set
     }
-    /// Include sections in elf and trampoline,
-    /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Include sections in elf,
+    /// also returns user_sp_base and what the initial user stack needs.
+    ///
+    /// 段的内容在缺页时才从 `elf_data` 里复制，所以地址空间会一直持有它。
+    /// 动态链接的程序还要传入 PT_INTERP 指定的解释器。
+    /// 两者都会用 [elf_supported] 检查，装载不了时返回 ENOEXEC
+    ///
+    /// [elf_supported]: super::elf_supported
+    pub fn from_elf(
+        elf_data: Arc<Vec<u8>>,
+        interp_data: Option<Arc<Vec<u8>>>,
+    ) -> Result<(Self, usize, ElfInfo), isize> {
         let mut memory_set = Self::new_bare();
-        // map trampoline
-        memory_set.map_trampoline();
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPage::from_addr(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+        if !elf_supported(&elf_data) {
+            return Err(ENOEXEC);
+        }
+        let elf = xmas_elf::ElfFile::new(&elf_data).map_err(|_| ENOEXEC)?;
+        // 位置无关的程序装载到固定的基址，本来就在基址之上的不用挪
+        let bias = if is_dyn(&elf) {
+            ELF_DYN_BASE.saturating_sub(load_range(&elf).0)
+        } else {
+            0
+        };
+        let max_end_vpn = memory_set.map_elf(&elf, &elf_data, bias);
+        let mut info = ElfInfo::new(&elf, bias);
+        if let Some(interp_data) = interp_data {
+            if !elf_supported(&interp_data) {
+                return Err(ENOEXEC);
             }
+            let interp = xmas_elf::ElfFile::new(&interp_data).map_err(|_| ENOEXEC)?;
+            // 解释器放在 mmap 的地址范围里，不占用程序的堆
+            let (start, end) = load_range(&interp);
+            let pages = usize::from(VirtAddr::from(end - start).ceil());
+            let base: usize = memory_set.find_free_area(pages).ok_or(ENOEXEC)?.into();
+            let interp_bias = base - start;
+            memory_set.map_elf(&interp, &interp_data, interp_bias);
+            info.entry = interp.header.pt2.entry_point() as usize + interp_bias;
+            info.interp_base = interp_bias;
         }
+        memory_set.exec_stack = info.exec_stack;
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_base: usize = max_end_va.into();
-        user_stack_base += PAGE_SIZE;
-        (
-            memory_set,
-            user_stack_base,
-            elf.header.pt2.entry_point() as usize,
-        )
-    }
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+        memory_set.heap_bottom = max_end_va.into();
+        memory_set.brk = memory_set.heap_bottom;
+        // 给堆留出空间，再空一页隔开用户栈
+        let user_stack_base = memory_set.heap_bottom + USER_HEAP_MAX + PAGE_SIZE;
+        Ok((memory_set, user_stack_base, info))
+    }
+    /// 把 PT_LOAD 段加上 `bias` 映射进来，返回最后一个段结束的页
+    fn map_elf(&mut self, elf: &ElfFile, elf_data: &Arc<Vec<u8>>, bias: usize) -> VirtPage {
+        let mut max_end_vpn = VirtPage::from_addr(0);
+        for ph in elf.program_iter() {
+            if !matches!(ph.get_type(), Ok(xmas_elf::program::Type::Load)) {
+                continue;
+            }
+            let start_va: VirtAddr = (ph.virtual_addr() as usize + bias).into();
+            let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize + bias).into();
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, map_perm);
+            map_area.file = Some(FileBacking {
+                data: elf_data.clone(),
+                offset: ph.offset() as usize,
+                len: ph.file_size() as usize,
+                start_va: start_va.into(),
+            });
+            max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+            self.push(map_area, None);
+        }
+        max_end_vpn
+    }
+    /// 用户栈的权限
+    pub fn stack_permission(&self) -> MapPermission {
+        let perm = MapPermission::R | MapPermission::W | MapPermission::U;
+        if self.exec_stack {
+            perm | MapPermission::X
+        } else {
+            perm
+        }
+    }
+    /// 内核往这个地址空间里写数据，不要求是当前的地址空间，装载程序时初始化用户栈用
+    pub fn copy_to_user(&mut self, va: usize, data: &[u8]) -> bool {
+        let end = va + data.len();
+        if self
+            .prepare_user_access(va.into(), end.into(), MapPermission::W)
+            .is_err()
+        {
+            return false;
+        }
+        let (mut va, mut data) = (va, data);
+        while !data.is_empty() {
+            let offset = va % PAGE_SIZE;
+            let len = data.len().min(PAGE_SIZE - offset);
+            let ppn = self.translate(VirtAddr::from(va).floor()).unwrap();
+            ppn.get_bytes_array()[offset..offset + len].copy_from_slice(&data[..len]);
+            data = &data[len..];
+            va += len;
+        }
+        true
+    }
+    /// fork 时复制地址空间，Framed 的页和父进程共享，第一次写的时候才复制
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
-        // map trampoline
-        memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
+        memory_set.exec_stack = user_space.exec_stack;
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().to_ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().to_ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            let mut new_area = MapArea::from_another(area);
+            if matches!(area.map_type, MapType::Framed | MapType::Lazy) {
+                new_area.share_frames(area, &user_space.page_table, &memory_set.page_table);
+                memory_set.areas.push(new_area);
+            } else {
+                memory_set.push(new_area, None);
             }
         }
         memory_set
     }
     pub fn activate(&self) {
-        let satp = self.page_table.get_satp();
-        unsafe {
-            satp::write(satp);
-            asm!("sfence.vma");
+        self.page_table.change();
+    }
+    /// 内核替用户访问 [start, end) 之前调用，出错的情况和 [Self::handle_page_fault] 一样。
+    /// 内核通过物理地址访问用户内存，不会触发缺页，
+    /// 所以要提前映射按需分配和换出的页，写之前还要处理写时复制
+    pub fn prepare_user_access(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        access: MapPermission,
+    ) -> Result<(), FaultError> {
+        let (start, end) = (start.floor(), end.ceil());
+        // TrapContext 这类内核用的页不带 U
+        let perm = access | MapPermission::U;
+        let mut vpn = start;
+        while vpn < end {
+            match self
+                .areas
+                .iter()
+                .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+            {
+                Some(area) if area.map_perm.contains(perm) => vpn = area.vpn_range.get_end(),
+                _ => return Err(FaultError::Invalid),
+            }
+        }
+        // 一次腾出足够的页帧，避免处理后面的页时把前面刚准备好的页换出去
+        self.reclaim(usize::from(end) - usize::from(start));
+        let mut vpn = start;
+        while vpn < end {
+            // 只读的话已经映射的页不用再处理
+            if access.contains(MapPermission::W) || self.translate(vpn).is_none() {
+                self.handle_page_fault(vpn.into(), access)?;
+            }
+            vpn.step();
         }
+        Ok(())
     }
-    pub fn translate(&self, vpn: VirtPage) -> Option<PTE> {
-        self.page_table.translate(vpn)
+    /// `[start, end)` 里已经映射的页帧，内核拿着它们的时候这些页不会被换出，
+    /// 被 munmap 或者进程退出时也要等内核用完才释放
+    pub fn pin_frames(&self, start: VirtAddr, end: VirtAddr) -> Vec<Arc<FrameTracker>> {
+        let (start, end) = (start.floor(), end.ceil());
+        self.areas
+            .iter()
+            .flat_map(|area| {
+                area.data_frames
+                    .range(start..end)
+                    .map(|(_, frame)| frame.0.clone())
+            })
+            .collect()
+    }
+    /// 处理落在本地址空间里的缺页异常
+    pub fn handle_page_fault(
+        &mut self,
+        vaddr: VirtAddr,
+        access: MapPermission,
+    ) -> Result<(), FaultError> {
+        let vpn = vaddr.floor();
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+        {
+            Some(area) => area,
+            None => return Err(FaultError::Invalid),
+        };
+        if !area.map_perm.contains(access) {
+            return Err(FaultError::Invalid);
+        }
+        // 被换出到交换区的页
+        if area.swapped.contains_key(&vpn) {
+            self.major_faults += 1;
+            return area
+                .swap_in(&self.page_table, vpn)
+                .then_some(())
+                .ok_or(FaultError::Invalid);
+        }
+        // 按需分配的页第一次被访问
+        if area.map_type == MapType::Lazy && !area.data_frames.contains_key(&vpn) {
+            // 为了写而分配的页直接标成脏页，内核通过物理地址写的时候硬件不会设置脏位
+            let dirty = access.contains(MapPermission::W);
+            // 所在的 2M 整块都还没有用过的话直接分配一个大页
+            let huge_start = VirtPage::from(usize::from(vpn) & !(MapPageSize::Page2m.pages() - 1));
+            if area.mapped_file.is_some() {
+                area.map_file_page(&self.page_table, vpn, dirty)?;
+            } else if area.map_huge(&self.page_table, huge_start, dirty).is_none()
+                && !area.map_one(&mut self.page_table, vpn, dirty)
+            {
+                return Err(FaultError::Invalid);
+            }
+            self.minor_faults += 1;
+            return Ok(());
+        }
+        // 写一个和其他地址空间共享的页
+        if access.contains(MapPermission::W) && area.data_frames.contains_key(&vpn) {
+            self.minor_faults += 1;
+            return area
+                .copy_on_write(&self.page_table, vpn)
+                .then_some(())
+                .ok_or(FaultError::Invalid);
+        }
+        // 页已经映射好了，只是 TLB 里还是旧的表项
+        if self.page_table.virt_to_phys(vaddr).is_some() {
+            arch::flush_tlb(Some(vaddr));
+            return Ok(());
+        }
+        Err(FaultError::Invalid)
+    }
+    /// 空闲页帧不够 `pages` 页时换出页，直到回到水位线以上或者没有能换出的页。
+    /// 先换出本地址空间的页，没有了再去换别的进程的
+    pub fn reclaim(&mut self, pages: usize) {
+        while swap_enabled() && frame_free_count() < SWAP_WATERMARK + pages {
+            if !self.swap_out_one() && !swap_out_other() {
+                break;
+            }
+        }
+    }
+    /// 用 clock 算法挑一页换出，访问位被置上的页清掉访问位再给一次机会
+    pub fn swap_out_one(&mut self) -> bool {
+        let candidates: Vec<(usize, VirtPage)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, area)| area.swappable_pages().map(move |vpn| (idx, vpn)))
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+        let start = self.clock_hand % candidates.len();
+        // 转一圈之后所有访问位都被清掉了，最差也会选中起点
+        let offset = (0..candidates.len())
+            .find(|&i| {
+                let vpn = candidates[(start + i) % candidates.len()].1;
+                !self.page_table.test_and_clear_accessed(vpn)
+            })
+            .unwrap_or(0);
+        let (idx, vpn) = candidates[(start + offset) % candidates.len()];
+        self.clock_hand = start + offset + 1;
+        self.areas[idx].swap_out(&self.page_table, vpn)
+    }
+    /// 缺页次数，依次是 minor 和 major
+    pub fn fault_stats(&self) -> (usize, usize) {
+        (self.minor_faults, self.major_faults)
+    }
+    pub fn translate(&self, vpn: VirtPage) -> Option<PhysPage> {
+        self.page_table.virt_to_phys(vpn.into()).map(|pa| pa.floor())
     }
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
@@ -252,11 +647,116 @@ impl MemorySet {
     }
 }
 
+/// 映射进地址空间的页帧
+///
+/// 外层 `Arc` 的引用计数是共享这一页的地址空间个数，写时复制看的是它；
+/// 内核访问用户内存时 pin 住的是里面的 [FrameTracker]，不算共享
+struct SharedFrame(Arc<FrameTracker>);
+
+impl SharedFrame {
+    fn new(frame: Arc<FrameTracker>) -> Arc<Self> {
+        Arc::new(Self(frame))
+    }
+}
+
+impl Deref for SharedFrame {
+    type Target = FrameTracker;
+    fn deref(&self) -> &FrameTracker {
+        &self.0
+    }
+}
+
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPage, PhysPage>,
+    /// 页帧可能和 fork 出来的其他地址空间共享，最后一个持有者释放时回收
+    data_frames: BTreeMap<VirtPage, Arc<SharedFrame>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// 缺页时用来填充的文件内容，没有的话填 0
+    file: Option<FileBacking>,
+    /// 换出到交换区的页，fork 之后也可能和其他地址空间共享
+    swapped: BTreeMap<VirtPage, Arc<SwapSlot>>,
+    /// MAP_SHARED 的区域，fork 之后父子进程写的是同一份页帧
+    shared: bool,
+    /// mmap 映射的文件，缺页时从页缓存里取，共享映射还要写回
+    mapped_file: Option<MappedFile>,
+}
+
+/// mmap 区域的内容从哪里来
+pub enum MmapBacking {
+    Anonymous,
+    SharedAnonymous,
+    /// 文件映射，页在缺页时才从文件的页缓存里取
+    File {
+        inode: Arc<Inode>,
+        /// 映射起点对应的文件偏移
+        offset: usize,
+        /// 文件在映射范围里的长度
+        len: usize,
+        shared: bool,
+    },
+}
+
+/// 缺页处理不了的原因
+pub enum FaultError {
+    /// 非法访问，或者换出之后还是没有空闲的页帧
+    Invalid,
+    /// 映射的文件页还不在页缓存里，读文件可能会让出 CPU，
+    /// 要放开进程的锁用 [load_page] 读进来再重试
+    ///
+    /// [load_page]: crate::fs::load_page
+    NotCached(Arc<Inode>, usize),
+}
+
+#[derive(Clone)]
+struct MappedFile {
+    inode: Arc<Inode>,
+    offset: usize,
+    /// 超出的部分在私有映射里填 0，写回时也不会让文件变长
+    len: usize,
+    start_va: usize,
+}
+
+/// 共享文件映射里要写回的一页
+pub struct FilePage {
+    inode: Arc<Inode>,
+    offset: usize,
+    len: usize,
+    frame: Arc<FrameTracker>,
+}
+
+impl FilePage {
+    /// 会读写磁盘，调用时不能拿着进程的锁
+    pub fn write_back(&self) {
+        self.inode
+            .write_at(self.offset, &self.frame.ppn.get_bytes_array()[..self.len]);
+    }
+}
+
+/// ELF 段对应的文件内容
+#[derive(Clone)]
+struct FileBacking {
+    data: Arc<Vec<u8>>,
+    /// 段在文件里的偏移和长度
+    offset: usize,
+    len: usize,
+    /// 段的起始虚拟地址，不一定页对齐
+    start_va: usize,
+}
+
+impl FileBacking {
+    /// 把 `vpn` 这一页里落在文件范围内的部分复制到 `ppn`
+    fn fill(&self, vpn: VirtPage, ppn: PhysPage) {
+        let page_va: usize = VirtAddr::from(vpn).into();
+        let start = page_va.max(self.start_va);
+        let end = (page_va + PAGE_SIZE).min(self.start_va + self.len);
+        if start >= end {
+            return;
+        }
+        let src = self.offset + (start - self.start_va);
+        ppn.get_bytes_array()[start - page_va..end - page_va]
+            .copy_from_slice(&self.data[src..src + (end - start)]);
+    }
 }
 
 impl MapArea {
@@ -273,6 +773,10 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
+            swapped: BTreeMap::new(),
+            shared: false,
+            mapped_file: None,
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -281,38 +785,310 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.clone(),
+            swapped: BTreeMap::new(),
+            shared: another.shared,
+            mapped_file: another.mapped_file.clone(),
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPage) {
+    /// 从 `at` 处拆成两段，自己保留前一段，返回后一段
+    fn split_off(&mut self, at: VirtPage) -> MapArea {
+        let end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        MapArea {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            file: self.file.clone(),
+            swapped: self.swapped.split_off(&at),
+            shared: self.shared,
+            mapped_file: self.mapped_file.clone(),
+        }
+    }
+    /// 修改权限并重新映射已经在内存里的页，没有任何权限的页只解除映射，页帧还保留着
+    fn set_permission(&mut self, page_table: &PageTable, permission: MapPermission) {
+        self.map_perm = permission;
+        let flags = self.mapping_flags(true);
+        let readable = !(permission - MapPermission::U).is_empty();
+        for vpn in self.vpn_range {
+            let ppn = match self.map_type {
+                MapType::Identical | MapType::Linear(_) => self.direct_ppn(vpn),
+                MapType::Framed | MapType::Lazy => match self.data_frames.get(&vpn) {
+                    Some(frame) => frame.ppn,
+                    None => continue,
+                },
+            };
+            if !readable {
+                page_table.unmap(vpn);
+                continue;
+            }
+            // 还和别人共享的页继续保持只读，写的时候再复制
+            let cow = !self.shared
+                && self
+                    .data_frames
+                    .get(&vpn)
+                    .map_or(false, |frame| Arc::strong_count(frame) > 1);
+            if cow {
+                page_table.map(ppn, vpn, flags - MappingFlags::W, MapPageSize::Page4k);
+            } else {
+                page_table.map(ppn, vpn, flags, MapPageSize::Page4k);
+            }
+        }
+    }
+    /// Identical 和 Linear 区域里 `vpn` 对应的物理页
+    fn direct_ppn(&self, vpn: VirtPage) -> PhysPage {
+        match self.map_type {
+            MapType::Identical => {
+                PhysPage::from(usize::from(vpn) & (arch::VIRT_ADDR_START_MASK >> 12))
+            }
+            MapType::Linear(pn_offset) => {
+                PhysPage::from((usize::from(vpn) as isize + pn_offset) as usize)
+            }
+            MapType::Framed | MapType::Lazy => unreachable!(),
+        }
+    }
+    /// 从对齐的 `vpn` 开始用一个大页映射，放不下或者没有连续的页帧时返回 None
+    ///
+    /// 大页的页帧还是按 4K 记在 `data_frames` 里，之后对其中一页的
+    /// 解除映射、改权限、写时复制和换出都会让页表把大页拆开
+    fn map_huge(
+        &mut self,
+        page_table: &PageTable,
+        vpn: VirtPage,
+        dirty: bool,
+    ) -> Option<MapPageSize> {
+        // 没有任何权限的页表项在有些架构上会被当成下一级页表
+        if !self.map_perm.contains(MapPermission::R) {
+            return None;
+        }
+        let end = self.vpn_range.get_end();
+        let fits = |size: MapPageSize| {
+            usize::from(vpn) % size.pages() == 0
+                && self.vpn_range.get_start() <= vpn
+                && vpn + size.pages() <= end
+        };
+        let flags = self.mapping_flags(dirty);
+        match self.map_type {
+            MapType::Identical | MapType::Linear(_) => {
+                let ppn = self.direct_ppn(vpn);
+                let size = [MapPageSize::Page1G, MapPageSize::Page2m]
+                    .into_iter()
+                    .find(|&size| fits(size) && size.aligned(vpn, ppn))?;
+                page_table.map(ppn, vpn, flags, size);
+                Some(size)
+            }
+            MapType::Framed | MapType::Lazy => {
+                let size = MapPageSize::Page2m;
+                let pages = size.pages();
+                // 内存紧张时不为了大页去挤占别人
+                if !fits(size)
+                    || self.file.is_some()
+                    || frame_free_count() < SWAP_WATERMARK + 2 * pages
+                    || self.data_frames.range(vpn..vpn + pages).next().is_some()
+                    || self.swapped.range(vpn..vpn + pages).next().is_some()
+                {
+                    return None;
+                }
+                let frames = frame_alloc_aligned(pages)?;
+                page_table.map(frames[0].ppn, vpn, flags, size);
+                for (i, frame) in frames.into_iter().enumerate() {
+                    self.data_frames
+                        .insert(vpn + i, SharedFrame::new(Arc::new(frame)));
+                }
+                Some(size)
+            }
+        }
+    }
+    /// 文件映射的页第一次被访问，共享映射直接用页缓存里的页帧，私有映射复制一份
+    fn map_file_page(
+        &mut self,
+        page_table: &PageTable,
+        vpn: VirtPage,
+        dirty: bool,
+    ) -> Result<(), FaultError> {
+        let file = self.mapped_file.as_ref().unwrap();
+        let page_offset = usize::from(VirtAddr::from(vpn)) - file.start_va;
+        let offset = file.offset + page_offset;
+        let cached = || {
+            cached_page(&file.inode, offset)
+                .ok_or_else(|| FaultError::NotCached(file.inode.clone(), offset))
+        };
+        let frame = if self.shared {
+            cached()?
+        } else {
+            let frame = frame_alloc_tracker().ok_or(FaultError::Invalid)?;
+            if page_offset < file.len {
+                frame
+                    .ppn
+                    .get_bytes_array()
+                    .copy_from_slice(cached()?.ppn.get_bytes_array());
+            }
+            Arc::new(frame)
+        };
+        // 共享的页之前可能被别人写过，都按脏页处理
+        let flags = self.mapping_flags(dirty || self.shared);
+        page_table.map(frame.ppn, vpn, flags, MapPageSize::Page4k);
+        self.data_frames.insert(vpn, SharedFrame::new(frame));
+        Ok(())
+    }
+    /// 映射一页，没有空闲的页帧时返回 false
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPage, dirty: bool) -> bool {
         let ppn: PhysPage;
         match self.map_type {
             MapType::Identical => {
-                ppn = PhysPage::from(usize::from(vpn)&(arch::VIRT_ADDR_START_MASK>>12)) ;
+                ppn = self.direct_ppn(vpn);
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame;
-                self.data_frames.insert(vpn, frame);
+            MapType::Framed | MapType::Lazy => {
+                let frame = match frame_alloc_tracker() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                if let Some(file) = &self.file {
+                    file.fill(vpn, frame.ppn);
+                }
+                ppn = frame.ppn;
+                self.data_frames
+                    .insert(vpn, SharedFrame::new(Arc::new(frame)));
             }
-            MapType::Linear(pn_offset) => {
+            MapType::Linear(_) => {
                 // check for sv39
                 assert!((usize::from(vpn)) < (1usize << 27));
-                ppn = PhysPage::from_addr((usize::from(vpn) as isize + pn_offset) as usize);
+                ppn = self.direct_ppn(vpn);
             }
         }
-        let mapping_flags = MappingFlags::from_bits(self.map_perm.bits as u64).unwrap();
-        page_table.map(ppn,vpn, mapping_flags,3);
+        page_table.map(ppn, vpn, self.mapping_flags(dirty), MapPageSize::Page4k);
+        true
     }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPage) {
-        if self.map_type == MapType::Framed {
+        // 还没有访问过或者被换出的页没有映射
+        if matches!(self.map_type, MapType::Framed | MapType::Lazy)
+            && !self.data_frames.contains_key(&vpn)
+        {
+            self.swapped.remove(&vpn);
+            return;
+        }
+        // 先解除映射，再释放页帧
+        page_table.unmap(vpn);
+        if matches!(self.map_type, MapType::Framed | MapType::Lazy) {
             self.data_frames.remove(&vpn);
         }
+    }
+    fn mapping_flags(&self, dirty: bool) -> MappingFlags {
+        let flags = MappingFlags::from_bits(self.map_perm.bits as u64).unwrap();
+        if dirty {
+            flags | MappingFlags::D
+        } else {
+            flags
+        }
+    }
+    /// 和 `another` 共享所有页帧，可写的页在两边都改成只读
+    ///
+    /// 之前的写入不一定写回过，重新映射时都标记为脏页
+    /// MAP_SHARED 的区域两边都保持可写
+    fn share_frames(&mut self, another: &MapArea, src: &PageTable, dst: &PageTable) {
+        let mut flags = self.mapping_flags(true);
+        if !self.shared {
+            flags.remove(MappingFlags::W);
+        }
+        for (&vpn, frame) in another.data_frames.iter() {
+            if self.map_perm.contains(MapPermission::W) && !self.shared {
+                src.map(frame.ppn, vpn, flags, MapPageSize::Page4k);
+            }
+            dst.map(frame.ppn, vpn, flags, MapPageSize::Page4k);
+            self.data_frames.insert(vpn, frame.clone());
+        }
+        self.swapped = another.swapped.clone();
+    }
+    /// 写到共享的页上，只剩自己在用就直接恢复写权限，否则复制一份，没有空闲的页帧时返回 false
+    fn copy_on_write(&mut self, page_table: &PageTable, vpn: VirtPage) -> bool {
+        let flags = self.mapping_flags(true);
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        if !self.shared && Arc::strong_count(frame) > 1 {
+            let new_frame = match frame_alloc_tracker() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = SharedFrame::new(Arc::new(new_frame));
+        }
+        page_table.map(frame.ppn, vpn, flags, MapPageSize::Page4k);
+        true
+    }
+    /// 可以换出的页，和其他地址空间共享的页不换出，
+    /// 内核正在通过 [UserBuffer] 访问的页被 pin 住了，也不换出，
+    /// 内核按物理地址访问的 TrapContext 这类不带 U 的页也不换出，
+    /// 被 mprotect 去掉所有权限的页没有映射，看不出有没有被写过，也不换出
+    fn swappable_pages(&self) -> impl Iterator<Item = VirtPage> + '_ {
+        let swappable =
+            self.map_perm.contains(MapPermission::U | MapPermission::R) && !self.shared;
+        self.data_frames
+            .iter()
+            .filter(move |(_, frame)| {
+                swappable && Arc::strong_count(frame) == 1 && Arc::strong_count(&frame.0) == 1
+            })
+            .map(|(&vpn, _)| vpn)
+    }
+    /// 把 `vpn` 换出，交换区满了返回 false
+    fn swap_out(&mut self, page_table: &PageTable, vpn: VirtPage) -> bool {
+        // 没被写过的按需分配的页直接丢掉，下次缺页时重新填充，
+        // 为写分配的页和重新映射过的页都带着脏位，不会被误丢
+        if self.map_type == MapType::Lazy && !page_table.is_dirty(vpn) {
+            page_table.unmap(vpn);
+            self.data_frames.remove(&vpn);
+            return true;
+        }
+        let slot = match swap_alloc() {
+            Some(slot) => slot,
+            None => return false,
+        };
         page_table.unmap(vpn);
+        let frame = self.data_frames.remove(&vpn).unwrap();
+        slot.write(frame.ppn);
+        self.swapped.insert(vpn, Arc::new(slot));
+        true
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// 从交换区读回 `vpn`，读回来的页还可能和别的地址空间共享着交换区，按脏页处理。
+    /// 没有空闲的页帧时返回 false，页留在交换区里
+    fn swap_in(&mut self, page_table: &PageTable, vpn: VirtPage) -> bool {
+        let frame = match frame_alloc_tracker() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let slot = self.swapped.remove(&vpn).unwrap();
+        slot.read(frame.ppn);
+        page_table.map(
+            frame.ppn,
+            vpn,
+            self.mapping_flags(true),
+            MapPageSize::Page4k,
+        );
+        self.data_frames
+            .insert(vpn, SharedFrame::new(Arc::new(frame)));
+        true
+    }
+    /// 页帧不够时解除已经映射的部分，返回 false
+    pub fn map(&mut self, page_table: &mut PageTable) -> bool {
+        // 缺页时再逐页映射
+        if self.map_type == MapType::Lazy {
+            return true;
         }
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < self.vpn_range.get_end() {
+            // 对齐的部分尽量用大页
+            match self.map_huge(page_table, vpn, false) {
+                Some(size) => vpn = vpn + size.pages(),
+                None if self.map_one(page_table, vpn, false) => vpn.step(),
+                None => {
+                    self.unmap(page_table);
+                    return false;
+                }
+            }
+        }
+        true
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
@@ -329,9 +1105,9 @@ impl MapArea {
         loop {
             let src = &data[start..len.min(start + PAGE_SIZE)];
             let dst = &mut page_table
-                .translate(current_vpn)
+                .virt_to_phys(current_vpn.into())
                 .unwrap()
-                .to_ppn()
+                .floor()
                 .get_bytes_array()[..src.len()];
             dst.copy_from_slice(src);
             start += PAGE_SIZE;
@@ -347,6 +1123,8 @@ impl MapArea {
 pub enum MapType {
     Identical,
     Framed,
+    /// 缺页时才分配页帧，有 `FileBacking` 的话从文件内容填充，否则填 0
+    Lazy,
     /// offset of page num
     Linear(isize),
 }
@@ -360,26 +1138,74 @@ bitflags! {
     }
 }
 
-// #[allow(unused)]
-// pub fn remap_test() {
-//     let mut kernel_space = KERNEL_SPACE.exclusive_access();
-//     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
-//     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
-//     let mid_data: VirtAddr = ((_sdata as usize + _edata as usize) / 2).into();
-//     assert!(!kernel_space
-//         .page_table
-//         .translate(mid_text.floor())
-//         .unwrap()
-//         .writable(),);
-//     assert!(!kernel_space
-//         .page_table
-//         .translate(mid_rodata.floor())
-//         .unwrap()
-//         .writable(),);
-//     assert!(!kernel_space
-//         .page_table
-//         .translate(mid_data.floor())
-//         .unwrap()
-//         .executable(),);
-//     println!("remap_test passed!");
-// }
+/// 线性映射里 `ppn` 所在的虚拟页
+fn linear_vpn(ppn: PhysPage) -> VirtPage {
+    VirtAddr::from(ppn.to_addr() | VIRT_ADDR_START).floor()
+}
+
+/// 把 `ppn` 从所有地址空间共用的线性映射里去掉，用作内核栈的保护页
+pub fn add_guard_page(ppn: PhysPage) {
+    KERNEL_SPACE
+        .exclusive_access()
+        .page_table
+        .unmap(linear_vpn(ppn));
+}
+
+/// 保护页还给页帧分配器之前恢复它的线性映射
+pub fn remove_guard_page(ppn: PhysPage) {
+    let flags = MappingFlags::R | MappingFlags::W;
+    KERNEL_SPACE.exclusive_access().page_table.map(
+        ppn,
+        linear_vpn(ppn),
+        flags,
+        MapPageSize::Page4k,
+    );
+}
+
+/// 检查内核各个段的权限和内核栈的保护页
+///
+/// loongarch64 的内核在直接映射窗口里，没有页级权限也没有保护页（见 `arch::KERNEL_PAGED`），
+/// 这些检查在那里不成立，所以不编译
+#[cfg(not(target_arch = "loongarch64"))]
+pub fn remap_test() {
+    let kstack = crate::task::kstack_alloc();
+    let kernel_space = KERNEL_SPACE.exclusive_access();
+    let flags = |va: usize| {
+        kernel_space
+            .page_table
+            .mapping_flags(VirtAddr::from(va).floor())
+    };
+    let mid = |start: usize, end: usize| (start + end) / 2;
+    let text = flags(mid(stext as usize, etext as usize)).unwrap();
+    assert!(text.contains(MappingFlags::R | MappingFlags::X));
+    assert!(!text.contains(MappingFlags::W));
+    let rodata = flags(mid(srodata as usize, erodata as usize)).unwrap();
+    assert!(rodata.contains(MappingFlags::R));
+    assert!(!rodata.intersects(MappingFlags::W | MappingFlags::X));
+    for va in [
+        mid(_sdata as usize, _edata as usize),
+        mid(sbss_with_stack as usize, _ebss as usize),
+        kstack.get_top() - PAGE_SIZE,
+    ] {
+        let data = flags(va).unwrap();
+        assert!(data.contains(MappingFlags::R | MappingFlags::W));
+        assert!(!data.contains(MappingFlags::X));
+    }
+    // 整个内核镜像里没有既可写又可执行的页
+    for va in (stext as usize..ekernel as usize).step_by(PAGE_SIZE) {
+        assert!(!flags(va)
+            .unwrap()
+            .contains(MappingFlags::W | MappingFlags::X));
+    }
+    let guard = kstack.guard_page();
+    assert!(flags(guard).is_none());
+    drop(kernel_space);
+    // 内核栈回收之后保护页恢复映射
+    drop(kstack);
+    assert!(KERNEL_SPACE
+        .exclusive_access()
+        .page_table
+        .mapping_flags(VirtAddr::from(guard).floor())
+        .is_some());
+    println!("remap_test passed!");
+}
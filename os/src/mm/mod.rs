@@ -1,93 +1,42 @@
+mod elf;
 mod frame_allocator;
 mod memory_set;
+pub mod swap;
+mod user_ptr;
 
 pub use arch::{VPNRange,StepByOne,PhysAddr,PhysPage,VirtAddr,VirtPage};
-pub use frame_allocator::{frame_alloc, frame_alloc_more, frame_dealloc, FrameTracker};
-pub use memory_set::{kernel_token, MapArea, MapPermission, MapType, MemorySet, KERNEL_SPACE};
+pub use elf::{elf_interpreter, elf_supported, init_user_stack, ElfInfo};
+pub use frame_allocator::{
+    add_frame_region, frame_alloc, frame_alloc_aligned, frame_alloc_more, frame_alloc_tracker,
+    frame_dealloc, frame_free_count, frame_stats, FrameStats, FrameTracker,
+};
+#[cfg(not(target_arch = "loongarch64"))]
+pub use memory_set::remap_test;
+pub use memory_set::{
+    add_guard_page, kernel_token, remove_guard_page, FaultError, FilePage, MapArea, MapPermission,
+    MapType, MemorySet, MmapBacking, KERNEL_SPACE,
+};
+pub use user_ptr::{UserPtr, UserSlice, EFAULT};
 
-pub use arch::{PageTable,PTE,MappingFlags};
-use alloc::string::String;
+pub use arch::{PageTable,MappingFlags,MapPageSize};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use riscv::register::satp;
+/// 页帧分配器拿到内存区域之后才能调用
 pub fn init() {
-	allocator::init();
-    frame_allocator::init_frame_allocator();
-    
-}
-pub fn init_kernel_space()
-{
-	let satp=KERNEL_SPACE.exclusive_access().token();
-	unsafe {
-		satp::write(satp);
-		core::arch::asm!("sfence.vma");
-	}
-}
-
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_satp(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().to_ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
-        }
-        start = end_va.into();
-    }
-    v
-}
-
-/// Load a string from other address spaces into kernel space without an end `\0`.
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_satp(token);
-    let mut string = String::new();
-    let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        }
-        string.push(ch as char);
-        va += 1;
-    }
-    string
-}
-
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
-    let page_table = PageTable::from_satp(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_ref()
-}
-
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::from_satp(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    // 页帧分配器有了内存之后，内核堆才能从这里扩充
+    allocator::set_page_provider(&frame_allocator::HeapPageProvider);
+    KERNEL_SPACE.exclusive_access().activate();
 }
 
 pub struct UserBuffer {
     pub buffers: Vec<&'static mut [u8]>,
+    /// 这些页帧在用完之前不能被换出或者释放，访问可能会阻塞很久，比如读管道
+    pins: Vec<Arc<FrameTracker>>,
 }
 
 impl UserBuffer {
-    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
-        Self { buffers }
+    pub fn new(buffers: Vec<&'static mut [u8]>, pins: Vec<Arc<FrameTracker>>) -> Self {
+        Self { buffers, pins }
     }
     pub fn len(&self) -> usize {
         let mut total: usize = 0;
@@ -106,6 +55,7 @@ impl IntoIterator for UserBuffer {
             buffers: self.buffers,
             current_buffer: 0,
             current_idx: 0,
+            _pins: self.pins,
         }
     }
 }
@@ -114,6 +64,7 @@ pub struct UserBufferIterator {
     buffers: Vec<&'static mut [u8]>,
     current_buffer: usize,
     current_idx: usize,
+    _pins: Vec<Arc<FrameTracker>>,
 }
 
 impl Iterator for UserBufferIterator {
@@ -0,0 +1,130 @@
+//! 交换区：空闲页帧不够时把用户页写到单独的块设备上，缺页时再读回来
+//!
+//! 每个地址空间用 clock 算法挑选自己的页换出，页表项的访问位由各个架构的 HAL 提供
+
+use super::PhysPage;
+use crate::config::PAGE_SIZE;
+use crate::drivers::SWAP_DEVICE;
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use easy_fs::BLOCK_SZ;
+use lazy_static::*;
+use log::info;
+
+/// 交换区大小，需要和 Makefile 里 swap.img 的大小一致
+const SWAP_PAGES: usize = 4096;
+
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// 空闲页帧少于这个数时开始换出，留一些给页表和内核用
+pub const SWAP_WATERMARK: usize = 16;
+
+/// 交换区里的一页，drop 时归还
+pub struct SwapSlot(usize);
+
+impl SwapSlot {
+    pub fn read(&self, ppn: PhysPage) {
+        let device = SWAP_DEVICE.as_ref().unwrap();
+        for (i, block) in ppn.get_bytes_array().chunks_mut(BLOCK_SZ).enumerate() {
+            device.read_block(self.0 * BLOCKS_PER_PAGE + i, block);
+        }
+        SWAP_IN.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn write(&self, ppn: PhysPage) {
+        let device = SWAP_DEVICE.as_ref().unwrap();
+        for (i, block) in ppn.get_bytes_array().chunks(BLOCK_SZ).enumerate() {
+            device.write_block(self.0 * BLOCKS_PER_PAGE + i, block);
+        }
+        SWAP_OUT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+struct SwapAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl SwapAllocator {
+    const fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(slot) = self.recycled.pop() {
+            Some(slot)
+        } else if self.current < SWAP_PAGES {
+            self.current += 1;
+            Some(self.current - 1)
+        } else {
+            None
+        }
+    }
+    /// 正在使用的页数
+    fn used(&self) -> usize {
+        self.current - self.recycled.len()
+    }
+    fn dealloc(&mut self, slot: usize) {
+        assert!(
+            slot < self.current && !self.recycled.contains(&slot),
+            "swap slot {} has not been allocated!",
+            slot
+        );
+        self.recycled.push(slot);
+    }
+}
+
+lazy_static! {
+    static ref SWAP_ALLOCATOR: UPIntrFreeCell<SwapAllocator> =
+        unsafe { UPIntrFreeCell::new(SwapAllocator::new()) };
+}
+
+static SWAP_IN: AtomicUsize = AtomicUsize::new(0);
+static SWAP_OUT: AtomicUsize = AtomicUsize::new(0);
+
+/// 在内存还充足的时候初始化交换设备，驱动本身也要分配页帧
+pub fn init() {
+    if SWAP_DEVICE.is_some() {
+        info!("swap: {} pages", SWAP_PAGES);
+    } else {
+        info!("swap: no swap device");
+    }
+}
+
+pub fn swap_enabled() -> bool {
+    SWAP_DEVICE.is_some()
+}
+
+pub fn swap_alloc() -> Option<SwapSlot> {
+    if !swap_enabled() {
+        return None;
+    }
+    SWAP_ALLOCATOR.exclusive_access().alloc().map(SwapSlot)
+}
+
+/// 换入和换出的总页数
+pub fn swap_stats() -> (usize, usize) {
+    (
+        SWAP_IN.load(Ordering::Relaxed),
+        SWAP_OUT.load(Ordering::Relaxed),
+    )
+}
+
+/// 交换区的总页数和空闲页数，没有交换设备时都是 0
+pub fn swap_space() -> (usize, usize) {
+    if !swap_enabled() {
+        return (0, 0);
+    }
+    (
+        SWAP_PAGES,
+        SWAP_PAGES - SWAP_ALLOCATOR.exclusive_access().used(),
+    )
+}
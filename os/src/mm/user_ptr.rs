@@ -0,0 +1,184 @@
+//! 系统调用访问用户内存的接口
+//!
+//! 访问之前先检查地址是否落在用户有相应权限的区域里，不合法时返回 [EFAULT]，
+//! 用户传来的坏指针不会让内核 panic
+
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{FaultError, FrameTracker, MapPermission, PageTable, StepByOne, UserBuffer, VirtAddr};
+use crate::config::PAGE_SIZE;
+use crate::task::current_task;
+
+/// 地址不合法，和 Linux 的错误码一致
+pub const EFAULT: isize = -14;
+
+/// 用户地址空间里的一个 `T`，不要求对齐，可以跨页
+pub struct UserPtr<T> {
+    token: usize,
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(token: usize, ptr: *const T) -> Self {
+        Self {
+            token,
+            addr: ptr as usize,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 往后第 `count` 个元素
+    pub fn add(&self, count: usize) -> Self {
+        Self {
+            token: self.token,
+            addr: self.addr.wrapping_add(count * size_of::<T>()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn read(&self) -> Result<T, isize> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let mut dst = value.as_mut_ptr() as *mut u8;
+        for chunk in self.slice().reader()?.buffers.iter() {
+            unsafe {
+                dst.copy_from_nonoverlapping(chunk.as_ptr(), chunk.len());
+                dst = dst.add(chunk.len());
+            }
+        }
+        Ok(unsafe { value.assume_init() })
+    }
+
+    pub fn write(&self, value: T) -> Result<(), isize> {
+        let src =
+            unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        self.slice().copy_from(src)
+    }
+
+    fn slice(&self) -> UserSlice {
+        UserSlice::new(self.token, self.addr as *const u8, size_of::<T>())
+    }
+}
+
+impl UserPtr<u8> {
+    /// 读一个以 `\0` 结尾的字符串，不包括结尾的 `\0`
+    pub fn read_str(&self) -> Result<String, isize> {
+        let mut string = String::new();
+        let mut va = self.addr;
+        loop {
+            // 每次只检查到页尾，字符串后面的页不一定合法
+            let len = PAGE_SIZE - va % PAGE_SIZE;
+            for chunk in UserSlice::new(self.token, va as *const u8, len)
+                .reader()?
+                .buffers
+                .iter()
+            {
+                for &ch in chunk.iter() {
+                    if ch == 0 {
+                        return Ok(string);
+                    }
+                    string.push(ch as char);
+                }
+            }
+            va += len;
+        }
+    }
+}
+
+/// 用户地址空间里的一段字节
+pub struct UserSlice {
+    token: usize,
+    addr: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(token: usize, ptr: *const u8, len: usize) -> Self {
+        Self {
+            token,
+            addr: ptr as usize,
+            len,
+        }
+    }
+
+    /// 内核从这段内存里读数据
+    pub fn reader(&self) -> Result<UserBuffer, isize> {
+        self.buffers(MapPermission::R)
+    }
+
+    /// 内核往这段内存里写数据
+    pub fn writer(&self) -> Result<UserBuffer, isize> {
+        self.buffers(MapPermission::W)
+    }
+
+    /// 把 `src` 拷到这段内存的开头，`src` 比这段内存长的部分丢掉
+    pub fn copy_from(&self, src: &[u8]) -> Result<(), isize> {
+        let len = self.len.min(src.len());
+        let mut src = &src[..len];
+        for chunk in UserSlice::new(self.token, self.addr as *const u8, len)
+            .writer()?
+            .buffers
+            .iter_mut()
+        {
+            chunk.copy_from_slice(&src[..chunk.len()]);
+            src = &src[chunk.len()..];
+        }
+        Ok(())
+    }
+
+    /// 按页切开，内核通过物理地址访问每一段，返回的 [UserBuffer] 用完之前这些页不会被换出
+    fn buffers(&self, access: MapPermission) -> Result<UserBuffer, isize> {
+        // 长度为 0 时不访问用户内存，地址是什么都可以
+        if self.len == 0 {
+            return Ok(UserBuffer::new(Vec::new(), Vec::new()));
+        }
+        // 结束地址向上取整到页时也不能溢出
+        let end = match self.addr.checked_add(self.len) {
+            Some(end) if end <= usize::MAX - PAGE_SIZE => end,
+            _ => return Err(EFAULT),
+        };
+        let pins = prepare_user_access(self.token, self.addr, end, access).ok_or(EFAULT)?;
+        let page_table = PageTable::from_token(self.token);
+        let mut start = self.addr;
+        let mut v = Vec::new();
+        while start < end {
+            let start_va = VirtAddr::from(start);
+            let mut vpn = start_va.floor();
+            let ppn = page_table.virt_to_phys(vpn.into()).ok_or(EFAULT)?.floor();
+            vpn.step();
+            let end_va = VirtAddr::from(vpn).min(VirtAddr::from(end));
+            if end_va.page_offset() == 0 {
+                v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+            } else {
+                v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+            }
+            start = end_va.into();
+        }
+        Ok(UserBuffer::new(v, pins))
+    }
+}
+
+/// 只能访问当前进程的地址空间，检查和缺页处理都在 [MemorySet] 里做。
+/// 返回准备好的页帧，在放开进程的锁之前拿住它们，别的核换页时就不会换出这些页
+///
+/// [MemorySet]: super::MemorySet
+fn prepare_user_access(
+    token: usize,
+    start: usize,
+    end: usize,
+    access: MapPermission,
+) -> Option<Vec<Arc<FrameTracker>>> {
+    let process = current_task().and_then(|task| task.process.upgrade())?;
+    process.handle_faults(|memory_set| {
+        if memory_set.token() != token {
+            return Err(FaultError::Invalid);
+        }
+        memory_set.prepare_user_access(start.into(), end.into(), access)?;
+        Ok(memory_set.pin_frames(start.into(), end.into()))
+    })
+}
@@ -1,8 +1,9 @@
 use alloc::{sync::Arc, vec::Vec};
+use arch::ContextOps;
 use lazy_static::lazy_static;
 use lose_net_stack::packets::tcp::TCPPacket;
 
-use crate::fs::File;
+use crate::fs::{File, FileType};
 use crate::sync::UPIntrFreeCell;
 use crate::task::TaskControlBlock;
 
@@ -104,7 +105,7 @@ pub fn accept_connection(_port: u16, tcp_packet: &TCPPacket, task: Arc<TaskContr
     inner.fd_table[fd] = Some(Arc::new(tcp_socket));
 
     let cx = task.inner_exclusive_access().get_trap_cx();
-    cx.x[10] = fd;
+    cx.set_ret(fd);
 }
 
 // store in the fd_table, delete the listen table when close the application.
@@ -138,4 +139,8 @@ impl File for PortFd {
     fn write(&self, _buf: crate::mm::UserBuffer) -> usize {
         0
     }
+
+    fn file_type(&self) -> FileType {
+        FileType::Socket
+    }
 }
@@ -2,7 +2,7 @@ use super::net_interrupt_handler;
 use super::socket::{add_socket, pop_data, remove_socket};
 use super::LOSE_NET_STACK;
 use super::NET_DEVICE;
-use crate::fs::File;
+use crate::fs::{File, FileType};
 use alloc::vec;
 use lose_net_stack::packets::udp::UDPPacket;
 use lose_net_stack::IPv4;
@@ -86,6 +86,10 @@ impl File for UDP {
         NET_DEVICE.transmit(&udp_packet.build_data());
         len
     }
+
+    fn file_type(&self) -> FileType {
+        FileType::Socket
+    }
 }
 
 impl Drop for UDP {
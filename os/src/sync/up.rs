@@ -1,7 +1,8 @@
-use core::cell::{RefCell, RefMut, UnsafeCell};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::*;
-use riscv::register::sstatus;
 
 /*
 /// Wrap a static data structure inside it so that we are
@@ -56,8 +57,10 @@ pub struct IntrMaskingInfo {
 }
 
 lazy_static! {
-    static ref INTR_MASKING_INFO: UPSafeCellRaw<IntrMaskingInfo> =
-        unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) };
+    /// 每个核各自记录关中断的嵌套层数
+    static ref INTR_MASKING_INFO: Vec<UPSafeCellRaw<IntrMaskingInfo>> = (0..arch::MAX_CPU_NUM)
+        .map(|_| unsafe { UPSafeCellRaw::new(IntrMaskingInfo::new()) })
+        .collect();
 }
 
 impl IntrMaskingInfo {
@@ -68,47 +71,112 @@ impl IntrMaskingInfo {
         }
     }
 
-    pub fn enter(&mut self) {
-        let sie = sstatus::read().sie();
-        unsafe {
-            sstatus::clear_sie();
+    /// 先关中断再取当前核的记录，中间不会被换到别的核上
+    pub fn enter() {
+        let sie = arch::irq_enabled();
+        arch::disable_irq();
+        let info = INTR_MASKING_INFO[arch::hart_id()].get_mut();
+        if info.nested_level == 0 {
+            info.sie_before_masking = sie;
         }
-        if self.nested_level == 0 {
-            self.sie_before_masking = sie;
-        }
-        self.nested_level += 1;
+        info.nested_level += 1;
     }
 
-    pub fn exit(&mut self) {
-        self.nested_level -= 1;
-        if self.nested_level == 0 && self.sie_before_masking {
-            unsafe {
-                sstatus::set_sie();
-            }
+    pub fn exit() {
+        let info = INTR_MASKING_INFO[arch::hart_id()].get_mut();
+        info.nested_level -= 1;
+        if info.nested_level == 0 && info.sie_before_masking {
+            arch::enable_irq();
         }
     }
 }
 
+/// 当前核是否拿着 [UPIntrFreeCell] 的锁
+pub fn holding_locks() -> bool {
+    IntrMaskingInfo::enter();
+    let held = INTR_MASKING_INFO[arch::hart_id()].get_mut().nested_level > 1;
+    IntrMaskingInfo::exit();
+    held
+}
+
+const NO_OWNER: usize = usize::MAX;
+
+/// 关中断的自旋锁
+///
+/// 同一个核重复获取会直接 panic，保留原来 RefCell 重复借用时的行为
 pub struct UPIntrFreeCell<T> {
+    locked: AtomicBool,
+    /// 持有锁的核
+    owner: AtomicUsize,
     /// inner data
-    inner: RefCell<T>,
+    inner: UnsafeCell<T>,
 }
 
 unsafe impl<T> Sync for UPIntrFreeCell<T> {}
 
-pub struct UPIntrRefMut<'a, T>(Option<RefMut<'a, T>>);
+pub struct UPIntrRefMut<'a, T>(&'a UPIntrFreeCell<T>);
 
 impl<T> UPIntrFreeCell<T> {
-    pub unsafe fn new(value: T) -> Self {
+    pub const unsafe fn new(value: T) -> Self {
         Self {
-            inner: RefCell::new(value),
+            locked: AtomicBool::new(false),
+            owner: AtomicUsize::new(NO_OWNER),
+            inner: UnsafeCell::new(value),
         }
     }
 
-    /// Panic if the data has been borrowed.
+    /// Panic if the data has been borrowed by the current CPU.
     pub fn exclusive_access(&self) -> UPIntrRefMut<'_, T> {
-        INTR_MASKING_INFO.get_mut().enter();
-        UPIntrRefMut(Some(self.inner.borrow_mut()))
+        IntrMaskingInfo::enter();
+        let cpu = arch::hart_id();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if self.owner.load(Ordering::Relaxed) == cpu {
+                panic!("already borrowed: BorrowMutError");
+            }
+            arch::handle_pending_ipi();
+            core::hint::spin_loop();
+        }
+        self.owner.store(cpu, Ordering::Relaxed);
+        UPIntrRefMut(self)
+    }
+
+    /// 当前核已经拿着锁时返回 None，别的核拿着时照常等待
+    pub fn exclusive_access_unless_held(&self) -> Option<UPIntrRefMut<'_, T>> {
+        IntrMaskingInfo::enter();
+        let cpu = arch::hart_id();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if self.owner.load(Ordering::Relaxed) == cpu {
+                IntrMaskingInfo::exit();
+                return None;
+            }
+            arch::handle_pending_ipi();
+            core::hint::spin_loop();
+        }
+        self.owner.store(cpu, Ordering::Relaxed);
+        Some(UPIntrRefMut(self))
+    }
+
+    /// 已经被借用时直接返回 None，不会等待
+    pub fn try_exclusive_access(&self) -> Option<UPIntrRefMut<'_, T>> {
+        IntrMaskingInfo::enter();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            IntrMaskingInfo::exit();
+            return None;
+        }
+        self.owner.store(arch::hart_id(), Ordering::Relaxed);
+        Some(UPIntrRefMut(self))
     }
 
     pub fn exclusive_session<F, V>(&self, f: F) -> V
@@ -122,19 +190,20 @@ impl<T> UPIntrFreeCell<T> {
 
 impl<'a, T> Drop for UPIntrRefMut<'a, T> {
     fn drop(&mut self) {
-        self.0 = None;
-        INTR_MASKING_INFO.get_mut().exit();
+        self.0.owner.store(NO_OWNER, Ordering::Relaxed);
+        self.0.locked.store(false, Ordering::Release);
+        IntrMaskingInfo::exit();
     }
 }
 
 impl<'a, T> Deref for UPIntrRefMut<'a, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        self.0.as_ref().unwrap().deref()
+        unsafe { &*self.0.inner.get() }
     }
 }
 impl<'a, T> DerefMut for UPIntrRefMut<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.as_mut().unwrap().deref_mut()
+        unsafe { &mut *self.0.inner.get() }
     }
 }
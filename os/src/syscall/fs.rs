@@ -1,5 +1,5 @@
 use crate::fs::{make_pipe, open_file, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
+use crate::mm::{UserPtr, UserSlice};
 use crate::task::{current_process, current_user_token};
 use alloc::sync::Arc;
 
@@ -17,7 +17,10 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match UserSlice::new(token, buf, len).reader() {
+            Ok(buf) => file.write(buf) as isize,
+            Err(err) => err,
+        }
     } else {
         -1
     }
@@ -37,7 +40,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match UserSlice::new(token, buf, len).writer() {
+            Ok(buf) => file.read(buf) as isize,
+            Err(err) => err,
+        }
     } else {
         -1
     }
@@ -46,7 +52,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match UserPtr::new(token, path).read_str() {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
     if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
         let mut inner = process.inner_exclusive_access();
         let fd = inner.alloc_fd();
@@ -79,8 +88,16 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
+    // 写用户内存时可能要处理写时复制，需要先释放进程的锁
+    drop(inner);
+    let pipe = UserPtr::new(token, pipe);
+    if let Err(err) = pipe.write(read_fd).and(pipe.add(1).write(write_fd)) {
+        // 用户拿不到这两个描述符，直接关掉
+        let mut inner = process.inner_exclusive_access();
+        inner.fd_table[read_fd].take();
+        inner.fd_table[write_fd].take();
+        return err;
+    }
     0
 }
 
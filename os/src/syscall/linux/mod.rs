@@ -0,0 +1,259 @@
+//! Linux 兼容的系统调用接口，打开 `linux-abi` 后可以运行静态链接的 musl 程序
+//!
+//! 按当前架构的 Linux 系统调用号分发，出错时返回负的 errno。
+//! 编号不小于 [NATIVE_SYSCALL_BASE] 的线程、同步、图形等调用还交给原来的接口处理
+
+mod fs;
+mod process;
+
+use fs::*;
+use process::*;
+
+use super::memory::{sys_brk, sys_mprotect, sys_munmap};
+use super::process::{sys_getpid, sys_yield};
+use log::warn;
+
+// riscv64、aarch64 和 LoongArch 用的都是 asm-generic 的编号
+#[cfg(not(target_arch = "x86_64"))]
+mod nr {
+    pub const SYSCALL_GETCWD: usize = 17;
+    pub const SYSCALL_DUP: usize = 23;
+    pub const SYSCALL_DUP3: usize = 24;
+    pub const SYSCALL_FCNTL: usize = 25;
+    pub const SYSCALL_IOCTL: usize = 29;
+    pub const SYSCALL_FACCESSAT: usize = 48;
+    pub const SYSCALL_CHDIR: usize = 49;
+    pub const SYSCALL_OPENAT: usize = 56;
+    pub const SYSCALL_CLOSE: usize = 57;
+    pub const SYSCALL_PIPE2: usize = 59;
+    pub const SYSCALL_GETDENTS64: usize = 61;
+    pub const SYSCALL_LSEEK: usize = 62;
+    pub const SYSCALL_READ: usize = 63;
+    pub const SYSCALL_WRITE: usize = 64;
+    pub const SYSCALL_READV: usize = 65;
+    pub const SYSCALL_WRITEV: usize = 66;
+    pub const SYSCALL_PPOLL: usize = 73;
+    pub const SYSCALL_READLINKAT: usize = 78;
+    pub const SYSCALL_NEWFSTATAT: usize = 79;
+    pub const SYSCALL_FSTAT: usize = 80;
+    pub const SYSCALL_EXIT: usize = 93;
+    pub const SYSCALL_EXIT_GROUP: usize = 94;
+    pub const SYSCALL_SET_TID_ADDRESS: usize = 96;
+    pub const SYSCALL_SET_ROBUST_LIST: usize = 99;
+    pub const SYSCALL_NANOSLEEP: usize = 101;
+    pub const SYSCALL_CLOCK_GETTIME: usize = 113;
+    pub const SYSCALL_SCHED_YIELD: usize = 124;
+    pub const SYSCALL_KILL: usize = 129;
+    pub const SYSCALL_RT_SIGACTION: usize = 134;
+    pub const SYSCALL_RT_SIGPROCMASK: usize = 135;
+    pub const SYSCALL_SETPGID: usize = 154;
+    pub const SYSCALL_GETPGID: usize = 155;
+    pub const SYSCALL_SETSID: usize = 157;
+    pub const SYSCALL_UNAME: usize = 160;
+    pub const SYSCALL_UMASK: usize = 166;
+    pub const SYSCALL_GETTIMEOFDAY: usize = 169;
+    pub const SYSCALL_GETPID: usize = 172;
+    pub const SYSCALL_GETPPID: usize = 173;
+    pub const SYSCALL_GETUID: usize = 174;
+    pub const SYSCALL_GETEUID: usize = 175;
+    pub const SYSCALL_GETGID: usize = 176;
+    pub const SYSCALL_GETEGID: usize = 177;
+    pub const SYSCALL_GETTID: usize = 178;
+    pub const SYSCALL_BRK: usize = 214;
+    pub const SYSCALL_MUNMAP: usize = 215;
+    pub const SYSCALL_CLONE: usize = 220;
+    pub const SYSCALL_EXECVE: usize = 221;
+    pub const SYSCALL_MMAP: usize = 222;
+    pub const SYSCALL_MPROTECT: usize = 226;
+    pub const SYSCALL_MADVISE: usize = 233;
+    pub const SYSCALL_WAIT4: usize = 260;
+    pub const SYSCALL_PRLIMIT64: usize = 261;
+}
+
+// x86_64 有自己的编号，还保留着 open、stat、fork 这些老的调用
+#[cfg(target_arch = "x86_64")]
+mod nr {
+    pub const SYSCALL_READ: usize = 0;
+    pub const SYSCALL_WRITE: usize = 1;
+    pub const SYSCALL_OPEN: usize = 2;
+    pub const SYSCALL_CLOSE: usize = 3;
+    pub const SYSCALL_STAT: usize = 4;
+    pub const SYSCALL_FSTAT: usize = 5;
+    pub const SYSCALL_LSTAT: usize = 6;
+    pub const SYSCALL_POLL: usize = 7;
+    pub const SYSCALL_LSEEK: usize = 8;
+    pub const SYSCALL_MMAP: usize = 9;
+    pub const SYSCALL_MPROTECT: usize = 10;
+    pub const SYSCALL_MUNMAP: usize = 11;
+    pub const SYSCALL_BRK: usize = 12;
+    pub const SYSCALL_RT_SIGACTION: usize = 13;
+    pub const SYSCALL_RT_SIGPROCMASK: usize = 14;
+    pub const SYSCALL_IOCTL: usize = 16;
+    pub const SYSCALL_READV: usize = 19;
+    pub const SYSCALL_WRITEV: usize = 20;
+    pub const SYSCALL_ACCESS: usize = 21;
+    pub const SYSCALL_PIPE: usize = 22;
+    pub const SYSCALL_SCHED_YIELD: usize = 24;
+    pub const SYSCALL_MADVISE: usize = 28;
+    pub const SYSCALL_DUP: usize = 32;
+    pub const SYSCALL_DUP2: usize = 33;
+    pub const SYSCALL_NANOSLEEP: usize = 35;
+    pub const SYSCALL_GETPID: usize = 39;
+    pub const SYSCALL_CLONE: usize = 56;
+    pub const SYSCALL_FORK: usize = 57;
+    pub const SYSCALL_VFORK: usize = 58;
+    pub const SYSCALL_EXECVE: usize = 59;
+    pub const SYSCALL_EXIT: usize = 60;
+    pub const SYSCALL_WAIT4: usize = 61;
+    pub const SYSCALL_KILL: usize = 62;
+    pub const SYSCALL_UNAME: usize = 63;
+    pub const SYSCALL_FCNTL: usize = 72;
+    pub const SYSCALL_GETCWD: usize = 79;
+    pub const SYSCALL_CHDIR: usize = 80;
+    pub const SYSCALL_READLINK: usize = 89;
+    pub const SYSCALL_UMASK: usize = 95;
+    pub const SYSCALL_GETTIMEOFDAY: usize = 96;
+    pub const SYSCALL_GETUID: usize = 102;
+    pub const SYSCALL_GETGID: usize = 104;
+    pub const SYSCALL_GETEUID: usize = 107;
+    pub const SYSCALL_GETEGID: usize = 108;
+    pub const SYSCALL_SETPGID: usize = 109;
+    pub const SYSCALL_GETPPID: usize = 110;
+    pub const SYSCALL_GETPGRP: usize = 111;
+    pub const SYSCALL_SETSID: usize = 112;
+    pub const SYSCALL_GETPGID: usize = 121;
+    pub const SYSCALL_ARCH_PRCTL: usize = 158;
+    pub const SYSCALL_GETTID: usize = 186;
+    pub const SYSCALL_GETDENTS64: usize = 217;
+    pub const SYSCALL_SET_TID_ADDRESS: usize = 218;
+    pub const SYSCALL_CLOCK_GETTIME: usize = 228;
+    pub const SYSCALL_EXIT_GROUP: usize = 231;
+    pub const SYSCALL_OPENAT: usize = 257;
+    pub const SYSCALL_NEWFSTATAT: usize = 262;
+    pub const SYSCALL_READLINKAT: usize = 267;
+    pub const SYSCALL_FACCESSAT: usize = 269;
+    pub const SYSCALL_PPOLL: usize = 271;
+    pub const SYSCALL_SET_ROBUST_LIST: usize = 273;
+    pub const SYSCALL_DUP3: usize = 292;
+    pub const SYSCALL_PIPE2: usize = 293;
+    pub const SYSCALL_PRLIMIT64: usize = 302;
+}
+
+use nr::*;
+
+/// 原来的接口从这个编号开始，Linux 的编号都比它小
+const NATIVE_SYSCALL_BASE: usize = 1000;
+
+// Linux 的错误码，访问用户内存出错时 UserPtr 已经返回了 EFAULT
+const ENOENT: isize = -2;
+const ESRCH: isize = -3;
+const EINTR: isize = -4;
+const EBADF: isize = -9;
+const ECHILD: isize = -10;
+const ENOMEM: isize = -12;
+const EEXIST: isize = -17;
+const ENOTDIR: isize = -20;
+const EISDIR: isize = -21;
+const EINVAL: isize = -22;
+const ENOTTY: isize = -25;
+const ENOSPC: isize = -28;
+const ESPIPE: isize = -29;
+const ERANGE: isize = -34;
+pub(super) const ENOSYS: isize = -38;
+
+/// 原来的接口失败时只返回 -1，换成对应的错误码
+fn errno(ret: isize, err: isize) -> isize {
+    if ret == -1 {
+        err
+    } else {
+        ret
+    }
+}
+
+/// 不属于 Linux 的编号返回 None，交给原来的接口处理
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> Option<isize> {
+    let ret = match syscall_id {
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
+        SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_DUP3 => sys_dup3(args[0], args[1], args[2]),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_FACCESSAT => sys_faccessat(args[1] as *const u8),
+        SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
+        SYSCALL_OPENAT => sys_openat(args[1] as *const u8, args[2]),
+        SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_PIPE2 => sys_pipe2(args[0] as *mut [i32; 2]),
+        SYSCALL_GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]),
+        SYSCALL_LSEEK => sys_lseek(args[0], args[1] as isize, args[2]),
+        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_READV => sys_readv(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_WRITEV => sys_writev(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_PPOLL => sys_ppoll(args[0] as *mut PollFd, args[1]),
+        SYSCALL_READLINKAT => sys_readlinkat(args[1] as *const u8),
+        SYSCALL_NEWFSTATAT => sys_newfstatat(
+            args[0],
+            args[1] as *const u8,
+            args[2] as *mut Kstat,
+            args[3],
+        ),
+        SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Kstat),
+        SYSCALL_EXIT | SYSCALL_EXIT_GROUP => sys_exit(args[0] as i32),
+        SYSCALL_SET_TID_ADDRESS | SYSCALL_GETTID => sys_getpid(),
+        SYSCALL_SET_ROBUST_LIST | SYSCALL_MADVISE | SYSCALL_SETPGID => 0,
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[1] as *mut TimeSpec),
+        SYSCALL_SCHED_YIELD => sys_yield(),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1]),
+        SYSCALL_RT_SIGACTION => sys_rt_sigaction(args[2] as *mut u8),
+        SYSCALL_RT_SIGPROCMASK => sys_rt_sigprocmask(args[2] as *mut u64),
+        SYSCALL_GETPGID | SYSCALL_SETSID => sys_getpid(),
+        SYSCALL_UNAME => sys_uname(args[0] as *mut UtsName),
+        SYSCALL_UMASK => 0o022,
+        SYSCALL_GETTIMEOFDAY => sys_gettimeofday(args[0] as *mut TimeVal),
+        SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETPPID => sys_getppid(),
+        // 只有 root 一个用户
+        SYSCALL_GETUID | SYSCALL_GETEUID | SYSCALL_GETGID | SYSCALL_GETEGID => 0,
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MUNMAP => errno(sys_munmap(args[0], args[1]), EINVAL),
+        SYSCALL_CLONE => sys_clone(args),
+        SYSCALL_EXECVE => sys_execve(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYSCALL_MPROTECT => errno(sys_mprotect(args[0], args[1], args[2]), EINVAL),
+        SYSCALL_WAIT4 => sys_wait4(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_PRLIMIT64 => sys_prlimit64(args[1], args[3] as *mut [u64; 2]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_OPEN => sys_openat(args[0] as *const u8, args[1]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_STAT | SYSCALL_LSTAT => {
+            sys_newfstatat(0, args[0] as *const u8, args[1] as *mut Kstat, 0)
+        }
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_POLL => sys_ppoll(args[0] as *mut PollFd, args[1]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_ACCESS => sys_faccessat(args[0] as *const u8),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_PIPE => sys_pipe2(args[0] as *mut [i32; 2]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_DUP2 => sys_dup2(args[0], args[1]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_FORK | SYSCALL_VFORK => sys_clone([0; 6]),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_READLINK => sys_readlinkat(args[0] as *const u8),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_GETPGRP => sys_getpid(),
+        #[cfg(target_arch = "x86_64")]
+        SYSCALL_ARCH_PRCTL => sys_arch_prctl(args[0], args[1]),
+        _ if syscall_id < NATIVE_SYSCALL_BASE => {
+            warn!("Unsupported linux syscall_id: {}", syscall_id);
+            ENOSYS
+        }
+        _ => return None,
+    };
+    Some(ret)
+}
@@ -0,0 +1,303 @@
+//! 进程、时间和信号相关的调用
+
+use arch::ContextOps;
+use log::debug;
+
+use super::fs::NOFILE_MAX;
+use super::{errno, ECHILD, EINTR, EINVAL, ENOENT, ENOMEM, ESRCH};
+use crate::config::USER_STACK_SIZE;
+use crate::mm::{UserPtr, UserSlice};
+use crate::syscall::memory as native_memory;
+use crate::syscall::process as native;
+use crate::syscall::sync::sys_sleep;
+use crate::task::{
+    check_signals_of_current, current_process, current_user_token, pid2process,
+    suspend_current_and_run_next, SignalFlags,
+};
+use crate::timer::get_time;
+
+const WNOHANG: usize = 1;
+
+/// mmap 里原来的接口认识的标志，MAP_NORESERVE、MAP_STACK 之类的提示直接忽略
+const MMAP_FLAGS_MASK: usize = 0x33;
+
+const RLIMIT_STACK: usize = 3;
+const RLIMIT_NOFILE: usize = 7;
+const RLIM_INFINITY: u64 = u64::MAX;
+
+/// 内核的 struct sigaction，aarch64 和 x86_64 多了 sa_restorer
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+const SIGACTION_SIZE: usize = 32;
+#[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+const SIGACTION_SIZE: usize = 24;
+
+#[cfg(target_arch = "riscv64")]
+const MACHINE: &str = "riscv64";
+#[cfg(target_arch = "aarch64")]
+const MACHINE: &str = "aarch64";
+#[cfg(target_arch = "x86_64")]
+const MACHINE: &str = "x86_64";
+#[cfg(target_arch = "loongarch64")]
+const MACHINE: &str = "loongarch64";
+
+bitflags! {
+    struct CloneFlags: usize {
+        const VM = 0x100;
+        const VFORK = 0x4000;
+        const THREAD = 0x10000;
+        const SETTLS = 0x80000;
+        const PARENT_SETTID = 0x100000;
+    }
+}
+
+/// struct utsname 的 6 个字段：系统、主机名、版本号、版本、架构、域名
+pub type UtsName = [[u8; 65]; 6];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeSpec {
+    sec: usize,
+    nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeVal {
+    sec: usize,
+    usec: usize,
+}
+
+/// 和 Linux 一样只保留退出码的低 8 位，负的退出码留给被信号杀死的进程
+pub fn sys_exit(exit_code: i32) -> ! {
+    native::sys_exit(exit_code & 0xff)
+}
+
+/// 只能创建进程，不能共享地址空间。CLONE_VM | CLONE_VFORK 的 vfork 按写时复制的 fork 处理，
+/// vfork 之后只做 exec 或者 _exit 的子进程看不出区别，单独的 CLONE_VM 返回 EINVAL
+pub fn sys_clone(args: [usize; 6]) -> isize {
+    // x86_64 的 tls 和 ctid 两个参数是反过来的
+    #[cfg(not(target_arch = "x86_64"))]
+    let (flags, newsp, ptid, tls) = (args[0], args[1], args[2], args[3]);
+    #[cfg(target_arch = "x86_64")]
+    let (flags, newsp, ptid, tls) = (args[0], args[1], args[2], args[4]);
+    let flags = CloneFlags::from_bits_truncate(flags);
+    if flags.contains(CloneFlags::THREAD) {
+        return EINVAL;
+    }
+    if flags.contains(CloneFlags::VM) {
+        if !flags.contains(CloneFlags::VFORK) {
+            return EINVAL;
+        }
+        debug!("vfork with CLONE_VM, fall back to fork");
+    }
+    let child = current_process().fork();
+    let child_pid = child.getpid();
+    let child_inner = child.inner_exclusive_access();
+    let task = child_inner.tasks[0].as_ref().unwrap();
+    let trap_cx = task.inner_exclusive_access().get_trap_cx();
+    trap_cx.set_ret(0);
+    if newsp != 0 {
+        trap_cx.set_sp(newsp);
+    }
+    if flags.contains(CloneFlags::SETTLS) {
+        trap_cx.set_tls(tls);
+    }
+    drop(child_inner);
+    if flags.contains(CloneFlags::PARENT_SETTID) {
+        let ptid = UserPtr::new(current_user_token(), ptid as *const i32);
+        if let Err(err) = ptid.write(child_pid as i32) {
+            return err;
+        }
+    }
+    child_pid as isize
+}
+
+/// 原来的接口成功时返回 argc，会被写进新程序的返回值寄存器，Linux 的程序在入口处看到的是 0
+pub fn sys_execve(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    match native::sys_exec(path, args, envs) {
+        argc if argc >= 0 => 0,
+        // 只有找不到文件时是 -1，其他错误已经是 Linux 的错误码
+        -1 => ENOENT,
+        err => err,
+    }
+}
+
+/// 没有进程组，`pid` 为 0 或者小于 -1 时等任意子进程
+pub fn sys_wait4(pid: isize, wstatus: *mut i32, options: usize) -> isize {
+    let pid = if pid > 0 { pid } else { -1 };
+    loop {
+        match native::reap_child(pid) {
+            Ok(Some((found_pid, exit_code))) => {
+                // 正常退出时退出码在第二个字节，被信号杀死时低 7 位是信号
+                let status = if exit_code >= 0 {
+                    (exit_code & 0xff) << 8
+                } else {
+                    -exit_code & 0x7f
+                };
+                if !wstatus.is_null() {
+                    if let Err(err) = UserPtr::new(current_user_token(), wstatus).write(status) {
+                        return err;
+                    }
+                }
+                return found_pid as isize;
+            }
+            Ok(None) if options & WNOHANG != 0 => return 0,
+            Ok(None) => {
+                if check_signals_of_current().is_some() {
+                    return EINTR;
+                }
+                suspend_current_and_run_next();
+            }
+            Err(()) => return ECHILD,
+        }
+    }
+}
+
+/// 只有 [SignalFlags] 里的信号能发，`sig` 为 0 时只检查进程是否存在
+pub fn sys_kill(pid: isize, sig: usize) -> isize {
+    if pid <= 0 {
+        return EINVAL;
+    }
+    let process = match pid2process(pid as usize) {
+        Some(process) => process,
+        None => return ESRCH,
+    };
+    if sig == 0 {
+        return 0;
+    }
+    if sig >= 32 {
+        return EINVAL;
+    }
+    match SignalFlags::from_bits(1 << sig) {
+        Some(flag) => {
+            process.inner_exclusive_access().signals |= flag;
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+pub fn sys_getppid() -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid() as isize)
+}
+
+/// 还不支持信号处理函数，libc 初始化时设置的都当作成功，原来的设置都是默认
+pub fn sys_rt_sigaction(old_act: *mut u8) -> isize {
+    if old_act.is_null() {
+        return 0;
+    }
+    let old_act = UserSlice::new(current_user_token(), old_act, SIGACTION_SIZE);
+    old_act
+        .copy_from(&[0; SIGACTION_SIZE])
+        .map_or_else(|err| err, |()| 0)
+}
+
+pub fn sys_rt_sigprocmask(old_set: *mut u64) -> isize {
+    if old_set.is_null() {
+        return 0;
+    }
+    UserPtr::new(current_user_token(), old_set)
+        .write(0)
+        .map_or_else(|err| err, |()| 0)
+}
+
+pub fn sys_uname(buf: *mut UtsName) -> isize {
+    let mut uts: UtsName = [[0; 65]; 6];
+    for (field, value) in uts
+        .iter_mut()
+        .zip(["Linux", "rcore", "5.15.0", "#1", MACHINE, ""])
+    {
+        field[..value.len()].copy_from_slice(value.as_bytes());
+    }
+    UserPtr::new(current_user_token(), buf)
+        .write(uts)
+        .map_or_else(|err| err, |()| 0)
+}
+
+/// 只能查询，设置的限制不起作用
+pub fn sys_prlimit64(resource: usize, old_limit: *mut [u64; 2]) -> isize {
+    if old_limit.is_null() {
+        return 0;
+    }
+    let limit = match resource {
+        RLIMIT_STACK => USER_STACK_SIZE as u64,
+        RLIMIT_NOFILE => NOFILE_MAX as u64,
+        _ => RLIM_INFINITY,
+    };
+    UserPtr::new(current_user_token(), old_limit)
+        .write([limit; 2])
+        .map_or_else(|err| err, |()| 0)
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    let ret = native_memory::sys_mmap(addr, len, prot, flags & MMAP_FLAGS_MASK, fd, offset);
+    errno(ret, ENOMEM)
+}
+
+fn uptime_usec() -> usize {
+    arch::time_to_usec(get_time())
+}
+
+/// 没有实时时钟，所有时钟都从开机时算起
+pub fn sys_clock_gettime(tp: *mut TimeSpec) -> isize {
+    let usec = uptime_usec();
+    let ts = TimeSpec {
+        sec: usec / 1_000_000,
+        nsec: usec % 1_000_000 * 1000,
+    };
+    UserPtr::new(current_user_token(), tp)
+        .write(ts)
+        .map_or_else(|err| err, |()| 0)
+}
+
+pub fn sys_gettimeofday(tv: *mut TimeVal) -> isize {
+    let usec = uptime_usec();
+    let tv_value = TimeVal {
+        sec: usec / 1_000_000,
+        usec: usec % 1_000_000,
+    };
+    UserPtr::new(current_user_token(), tv)
+        .write(tv_value)
+        .map_or_else(|err| err, |()| 0)
+}
+
+/// 定时器的精度是毫秒，不足一毫秒的部分向上取整，不会被信号打断
+pub fn sys_nanosleep(req: *const TimeSpec) -> isize {
+    let req = match UserPtr::new(current_user_token(), req).read() {
+        Ok(req) if req.nsec < 1_000_000_000 => req,
+        Ok(_) => return EINVAL,
+        Err(err) => return err,
+    };
+    sys_sleep(req.sec.saturating_mul(1000) + req.nsec.div_ceil(1_000_000))
+}
+
+/// musl 在 x86_64 上用它设置 TLS
+#[cfg(target_arch = "x86_64")]
+pub fn sys_arch_prctl(code: usize, addr: usize) -> isize {
+    const ARCH_SET_FS: usize = 0x1002;
+    const ARCH_GET_FS: usize = 0x1003;
+    let trap_cx = crate::task::current_trap_cx();
+    match code {
+        ARCH_SET_FS => {
+            trap_cx.set_tls(addr);
+            0
+        }
+        ARCH_GET_FS => UserPtr::new(current_user_token(), addr as *const usize)
+            .write(trap_cx.tls())
+            .map_or_else(|err| err, |()| 0),
+        _ => EINVAL,
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config::{MMAP_TOP, PAGE_SIZE};
+use crate::drivers::virtio::dma_pages;
+use crate::mm::swap::swap_space;
+use crate::mm::{frame_stats, MapPermission, MmapBacking, UserSlice, VirtAddr, VirtPage};
+use crate::task::{current_process, current_user_token};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+bitflags! {
+    struct MmapProt: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    struct MmapFlags: usize {
+        const SHARED = 1 << 0;
+        const PRIVATE = 1 << 1;
+        const FIXED = 1 << 4;
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+fn prot_to_permission(prot: usize) -> Option<MapPermission> {
+    let prot = MmapProt::from_bits(prot)?;
+    let mut permission = MapPermission::U;
+    // 只写的页表项在有些架构上不合法，可写就一定可读
+    if prot.intersects(MmapProt::READ | MmapProt::WRITE) {
+        permission |= MapPermission::R;
+    }
+    if prot.contains(MmapProt::WRITE) {
+        permission |= MapPermission::W;
+    }
+    if prot.contains(MmapProt::EXEC) {
+        permission |= MapPermission::X;
+    }
+    Some(permission)
+}
+
+/// 检查 `[addr, addr + len)` 并转换成页号范围，`addr` 必须页对齐
+fn page_range(addr: usize, len: usize) -> Option<(VirtPage, VirtPage)> {
+    if addr % PAGE_SIZE != 0 || len == 0 || addr.checked_add(len)? > MMAP_TOP {
+        return None;
+    }
+    Some((
+        VirtAddr::from(addr).floor(),
+        VirtAddr::from(addr + len).ceil(),
+    ))
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    let (permission, flags) = match (prot_to_permission(prot), MmapFlags::from_bits(flags)) {
+        (Some(permission), Some(flags)) => (permission, flags),
+        _ => return -1,
+    };
+    // SHARED 和 PRIVATE 必须且只能指定一个
+    let shared = flags.contains(MmapFlags::SHARED);
+    if len == 0 || offset % PAGE_SIZE != 0 || shared == flags.contains(MmapFlags::PRIVATE) {
+        return -1;
+    }
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    let process = current_process();
+    let backing = if flags.contains(MmapFlags::ANONYMOUS) {
+        if shared {
+            MmapBacking::SharedAnonymous
+        } else {
+            MmapBacking::Anonymous
+        }
+    } else {
+        let file = match process.inner_exclusive_access().fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return -1,
+        };
+        let os_inode = match file.as_os_inode() {
+            Some(os_inode) => os_inode,
+            None => return -1,
+        };
+        if !file.readable() || (shared && permission.contains(MapPermission::W) && !file.writable())
+        {
+            return -1;
+        }
+        let inode = os_inode.inode();
+        MmapBacking::File {
+            len: inode.size().saturating_sub(offset).min(pages * PAGE_SIZE),
+            inode,
+            offset,
+            shared,
+        }
+    };
+    let mut inner = process.inner_exclusive_access();
+    let mut writeback = Vec::new();
+    let start = if flags.contains(MmapFlags::FIXED) {
+        // 覆盖掉范围里原有的映射
+        let (start, end) = match page_range(addr, len) {
+            Some(range) if addr != 0 => range,
+            _ => return -1,
+        };
+        writeback = inner.memory_set.shared_file_pages(start, end);
+        inner.memory_set.remove_range(start, end);
+        start
+    } else {
+        // 地址只是个建议，被占用了就另找一段
+        match page_range(addr, len) {
+            Some((start, end)) if addr != 0 && inner.memory_set.is_free(start, end) => start,
+            _ => match inner.memory_set.find_free_area(pages) {
+                Some(start) => start.floor(),
+                None => return -1,
+            },
+        }
+    };
+    let mapped = inner.memory_set.insert_mmap_area(
+        start.into(),
+        (start + pages).into(),
+        permission,
+        backing,
+    );
+    drop(inner);
+    for page in writeback {
+        page.write_back();
+    }
+    if !mapped {
+        return -1;
+    }
+    let start_va: usize = VirtAddr::from(start).into();
+    start_va as isize
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    let (start, end) = match page_range(addr, len) {
+        Some(range) => range,
+        None => return -1,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let writeback = inner.memory_set.shared_file_pages(start, end);
+    inner.memory_set.remove_range(start, end);
+    drop(inner);
+    // 写文件可能会让出 CPU，不能拿着进程的锁
+    for page in writeback {
+        page.write_back();
+    }
+    0
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    let permission = match prot_to_permission(prot) {
+        Some(permission) => permission,
+        None => return -1,
+    };
+    let (start, end) = match page_range(addr, len) {
+        Some(range) => range,
+        None => return -1,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.protect_range(start, end, permission) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// 和 Linux 一样，参数为 0 或者移动失败时返回当前的堆顶
+pub fn sys_brk(addr: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if addr != 0 {
+        inner.memory_set.set_brk(addr);
+    }
+    inner.memory_set.brk() as isize
+}
+
+/// 堆顶移动 `increment` 字节，返回原来的堆顶，失败返回 -1
+pub fn sys_sbrk(increment: isize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old_brk = inner.memory_set.brk();
+    match old_brk.checked_add_signed(increment) {
+        Some(new_brk) if inner.memory_set.set_brk(new_brk) => old_brk as isize,
+        _ => -1,
+    }
+}
+
+/// 把 /proc/meminfo 格式的内存统计写进 `buf`，超出 `len` 的部分截掉，返回写入的字节数
+pub fn sys_meminfo(buf: *mut u8, len: usize) -> isize {
+    let stats = frame_stats();
+    let (swap_total, swap_free) = swap_space();
+    let caches = allocator::cache_stats();
+    let heap = allocator::heap_stats();
+    let slabs: usize = caches.iter().map(|cache| cache.slabs).sum();
+    let mut text = String::new();
+    for (name, bytes) in [
+        ("MemTotal", stats.total * PAGE_SIZE),
+        ("MemFree", stats.free * PAGE_SIZE),
+        ("DmaUsed", dma_pages() * PAGE_SIZE),
+        ("SwapTotal", swap_total * PAGE_SIZE),
+        ("SwapFree", swap_free * PAGE_SIZE),
+        ("Slab", slabs * allocator::SLAB_SIZE),
+        ("HeapTotal", heap.total),
+        ("HeapUsed", heap.used),
+    ] {
+        let kb = bytes / 1024;
+        writeln!(text, "{}:{:>w$} kB", name, kb, w = 16 - name.len()).unwrap();
+    }
+    // 可用的 ASID 个数和换过几代
+    let (asids, generation) = arch::asid_stats();
+    for (name, value) in [("Asids", asids), ("AsidGeneration", generation)] {
+        writeln!(text, "{}:{:>w$}", name, value, w = 16 - name.len()).unwrap();
+    }
+    // 和 /proc/buddyinfo 一样，按 order 从小到大列出空闲块数
+    text.push_str("FreeBlocks:");
+    for count in stats.free_blocks {
+        write!(text, " {}", count).unwrap();
+    }
+    text.push('\n');
+    // 每个 slab 缓存：使用中的对象、总对象数、slab 数
+    for cache in caches {
+        let name = format!("kmalloc-{}", cache.object_size);
+        let width = 16 - name.len();
+        writeln!(
+            text,
+            "{}:{:>width$} {} {}",
+            name, cache.in_use, cache.capacity, cache.slabs
+        )
+        .unwrap();
+    }
+
+    let len = len.min(text.len());
+    match UserSlice::new(current_user_token(), buf, len).copy_from(text.as_bytes()) {
+        Ok(()) => len as isize,
+        Err(err) => err,
+    }
+}
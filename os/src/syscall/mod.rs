@@ -9,12 +9,21 @@ const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
+const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
@@ -32,10 +41,15 @@ const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_SBRK: usize = 4000;
+const SYSCALL_MEMINFO: usize = 4001;
 
 mod fs;
 mod gui;
 mod input;
+#[cfg(feature = "linux-abi")]
+mod linux;
+mod memory;
 mod net;
 mod process;
 mod sync;
@@ -44,12 +58,26 @@ mod thread;
 use fs::*;
 use gui::*;
 use input::*;
+use memory::*;
 use net::*;
 use process::*;
 use sync::*;
 use thread::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+use log::warn;
+
+/// 不支持的系统调用的返回值，linux-abi 下和 Linux 一样是 ENOSYS
+#[cfg(feature = "linux-abi")]
+const UNSUPPORTED: isize = linux::ENOSYS;
+#[cfg(not(feature = "linux-abi"))]
+const UNSUPPORTED: isize = -1;
+
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    // 两套编号有重叠，打开 linux-abi 之后重叠的部分按 Linux 的处理
+    #[cfg(feature = "linux-abi")]
+    if let Some(ret) = linux::syscall(syscall_id, args) {
+        return ret;
+    }
     match syscall_id {
         SYSCALL_DUP => sys_dup(args[0]),
         SYSCALL_CONNECT => sys_connect(args[0] as _, args[1] as _, args[2] as _),
@@ -62,12 +90,31 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_SCHED_SETSCHEDULER => {
+            sys_sched_setscheduler(args[0], args[1], args[2] as *const SchedParam)
+        }
+        SYSCALL_SCHED_GETSCHEDULER => sys_sched_getscheduler(args[0]),
+        SYSCALL_SCHED_SETAFFINITY => {
+            sys_sched_setaffinity(args[0], args[1], args[2] as *const usize)
+        }
+        SYSCALL_SCHED_GETAFFINITY => {
+            sys_sched_getaffinity(args[0], args[1], args[2] as *mut usize)
+        }
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
+        SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
+        SYSCALL_EXEC => sys_exec(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
@@ -85,6 +132,11 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_FRAMEBUFFER_FLUSH => sys_framebuffer_flush(),
         SYSCALL_EVENT_GET => sys_event_get(),
         SYSCALL_KEY_PRESSED => sys_key_pressed(),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_SBRK => sys_sbrk(args[0] as isize),
+        SYSCALL_MEMINFO => sys_meminfo(args[0] as *mut u8, args[1]),
+        _ => {
+            warn!("Unsupported syscall_id: {}", syscall_id);
+            UNSUPPORTED
+        }
     }
 }
@@ -1,3 +1,4 @@
+use arch::ContextOps;
 use crate::net::port_table::{accept, listen, port_acceptable, PortFd};
 use crate::net::udp::UDP;
 use crate::net::{net_interrupt_handler, IPv4};
@@ -49,5 +50,5 @@ pub fn sys_accept(port_index: usize) -> isize {
     }
 
     let cx = current_trap_cx();
-    cx.x[10] as isize
+    cx.ret() as isize
 }
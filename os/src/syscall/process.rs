@@ -1,13 +1,18 @@
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use arch::ContextOps;
+use crate::config::USER_STACK_SIZE;
+use crate::mm::UserPtr;
+use crate::task::binfmt::E2BIG;
 use crate::task::{
-    current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
-    suspend_current_and_run_next, SignalFlags,
+    block_current_and_run_next_with, current_process, current_task, current_user_token,
+    exit_current_and_run_next, load_program, pid2process, requeue_task,
+    suspend_current_and_run_next, SchedPolicy, SignalFlags, TaskControlBlock, MIN_PRIORITY,
+    RT_PRIORITY_MAX,
 };
-use crate::timer::get_time_ms;
+use crate::timer::{add_timer, get_time_ms};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
 
 pub fn sys_exit(exit_code: i32) -> ! {
     exit_current_and_run_next(exit_code);
@@ -15,7 +20,19 @@ pub fn sys_exit(exit_code: i32) -> ! {
 }
 
 pub fn sys_yield() -> isize {
-    suspend_current_and_run_next();
+    let task = current_task().unwrap();
+    let mut sched = task.sched.exclusive_access();
+    if sched.policy == SchedPolicy::Deadline {
+        // SCHED_DEADLINE 的任务 yield 表示本周期已经完成，睡到下一个周期再释放
+        let release = sched.finish_period();
+        drop(sched);
+        drop(task);
+        block_current_and_run_next_with(|task| add_timer(release, task));
+    } else {
+        drop(sched);
+        drop(task);
+        suspend_current_and_run_next();
+    }
     0
 }
 
@@ -37,39 +54,62 @@ pub fn sys_fork() -> isize {
     let trap_cx = task.inner_exclusive_access().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
-    trap_cx.x[10] = 0;
+    trap_cx.set_ret(0);
     new_pid as isize
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    let mut args_vec: Vec<String> = Vec::new();
+/// 读用户传来的以空指针结尾的字符串指针数组，`ptr` 为空时当作空数组
+fn read_str_array(token: usize, mut ptr: *const usize) -> Result<Vec<String>, isize> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
-        if arg_str_ptr == 0 {
-            break;
-        }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
-        unsafe {
-            args = args.add(1);
+        match UserPtr::new(token, ptr).read()? {
+            0 => return Ok(strings),
+            str_ptr => strings.push(UserPtr::new(token, str_ptr as *const u8).read_str()?),
         }
+        ptr = ptr.wrapping_add(1);
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let process = current_process();
-        let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
-    } else {
-        -1
+}
+
+pub fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let token = current_user_token();
+    let path = match UserPtr::new(token, path).read_str() {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+    let (args_vec, envs_vec) = match (read_str_array(token, args), read_str_array(token, envs)) {
+        (Ok(args), Ok(envs)) => (args, envs),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let image = match load_program(&path, args_vec) {
+        Ok(image) => image,
+        Err(err) => return err,
+    };
+    // 参数和环境变量都要放到新程序的用户栈上，留一半给程序自己用
+    let size: usize = image
+        .args
+        .iter()
+        .chain(envs_vec.iter())
+        .map(|s| s.len() + 1 + core::mem::size_of::<usize>())
+        .sum();
+    if size > USER_STACK_SIZE / 2 {
+        return E2BIG;
     }
+    let process = current_process();
+    let argc = image.args.len();
+    if let Err(err) = process.exec(image.elf_data, image.interp_data, image.args, envs_vec) {
+        return err;
+    }
+    // return argc because the return value register will be covered with it later
+    argc as isize
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// 回收一个已经退出的子进程，返回它的 pid 和退出码，`pid` 为 -1 时可以是任意子进程
+///
+/// 没有符合条件的子进程时返回 Err，它们都还在运行时返回 Ok(None)
+pub(super) fn reap_child(pid: isize) -> Result<Option<(usize, i32)>, ()> {
     let process = current_process();
     // find a child process
 
@@ -79,7 +119,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return Err(());
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
@@ -95,14 +135,30 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        Ok(Some((found_pid, exit_code)))
     } else {
-        -2
+        Ok(None)
     }
     // ---- release current PCB automatically
 }
 
+/// If there is not a child process whose pid is same as given, return -1.
+/// Else if there is a child process but it is still running, return -2.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    let (found_pid, exit_code) = match reap_child(pid) {
+        Ok(Some(child)) => child,
+        Ok(None) => return -2,
+        Err(()) => return -1,
+    };
+    // 和 Linux 一样，传空指针表示不关心退出码
+    if !exit_code_ptr.is_null() {
+        if let Err(err) = UserPtr::new(current_user_token(), exit_code_ptr).write(exit_code) {
+            return err;
+        }
+    }
+    found_pid as isize
+}
+
 pub fn sys_kill(pid: usize, signal: u32) -> isize {
     if let Some(process) = pid2process(pid) {
         if let Some(flag) = SignalFlags::from_bits(signal) {
@@ -115,3 +171,124 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
         -1
     }
 }
+
+/// 设置当前线程的优先级，数值越大越优先，成功时返回设置的值
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio < MIN_PRIORITY as isize {
+        return -1;
+    }
+    current_task().unwrap().sched.exclusive_access().priority = prio as usize;
+    prio
+}
+
+/// pid 为 0 表示当前线程，否则是该进程的主线程
+fn sched_target(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    if pid == 0 {
+        return current_task();
+    }
+    let process = pid2process(pid)?;
+    let process_inner = process.inner_exclusive_access();
+    process_inner.tasks.first().cloned().flatten()
+}
+
+pub fn sys_sched_setaffinity(pid: usize, cpusetsize: usize, mask: *const usize) -> isize {
+    if cpusetsize < core::mem::size_of::<usize>() {
+        return -1;
+    }
+    let mask = match UserPtr::new(current_user_token(), mask).read() {
+        Ok(mask) => mask & arch::cpu_online_mask(),
+        Err(err) => return err,
+    };
+    // 至少要包含一个已经上线的核
+    if mask == 0 {
+        return -1;
+    }
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    task.cpu_mask.store(mask, Ordering::Relaxed);
+    // 当前核不在新的集合里，让出 CPU，重新入队时会换到允许的核上
+    if pid == 0 && !task.can_run_on(arch::hart_id()) {
+        drop(task);
+        suspend_current_and_run_next();
+    }
+    0
+}
+
+pub fn sys_sched_getaffinity(pid: usize, cpusetsize: usize, mask: *mut usize) -> isize {
+    if cpusetsize < core::mem::size_of::<usize>() {
+        return -1;
+    }
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    let online = task.cpu_mask.load(Ordering::Relaxed) & arch::cpu_online_mask();
+    if let Err(err) = UserPtr::new(current_user_token(), mask).write(online) {
+        return err;
+    }
+    // 和 Linux 一样返回写入的字节数
+    core::mem::size_of::<usize>() as isize
+}
+
+/// sched_setscheduler 的参数，SCHED_DEADLINE 用 period 和 deadline，其他实时策略只用 priority
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SchedParam {
+    pub priority: usize,
+    pub period_ms: usize,
+    pub deadline_ms: usize,
+}
+
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: *const SchedParam) -> isize {
+    let policy = match SchedPolicy::from_usize(policy) {
+        Some(policy) => policy,
+        None => return -1,
+    };
+    let param = match UserPtr::new(current_user_token(), param).read() {
+        Ok(param) => param,
+        Err(err) => return err,
+    };
+    let valid = match policy {
+        SchedPolicy::Normal => true,
+        SchedPolicy::Fifo | SchedPolicy::RoundRobin => {
+            (1..=RT_PRIORITY_MAX).contains(&param.priority)
+        }
+        SchedPolicy::Deadline => {
+            param.period_ms > 0 && param.deadline_ms > 0 && param.deadline_ms <= param.period_ms
+        }
+    };
+    if !valid {
+        return -1;
+    }
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -1,
+    };
+    let set_policy = || {
+        task.sched.exclusive_access().set_policy(
+            policy,
+            param.priority,
+            param.period_ms,
+            param.deadline_ms,
+        )
+    };
+    if pid == 0 {
+        // 当前任务不在就绪队列里，改完马上重新入队
+        set_policy();
+        drop(task);
+        suspend_current_and_run_next();
+    } else {
+        // 已经在就绪队列里的任务要换到新策略对应的队列和位置
+        requeue_task(&task, set_policy);
+    }
+    0
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    match sched_target(pid) {
+        Some(task) => task.sched.exclusive_access().policy as isize,
+        None => -1,
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{
-    mm::kernel_token,
     task::{add_task, current_task, TaskControlBlock},
-    trap::{trap_handler, TrapContext},
+    trap::app_init_context,
 };
 use alloc::sync::Arc;
+use arch::ContextOps;
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     let task = current_task().unwrap();
@@ -20,9 +20,10 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     ));
     // add new task to scheduler
     add_task(Arc::clone(&new_task));
-    let new_task_inner = new_task.inner_exclusive_access();
+    let mut new_task_inner = new_task.inner_exclusive_access();
     let new_task_res = new_task_inner.res.as_ref().unwrap();
     let new_task_tid = new_task_res.tid;
+    let new_task_ustack_top = new_task_res.ustack_top();
     let mut process_inner = process.inner_exclusive_access();
     // add new thread to current process
     let tasks = &mut process_inner.tasks;
@@ -31,14 +32,8 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     }
     tasks[new_task_tid] = Some(Arc::clone(&new_task));
     let new_task_trap_cx = new_task_inner.get_trap_cx();
-    *new_task_trap_cx = TrapContext::app_init_context(
-        entry,
-        new_task_res.ustack_top(),
-        kernel_token(),
-        new_task.kstack.get_top(),
-        trap_handler as usize,
-    );
-    (*new_task_trap_cx).x[10] = arg;
+    *new_task_trap_cx = app_init_context(entry, new_task_ustack_top);
+    new_task_trap_cx.set_arg0(arg);
     new_task_tid as isize
 }
 
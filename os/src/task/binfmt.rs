@@ -0,0 +1,163 @@
+//! 可执行文件格式，exec 按文件开头的魔数选择装载方式
+//!
+//! 内置 ELF 和 `#!` 脚本两种格式，其他格式通过 [register_binfmt] 加进来
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+use crate::fs::{open_file, OpenFlags};
+use crate::mm::{elf_interpreter, elf_supported};
+use crate::sync::UPIntrFreeCell;
+
+/// 参数和环境变量太多，新程序的用户栈放不下
+pub const E2BIG: isize = -7;
+/// 不是能执行的文件格式，和 Linux 的错误码一致
+pub const ENOEXEC: isize = -8;
+/// 脚本的解释器套了太多层
+pub const ELOOP: isize = -40;
+
+/// 脚本最多能套几层解释器，和 Linux 一样
+const MAX_REEXEC: usize = 4;
+/// `#!` 那一行最多看这么长
+const SHEBANG_MAX: usize = 256;
+
+/// 可以直接交给 [ProcessControlBlock::exec] 的程序
+///
+/// [ProcessControlBlock::exec]: super::ProcessControlBlock::exec
+pub struct ExecImage {
+    pub elf_data: Arc<Vec<u8>>,
+    /// 动态链接的程序的解释器
+    pub interp_data: Option<Arc<Vec<u8>>>,
+    pub args: Vec<String>,
+}
+
+/// 一种格式处理完之后的结果
+pub enum BinfmtResult {
+    Image(ExecImage),
+    /// 换成另一个程序和参数重新查找格式，脚本用它交给解释器执行
+    Reexec {
+        path: String,
+        args: Vec<String>,
+    },
+}
+
+/// 一种可执行文件格式
+pub trait Binfmt: Send + Sync {
+    /// 根据文件开头判断是不是这种格式
+    fn matches(&self, data: &[u8]) -> bool;
+    /// `path` 是要执行的文件，`args` 是用户传来的参数
+    fn load(&self, path: &str, data: Vec<u8>, args: Vec<String>) -> Result<BinfmtResult, isize>;
+}
+
+struct ElfBinfmt;
+
+impl Binfmt for ElfBinfmt {
+    fn matches(&self, data: &[u8]) -> bool {
+        data.starts_with(b"\x7fELF")
+    }
+
+    fn load(&self, _path: &str, data: Vec<u8>, args: Vec<String>) -> Result<BinfmtResult, isize> {
+        if !elf_supported(&data) {
+            return Err(ENOEXEC);
+        }
+        // 动态链接的程序还要装载解释器
+        let interp_data = match elf_interpreter(&data) {
+            Some(interp) => {
+                let interp_data = read_file(&interp)?;
+                if !elf_supported(&interp_data) {
+                    return Err(ENOEXEC);
+                }
+                Some(Arc::new(interp_data))
+            }
+            None => None,
+        };
+        Ok(BinfmtResult::Image(ExecImage {
+            elf_data: Arc::new(data),
+            interp_data,
+            args,
+        }))
+    }
+}
+
+/// `#!interpreter [arg]` 开头的脚本，和 Linux 一样，解释器后面的内容整体作为一个参数
+struct ScriptBinfmt;
+
+impl Binfmt for ScriptBinfmt {
+    fn matches(&self, data: &[u8]) -> bool {
+        data.starts_with(b"#!")
+    }
+
+    fn load(&self, path: &str, data: Vec<u8>, args: Vec<String>) -> Result<BinfmtResult, isize> {
+        let line = &data[2..data.len().min(SHEBANG_MAX)];
+        let line = line.split(|&ch| ch == b'\n').next().unwrap();
+        let line = core::str::from_utf8(line).map_err(|_| ENOEXEC)?.trim();
+        let (interp, arg) = match line.split_once(|ch: char| ch == ' ' || ch == '\t') {
+            Some((interp, arg)) => (interp, Some(arg.trim())),
+            None => (line, None),
+        };
+        if interp.is_empty() {
+            return Err(ENOEXEC);
+        }
+        // 解释器、可选的参数、脚本的路径，然后是原来除了 argv[0] 以外的参数
+        let mut new_args = vec![interp.to_string()];
+        new_args.extend(arg.map(String::from));
+        new_args.push(path.to_string());
+        new_args.extend(args.into_iter().skip(1));
+        Ok(BinfmtResult::Reexec {
+            path: interp.to_string(),
+            args: new_args,
+        })
+    }
+}
+
+lazy_static! {
+    static ref BINFMTS: UPIntrFreeCell<Vec<Arc<dyn Binfmt>>> = unsafe {
+        UPIntrFreeCell::new(vec![
+            Arc::new(ElfBinfmt) as Arc<dyn Binfmt>,
+            Arc::new(ScriptBinfmt),
+        ])
+    };
+}
+
+/// 加入一种可执行文件格式，排在已有的格式后面
+#[allow(unused)]
+pub fn register_binfmt(binfmt: Arc<dyn Binfmt>) {
+    BINFMTS.exclusive_access().push(binfmt);
+}
+
+/// easy-fs 只有根目录，路径只看最后一段，找不到文件时返回 -1
+fn read_file(path: &str) -> Result<Vec<u8>, isize> {
+    let name = path.rsplit('/').next().unwrap();
+    open_file(name, OpenFlags::RDONLY)
+        .map(|inode| inode.read_all())
+        .ok_or(-1)
+}
+
+/// 读出 `path` 对应的程序，按格式一层层处理，直到得到可以直接装载的 ELF
+pub fn load_program(path: &str, args: Vec<String>) -> Result<ExecImage, isize> {
+    let (mut path, mut args) = (path.to_string(), args);
+    for _ in 0..=MAX_REEXEC {
+        let data = read_file(&path)?;
+        // 装载时可能还要读文件，不能拿着锁
+        let binfmt = BINFMTS
+            .exclusive_access()
+            .iter()
+            .find(|binfmt| binfmt.matches(&data))
+            .cloned()
+            .ok_or(ENOEXEC)?;
+        match binfmt.load(&path, data, args)? {
+            BinfmtResult::Image(image) => return Ok(image),
+            BinfmtResult::Reexec {
+                path: new_path,
+                args: new_args,
+            } => {
+                path = new_path;
+                args = new_args;
+            }
+        }
+    }
+    Err(ELOOP)
+}
@@ -1,6 +1,8 @@
 use super::ProcessControlBlock;
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
-use crate::mm::{MapPermission, PhysPage, VirtAddr, KERNEL_SPACE};
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, USER_STACK_SIZE};
+use crate::mm::{
+    add_guard_page, frame_alloc_more, remove_guard_page, FrameTracker, MemorySet, VirtAddr,
+};
 use crate::sync::UPIntrFreeCell;
 use alloc::{
     sync::{Arc, Weak},
@@ -42,8 +44,6 @@ impl RecycleAllocator {
 lazy_static! {
     static ref PID_ALLOCATOR: UPIntrFreeCell<RecycleAllocator> =
         unsafe { UPIntrFreeCell::new(RecycleAllocator::new()) };
-    static ref KSTACK_ALLOCATOR: UPIntrFreeCell<RecycleAllocator> =
-        unsafe { UPIntrFreeCell::new(RecycleAllocator::new()) };
 }
 
 pub const IDLE_PID: usize = 0;
@@ -60,35 +60,17 @@ impl Drop for PidHandle {
     }
 }
 
-/// Return (bottom, top) of a kernel stack in kernel space.
-pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
-    let bottom = top - KERNEL_STACK_SIZE;
-    (bottom, top)
-}
-
-pub struct KernelStack(pub usize);
+/// 内核栈直接使用物理页帧，通过线性映射访问，因此在所有地址空间中都可见
+///
+/// 栈底下面多分配一页作为保护页，从线性映射里去掉，栈溢出时触发缺页而不是写坏相邻的页帧
+pub struct KernelStack(Vec<FrameTracker>);
 
 pub fn kstack_alloc() -> KernelStack {
-    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
-    let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
-    KERNEL_SPACE.exclusive_access().insert_framed_area(
-        kstack_bottom.into(),
-        kstack_top.into(),
-        MapPermission::R | MapPermission::W,
-    );
-    KernelStack(kstack_id)
-}
-
-impl Drop for KernelStack {
-    fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
-        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
-        KERNEL_SPACE
-            .exclusive_access()
-            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
-        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.0);
+    let frames = frame_alloc_more(KERNEL_STACK_SIZE / PAGE_SIZE + 1).unwrap();
+    if arch::KERNEL_PAGED {
+        add_guard_page(frames.last().unwrap().ppn);
     }
+    KernelStack(frames)
 }
 
 impl KernelStack {
@@ -105,8 +87,21 @@ impl KernelStack {
         ptr_mut
     }
     pub fn get_top(&self) -> usize {
-        let (_, kernel_stack_top) = kernel_stack_position(self.0);
-        kernel_stack_top
+        // frame_alloc_more 分配的页帧是连续的，第一个是最高的一页
+        (self.0[0].ppn.to_addr() | arch::VIRT_ADDR_START) + PAGE_SIZE
+    }
+    /// 栈底下面的保护页的地址
+    pub fn guard_page(&self) -> usize {
+        self.get_top() - KERNEL_STACK_SIZE - PAGE_SIZE
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        // 页帧回收之后会通过线性映射清零，先恢复映射
+        if arch::KERNEL_PAGED {
+            remove_guard_page(self.0.last().unwrap().ppn);
+        }
     }
 }
 
@@ -116,14 +111,22 @@ pub struct TaskUserRes {
     pub process: Weak<ProcessControlBlock>,
 }
 
-fn trap_cx_bottom_from_tid(tid: usize) -> usize {
-    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
-}
-
 fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
     ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
 }
 
+/// 在 `memory_set` 里映射线程 `tid` 的用户栈，返回栈顶
+///
+/// exec 时先映射到还没换上的新地址空间里
+pub fn map_user_stack(memory_set: &mut MemorySet, ustack_base: usize, tid: usize) -> usize {
+    let ustack_bottom = ustack_bottom_from_tid(ustack_base, tid);
+    let ustack_top = ustack_bottom + USER_STACK_SIZE;
+    // 用户栈用到哪一页才分配哪一页
+    let perm = memory_set.stack_permission();
+    memory_set.insert_lazy_area(ustack_bottom.into(), ustack_top.into(), perm);
+    ustack_top
+}
+
 impl TaskUserRes {
     pub fn new(
         process: Arc<ProcessControlBlock>,
@@ -146,21 +149,7 @@ impl TaskUserRes {
         let process = self.process.upgrade().unwrap();
         let mut process_inner = process.inner_exclusive_access();
         // alloc user stack
-        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
-        let ustack_top = ustack_bottom + USER_STACK_SIZE;
-        process_inner.memory_set.insert_framed_area(
-            ustack_bottom.into(),
-            ustack_top.into(),
-            MapPermission::R | MapPermission::W | MapPermission::U,
-        );
-        // alloc trap_cx
-        let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
-        let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
-        process_inner.memory_set.insert_framed_area(
-            trap_cx_bottom.into(),
-            trap_cx_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
+        map_user_stack(&mut process_inner.memory_set, self.ustack_base, self.tid);
     }
 
     fn dealloc_user_res(&self) {
@@ -172,11 +161,6 @@ impl TaskUserRes {
         process_inner
             .memory_set
             .remove_area_with_start_vpn(ustack_bottom_va.into());
-        // dealloc trap_cx manually
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
-        process_inner
-            .memory_set
-            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
     }
 
     #[allow(unused)]
@@ -195,21 +179,6 @@ impl TaskUserRes {
         process_inner.dealloc_tid(self.tid);
     }
 
-    pub fn trap_cx_user_va(&self) -> usize {
-        trap_cx_bottom_from_tid(self.tid)
-    }
-
-    pub fn trap_cx_ppn(&self) -> PhysPage {
-        let process = self.process.upgrade().unwrap();
-        let process_inner = process.inner_exclusive_access();
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
-        process_inner
-            .memory_set
-            .translate(trap_cx_bottom_va.into())
-            .unwrap()
-            .to_ppn()
-    }
-
     pub fn ustack_base(&self) -> usize {
         self.ustack_base
     }
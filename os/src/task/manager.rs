@@ -1,37 +1,65 @@
+use super::scheduler::{RunQueue, Scheduler};
 use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
 use crate::sync::UPIntrFreeCell;
-use alloc::collections::{BTreeMap, VecDeque};
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
-pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
-}
+/// 每个核自己的就绪队列
+#[percpu::def_percpu]
+static RUN_QUEUE: UPIntrFreeCell<RunQueue> = unsafe { UPIntrFreeCell::new(RunQueue::new()) };
 
-/// A simple FIFO scheduler.
-impl TaskManager {
-    pub fn new() -> Self {
-        Self {
-            ready_queue: VecDeque::new(),
-        }
-    }
-    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
-    }
-    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
-    }
-}
+/// 每隔多少次时钟中断做一次负载均衡
+const BALANCE_INTERVAL: usize = 10;
+
+#[percpu::def_percpu]
+static BALANCE_TICKS: usize = 0;
 
 lazy_static! {
-    pub static ref TASK_MANAGER: UPIntrFreeCell<TaskManager> =
-        unsafe { UPIntrFreeCell::new(TaskManager::new()) };
     pub static ref PID2PCB: UPIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
         unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
 }
 
+/// `cpu` 的就绪队列，启动时每个核都从主核复制了一份空队列
+fn run_queue(cpu: usize) -> &'static UPIntrFreeCell<RunQueue> {
+    unsafe { &*((percpu::percpu_area_base(cpu) + RUN_QUEUE.offset()) as *const _) }
+}
+
+fn online_cpus() -> impl Iterator<Item = usize> {
+    let mask = arch::cpu_online_mask();
+    (0..arch::MAX_CPU_NUM).filter(move |cpu| mask & (1 << cpu) != 0)
+}
+
+/// 优先留在当前核上，不允许的话放到允许的核里最空闲的那个
+fn select_cpu(task: &TaskControlBlock) -> usize {
+    let cpu = arch::hart_id();
+    if task.can_run_on(cpu) {
+        return cpu;
+    }
+    online_cpus()
+        .filter(|&other| task.can_run_on(other))
+        .min_by_key(|&other| run_queue(other).exclusive_access().len())
+        .unwrap_or(cpu)
+}
+
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    task.sched.exclusive_access().on_ready();
+    run_queue(select_cpu(&task)).exclusive_access().add(task);
+}
+
+/// 在就绪队列的锁里修改 `task` 的调度参数并重新入队，让它按新的参数排队；
+/// 不在任何就绪队列里（正在运行或者阻塞）时直接修改，下次入队时生效
+pub fn requeue_task(task: &Arc<TaskControlBlock>, update: impl FnOnce()) {
+    for cpu in online_cpus() {
+        let mut queue = run_queue(cpu).exclusive_access();
+        if queue.remove(task) {
+            update();
+            queue.add(task.clone());
+            return;
+        }
+    }
+    update();
 }
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
@@ -42,7 +70,70 @@ pub fn wakeup_task(task: Arc<TaskControlBlock>) {
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    let cpu = arch::hart_id();
+    loop {
+        // 先放开本核的队列再去锁别的核，否则两个空闲的核会互相等待
+        let task = run_queue(cpu).exclusive_access().fetch();
+        let task = match task {
+            Some(task) => task,
+            // 本核没有任务了，去其他核偷一个
+            None => return steal_task(cpu),
+        };
+        let target = select_cpu(&task);
+        if target == cpu {
+            return Some(task);
+        }
+        // 入队之后亲和性被改过，转交给允许的核
+        run_queue(target).exclusive_access().add(task);
+    }
+}
+
+fn steal_task(cpu: usize) -> Option<Arc<TaskControlBlock>> {
+    online_cpus()
+        .filter(|&other| other != cpu)
+        .find_map(|other| run_queue(other).exclusive_access().steal(cpu))
+}
+
+/// 时钟中断里判断当前任务要不要被抢占
+pub fn should_preempt() -> bool {
+    let task = match super::current_task() {
+        Some(task) => task,
+        None => return false,
+    };
+    // 先把调度参数取出来，不在持有 sched 锁的时候去锁队列
+    let sched = task.sched.exclusive_access();
+    let (policy, priority, deadline) = (sched.policy, sched.rt_priority, sched.deadline);
+    let ran_ms = sched.running_ms();
+    drop(sched);
+    run_queue(arch::hart_id())
+        .exclusive_access()
+        .should_preempt(policy, priority, deadline, ran_ms)
+}
+
+/// 在时钟中断里调用，定期从最忙的核搬一半的差值过来
+pub fn load_balance() {
+    let ticks = BALANCE_TICKS.read_current() + 1;
+    if ticks < BALANCE_INTERVAL {
+        BALANCE_TICKS.write_current(ticks);
+        return;
+    }
+    BALANCE_TICKS.write_current(0);
+
+    let cpu = arch::hart_id();
+    let local_len = run_queue(cpu).exclusive_access().len();
+    let busiest = online_cpus()
+        .filter(|&other| other != cpu)
+        .map(|other| (other, run_queue(other).exclusive_access().len()))
+        .max_by_key(|&(_, len)| len);
+    if let Some((busiest, len)) = busiest {
+        for _ in 0..len.saturating_sub(local_len) / 2 {
+            let task = run_queue(busiest).exclusive_access().steal(cpu);
+            match task {
+                Some(task) => run_queue(cpu).exclusive_access().add(task),
+                None => break,
+            }
+        }
+    }
 }
 
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
@@ -54,6 +145,20 @@ pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
 }
 
+/// 从别的进程里换出一页，拿不到锁的进程直接跳过，不等待，
+/// 调用的时候一般还拿着自己进程的锁，自己也会被跳过
+pub fn swap_out_other() -> bool {
+    let processes: Vec<Arc<ProcessControlBlock>> = match PID2PCB.try_exclusive_access() {
+        Some(map) => map.values().cloned().collect(),
+        None => return false,
+    };
+    processes.iter().any(|process| {
+        process
+            .try_inner_exclusive_access()
+            .map_or(false, |mut inner| inner.memory_set.swap_out_one())
+    })
+}
+
 pub fn remove_from_pid2process(pid: usize) {
     let mut map = PID2PCB.exclusive_access();
     if map.remove(&pid).is_none() {
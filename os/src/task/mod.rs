@@ -1,30 +1,33 @@
-mod context;
+pub mod binfmt;
 mod id;
 mod manager;
 mod process;
 mod processor;
+mod scheduler;
 mod signal;
-mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
 use self::id::TaskUserRes;
 use crate::fs::{open_file, OpenFlags};
-use arch::shutdown;
-use alloc::{sync::Arc, vec::Vec};
+use arch::{shutdown, KContext};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use lazy_static::*;
-use log::info;
+use log::{debug, info};
 use manager::fetch_task;
 use process::ProcessControlBlock;
-use switch::__switch;
 
-pub use context::TaskContext;
+pub use binfmt::load_program;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
-pub use manager::{add_task, pid2process, remove_from_pid2process, wakeup_task};
+pub use manager::{
+    add_task, load_balance, pid2process, remove_from_pid2process, requeue_task, should_preempt,
+    swap_out_other, wakeup_task,
+};
 pub use processor::{
-    current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_kstack_top, current_process, current_task, current_trap_cx, current_user_token,
+    run_tasks, schedule, take_current_task,
 };
+pub use scheduler::{SchedPolicy, MIN_PRIORITY, RT_PRIORITY_MAX};
 pub use signal::SignalFlags;
 pub use task::{TaskControlBlock, TaskStatus};
 
@@ -34,7 +37,7 @@ pub fn suspend_current_and_run_next() {
 
     // ---- access current TCB exclusively
     let mut task_inner = task.inner_exclusive_access();
-    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    let task_cx_ptr = &mut task_inner.task_cx as *mut KContext;
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
@@ -47,11 +50,11 @@ pub fn suspend_current_and_run_next() {
 }
 
 /// This function must be followed by a schedule
-pub fn block_current_task() -> *mut TaskContext {
+pub fn block_current_task() -> *mut KContext {
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     task_inner.task_status = TaskStatus::Blocked;
-    &mut task_inner.task_cx as *mut TaskContext
+    &mut task_inner.task_cx as *mut KContext
 }
 
 pub fn block_current_and_run_next() {
@@ -59,12 +62,45 @@ pub fn block_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// 阻塞当前任务，标记为 Blocked 之后在持有任务锁的时候用 `register` 登记唤醒
+///
+/// 唤醒要先拿到任务锁，所以不会在任务标记为阻塞之前就把它放回就绪队列
+pub fn block_current_and_run_next_with(register: impl FnOnce(Arc<TaskControlBlock>)) {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Blocked;
+    register(Arc::clone(&task));
+    let task_cx_ptr = &mut task_inner.task_cx as *mut KContext;
+    drop(task_inner);
+    schedule(task_cx_ptr);
+}
+
 /// Exit the current 'Running' task and run the next task in task list.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    // 写回共享的文件映射可能要等磁盘，要在当前任务被取下来之前做
+    let task = current_task().unwrap();
+    let is_main_thread = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .map_or(false, |res| res.tid == 0);
+    if is_main_thread {
+        task.process.upgrade().unwrap().sync_shared_mappings();
+    }
+    drop(task);
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     let process = task.process.upgrade().unwrap();
     let tid = task_inner.res.as_ref().unwrap().tid;
+    let sched = task.sched.exclusive_access();
+    debug!(
+        "pid {} tid {} exit, runtime {}us, wait {}us",
+        process.getpid(),
+        tid,
+        arch::time_to_usec(sched.runtime),
+        arch::time_to_usec(sched.wait_time)
+    );
+    drop(sched);
     // record exit code
     task_inner.exit_code = Some(exit_code);
     task_inner.res = None;
@@ -89,8 +125,16 @@ pub fn exit_current_and_run_next(exit_code: i32) {
                 shutdown();
             }
         }
+        // 父进程可能在别的核上马上回收这个地址空间，先换回内核页表
+        arch::switch_to_kernel_page_table();
         remove_from_pid2process(pid);
         let mut process_inner = process.inner_exclusive_access();
+        let (minor_faults, major_faults) = process_inner.memory_set.fault_stats();
+        let (swap_in, swap_out) = crate::mm::swap::swap_stats();
+        debug!(
+            "pid {} exit, {} minor faults, {} major faults, swap in {} out {} pages in total",
+            pid, minor_faults, major_faults, swap_in, swap_out
+        );
         // mark this process as a zombie process
         process_inner.is_zombie = true;
         // record exit code of main process
@@ -138,15 +182,33 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     }
     drop(process);
     // we do not have to save task context
-    let mut _unused = TaskContext::zero_init();
+    let mut _unused = KContext::blank();
     schedule(&mut _unused as *mut _);
 }
 
+/// 第一个进程的命令行
+///
+/// 用 Linux 的系统调用接口时 user 里的程序没法运行，直接把静态链接的 busybox 当作第一个进程，
+/// 编译时可以用环境变量 INIT 换成别的命令，它退出时关机
+#[cfg(feature = "linux-abi")]
+const INIT_CMDLINE: &str = match option_env!("INIT") {
+    Some(cmdline) if !cmdline.is_empty() => cmdline,
+    _ => "busybox sh",
+};
+#[cfg(not(feature = "linux-abi"))]
+const INIT_CMDLINE: &str = "initproc";
+
+/// 第一个进程的环境变量，所有程序都在根目录下
+const INIT_ENVS: &[&str] = &["PATH=/"];
+
 lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
-        let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
+        let args: Vec<String> = INIT_CMDLINE.split_whitespace().map(String::from).collect();
+        let envs: Vec<String> = INIT_ENVS.iter().map(|env| String::from(*env)).collect();
+        let inode = open_file(&args[0], OpenFlags::RDONLY)
+            .unwrap_or_else(|| panic!("init program {} not found", args[0]));
         let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        ProcessControlBlock::new(Arc::new(v), &args, &envs).expect("init is not a loadable ELF")
     };
 }
 
@@ -1,12 +1,14 @@
-use super::id::RecycleAllocator;
+use super::binfmt::E2BIG;
+use super::id::{map_user_stack, RecycleAllocator};
 use super::manager::insert_into_pid2process;
 use super::TaskControlBlock;
 use super::{add_task, SignalFlags};
 use super::{pid_alloc, PidHandle};
-use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
+use crate::fs::{load_page, File, Stdin, Stdout};
+use crate::mm::{init_user_stack, ElfInfo, FaultError, MemorySet, VirtPage};
 use crate::sync::{Condvar, Mutex, Semaphore, UPIntrFreeCell, UPIntrRefMut};
-use crate::trap::{trap_handler, TrapContext};
+use crate::trap::app_init_context;
+use arch::{Context, ContextOps};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
@@ -72,9 +74,48 @@ impl ProcessControlBlock {
         self.inner.exclusive_access()
     }
 
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+    /// 在地址空间上处理缺页，映射的文件页不在页缓存里时放开锁读进来再重试，
+    /// 处理不了返回 None
+    pub fn handle_faults<T>(
+        &self,
+        mut f: impl FnMut(&mut MemorySet) -> Result<T, FaultError>,
+    ) -> Option<T> {
+        // 读进来的页一直拿到处理完，免得重试之前又被释放
+        let mut loaded = Vec::new();
+        loop {
+            let result = f(&mut self.inner_exclusive_access().memory_set);
+            match result {
+                Ok(value) => return Some(value),
+                Err(FaultError::Invalid) => return None,
+                Err(FaultError::NotCached(inode, offset)) => {
+                    loaded.push(load_page(&inode, offset)?)
+                }
+            }
+        }
+    }
+
+    /// 和 [Self::handle_faults] 一样，但是不读文件，文件页不在页缓存里时直接返回 None，
+    /// 给内核态的缺页用，那里不能等块设备
+    pub fn handle_cached_faults<T>(
+        &self,
+        f: impl FnOnce(&mut MemorySet) -> Result<T, FaultError>,
+    ) -> Option<T> {
+        f(&mut self.inner_exclusive_access().memory_set).ok()
+    }
+
+    /// 已经被借用时返回 None，换页时从别的进程那里借页帧用
+    pub fn try_inner_exclusive_access(&self) -> Option<UPIntrRefMut<'_, ProcessControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+
+    /// 创建第一个进程，`args` 和 `envs` 是它的参数和环境变量
+    pub fn new(
+        elf_data: Arc<Vec<u8>>,
+        args: &[String],
+        envs: &[String],
+    ) -> Result<Arc<Self>, isize> {
+        // memory_set with elf program headers/user stack
+        let (memory_set, ustack_base, elf_info) = MemorySet::from_elf(elf_data, None)?;
         // allocate a pid
         let pid_handle = pid_alloc();
         let process = Arc::new(Self {
@@ -103,25 +144,28 @@ impl ProcessControlBlock {
                 })
             },
         });
-        // create a main thread, we should allocate ustack and trap_cx here
+        // create a main thread, we should allocate ustack here
         let task = Arc::new(TaskControlBlock::new(
             Arc::clone(&process),
             ustack_base,
             true,
         ));
         // prepare trap_cx of main thread
-        let task_inner = task.inner_exclusive_access();
-		let trap_cx = task_inner.get_trap_cx();
-        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
-        let kstack_top = task.kstack.get_top();
-        drop(task_inner);
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
+        let ustack_top = task
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .ustack_top();
+        let trap_cx = main_thread_context(
+            &mut process.inner_exclusive_access().memory_set,
             ustack_top,
-            KERNEL_SPACE.exclusive_access().token(),
-            kstack_top,
-            trap_handler as usize,
-        );
+            &elf_info,
+            args,
+            envs,
+        )
+        .ok_or(E2BIG)?;
+        *task.inner_exclusive_access().get_trap_cx() = trap_cx;
         // add main thread to the process
         let mut process_inner = process.inner_exclusive_access();
         process_inner.tasks.push(Some(Arc::clone(&task)));
@@ -129,68 +173,58 @@ impl ProcessControlBlock {
         insert_into_pid2process(process.getpid(), Arc::clone(&process));
         // add main thread to scheduler
         add_task(task);
-        process
+        Ok(process)
+    }
+
+    /// 把共享文件映射的内容写回文件，地址空间被替换或者回收之前调用
+    pub fn sync_shared_mappings(&self) {
+        let pages = self
+            .inner_exclusive_access()
+            .memory_set
+            .shared_file_pages(VirtPage::from(0), VirtPage::from(usize::MAX));
+        // 写文件可能会让出 CPU，不能拿着进程的锁
+        for page in pages {
+            page.write_back();
+        }
     }
 
     /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    ///
+    /// 动态链接的程序要同时传入解释器，`envs` 是新程序的环境变量。
+    /// 装载不了时返回 ENOEXEC，新程序的用户栈放不下参数时返回 E2BIG，这时原来的程序不受影响
+    pub fn exec(
+        self: &Arc<Self>,
+        elf_data: Arc<Vec<u8>>,
+        interp_data: Option<Arc<Vec<u8>>>,
+        args: Vec<String>,
+        envs: Vec<String>,
+    ) -> Result<(), isize> {
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
-        let new_token = memory_set.token();
+        // memory_set with elf program headers/user stack
+        let (mut memory_set, ustack_base, elf_info) = MemorySet::from_elf(elf_data, interp_data)?;
+        // 主线程的 tid 不变，先在新的地址空间里放好它的用户栈，再换掉旧的
+        let task = self.inner_exclusive_access().get_task(0);
+        let tid = task.inner_exclusive_access().res.as_ref().unwrap().tid;
+        let ustack_top = map_user_stack(&mut memory_set, ustack_base, tid);
+        let trap_cx = main_thread_context(&mut memory_set, ustack_top, &elf_info, &args, &envs)
+            .ok_or(E2BIG)?;
+        self.sync_shared_mappings();
+        // 先切换到新的地址空间，再回收旧的页表
+        memory_set.activate();
         // substitute memory_set
         self.inner_exclusive_access().memory_set = memory_set;
-        // then we alloc user resource for main thread again
-        // since memory_set has been changed
-        let task = self.inner_exclusive_access().get_task(0);
         let mut task_inner = task.inner_exclusive_access();
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
-        task_inner.res.as_mut().unwrap().alloc_user_res();
-        task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
-        // push arguments on user stack
-        let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    new_token,
-                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0;
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translated_refmut(new_token, p as *mut u8) = *c;
-                p += 1;
-            }
-            *translated_refmut(new_token, p as *mut u8) = 0;
-        }
-        // make the user_sp aligned to 8B for k210 platform
-        user_sp -= user_sp % core::mem::size_of::<usize>();
-        // initialize trap_cx
-        let mut trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            task.kstack.get_top(),
-            trap_handler as usize,
-        );
-        trap_cx.x[10] = args.len();
-        trap_cx.x[11] = argv_base;
         *task_inner.get_trap_cx() = trap_cx;
+        Ok(())
     }
 
     /// Only support processes with a single thread.
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         let mut parent = self.inner_exclusive_access();
         assert_eq!(parent.thread_count(), 1);
-        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        // clone parent's memory_set completely including ustacks
+        let memory_set = MemorySet::from_existed_user(&mut parent.memory_set);
         // alloc a pid
         let pid = pid_alloc();
         // copy fd table
@@ -234,7 +268,7 @@ impl ProcessControlBlock {
                 .as_ref()
                 .unwrap()
                 .ustack_base(),
-            // here we do not allocate trap_cx or ustack again
+            // here we do not allocate ustack again
             // but mention that we allocate a new kstack here
             false,
         ));
@@ -242,11 +276,9 @@ impl ProcessControlBlock {
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
         drop(child_inner);
-        // modify kstack_top in trap_cx of this thread
-        let task_inner = task.inner_exclusive_access();
-        let trap_cx = task_inner.get_trap_cx();
-        trap_cx.kernel_sp = task.kstack.get_top();
-        drop(task_inner);
+        // copy trap_cx of parent's main thread
+        let parent_trap_cx = parent.get_task(0).inner_exclusive_access().trap_cx.clone();
+        task.inner_exclusive_access().trap_cx = parent_trap_cx;
         insert_into_pid2process(child.getpid(), Arc::clone(&child));
         // add this thread to scheduler
         add_task(task);
@@ -257,3 +289,23 @@ impl ProcessControlBlock {
         self.pid.0
     }
 }
+
+/// 按 Linux 的约定在主线程的用户栈上放好参数、环境变量和辅助向量，返回主线程的上下文，
+/// 栈放不下时返回 None
+fn main_thread_context(
+    memory_set: &mut MemorySet,
+    ustack_top: usize,
+    elf_info: &ElfInfo,
+    args: &[String],
+    envs: &[String],
+) -> Option<Context> {
+    let stack = init_user_stack(memory_set, ustack_top, elf_info, args, envs)?;
+    let mut trap_cx = app_init_context(elf_info.entry, stack.sp);
+    // 用户库的 _start 直接从寄存器里取 argc 和 argv
+    trap_cx.set_arg0(args.len());
+    trap_cx.set_arg1(stack.argv);
+    if let Some(tls) = stack.tls {
+        trap_cx.set_tls(tls);
+    }
+    Some(trap_cx)
+}
@@ -1,26 +1,26 @@
-use super::__switch;
 use super::{fetch_task, TaskStatus};
-use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use super::{ProcessControlBlock, TaskControlBlock};
 use crate::sync::UPIntrFreeCell;
-use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use arch::{context_switch, Context, KContext, PageTable};
+use core::sync::atomic::Ordering;
 use log::info;
-use core::arch::asm;
 use lazy_static::*;
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
-    idle_task_cx: TaskContext,
+    idle_task_cx: KContext,
 }
 
 impl Processor {
     pub fn new() -> Self {
         Self {
             current: None,
-            idle_task_cx: TaskContext::zero_init(),
+            idle_task_cx: KContext::blank(),
         }
     }
-    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+    fn get_idle_task_cx_ptr(&mut self) -> *mut KContext {
         &mut self.idle_task_cx as *mut _
     }
     pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
@@ -32,41 +32,63 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPIntrFreeCell<Processor> =
-        unsafe { UPIntrFreeCell::new(Processor::new()) };
+    /// 每个核一个 Processor，用 hart_id 作下标
+    static ref PROCESSORS: Vec<UPIntrFreeCell<Processor>> = (0..arch::MAX_CPU_NUM)
+        .map(|_| unsafe { UPIntrFreeCell::new(Processor::new()) })
+        .collect();
+}
+
+/// 当前核的 Processor
+///
+/// 内核态不会被抢占，任务只会在 schedule 里换核，拿到的引用在使用期间一直有效
+fn local_processor() -> &'static UPIntrFreeCell<Processor> {
+    &PROCESSORS[arch::hart_id()]
 }
 
 pub fn run_tasks() {
-	info!("go into run tasks");
+	info!("hart {} go into run tasks", arch::hart_id());
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
-		info!("run tasks loop1");
+        let mut processor = local_processor().exclusive_access();
         if let Some(task) = fetch_task() {
+            // 任务可能刚被别的核放回就绪队列，要等那个核切回 idle 之后才能接着运行
+            while task.on_cpu.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::Relaxed);
+            task.sched.exclusive_access().on_run();
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let next_task_cx_ptr = task.inner.exclusive_session(|task_inner| {
                 task_inner.task_status = TaskStatus::Running;
-                &task_inner.task_cx as *const TaskContext
+                &task_inner.task_cx as *const KContext
             });
+            // 各地址空间共享内核部分，切换到任务的页表后内核仍可继续运行
+            PageTable::from_token(task.get_user_token()).change();
+            // 已经退出的任务在切回 idle 之前还在用自己的内核栈，这里多留一份引用
+            let running = task.clone();
             processor.current = Some(task);
             // release processor manually
             drop(processor);
-			info!("run tasks loop2");
             unsafe {
-                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+                context_switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            running.on_cpu.store(false, Ordering::Release);
         } else {
-            println!("no tasks available in run_tasks");
+            drop(processor);
+            core::hint::spin_loop();
         }
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    let task = local_processor().exclusive_access().take_current()?;
+    // 任务马上要让出 CPU，先结算这一次的运行时间
+    task.sched.exclusive_access().on_stop();
+    Some(task)
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    local_processor().exclusive_access().current()
 }
 
 pub fn current_process() -> Arc<ProcessControlBlock> {
@@ -78,23 +100,13 @@ pub fn current_user_token() -> usize {
     task.get_user_token()
 }
 
-pub fn current_trap_cx() -> &'static mut TrapContext {
+pub fn current_trap_cx() -> &'static mut Context {
     current_task()
         .unwrap()
         .inner_exclusive_access()
         .get_trap_cx()
 }
 
-pub fn current_trap_cx_user_va() -> usize {
-    current_task()
-        .unwrap()
-        .inner_exclusive_access()
-        .res
-        .as_ref()
-        .unwrap()
-        .trap_cx_user_va()
-}
-
 pub fn current_kstack_top() -> usize {
 	extern "C" {
         fn _sbss();
@@ -110,10 +122,10 @@ pub fn current_kstack_top() -> usize {
     // current_task().unwrap().kstack.get_top()
 }
 
-pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+pub fn schedule(switched_task_cx_ptr: *mut KContext) {
     let idle_task_cx_ptr =
-        PROCESSOR.exclusive_session(|processor| processor.get_idle_task_cx_ptr());
+        local_processor().exclusive_session(|processor| processor.get_idle_task_cx_ptr());
     unsafe {
-        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+        context_switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
 }
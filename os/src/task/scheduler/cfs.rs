@@ -0,0 +1,58 @@
+use super::{remove_from, steal_from, Scheduler, DEFAULT_PRIORITY};
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+/// 默认优先级对应的权重
+const NICE_0_WEIGHT: usize = 1024;
+
+fn weight(priority: usize) -> usize {
+    NICE_0_WEIGHT * priority / DEFAULT_PRIORITY
+}
+
+/// 每次选虚拟运行时间最小的任务，优先级越高虚拟时间涨得越慢
+pub struct CfsScheduler {
+    /// (vruntime, 入队序号) -> task
+    queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    seq: usize,
+    /// 单调增长，新任务和刚醒来的任务从这里起步
+    min_vruntime: usize,
+}
+
+impl CfsScheduler {
+    pub const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            seq: 0,
+            min_vruntime: 0,
+        }
+    }
+}
+
+impl Scheduler for CfsScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let vruntime = {
+            let mut sched = task.sched.exclusive_access();
+            sched.vruntime += sched.last_slice * NICE_0_WEIGHT / weight(sched.priority);
+            sched.last_slice = 0;
+            sched.vruntime = sched.vruntime.max(self.min_vruntime);
+            sched.vruntime
+        };
+        self.seq += 1;
+        self.queue.insert((vruntime, self.seq), task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((vruntime, _), task) = self.queue.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(task)
+    }
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        steal_from(&mut self.queue, cpu)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_from(&mut self.queue, task)
+    }
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
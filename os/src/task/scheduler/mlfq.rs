@@ -0,0 +1,84 @@
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use crate::timer::get_time;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+const LEVELS: usize = 4;
+/// 每一层的时间额度，用完之后降一层
+const QUANTUM_US: [usize; LEVELS] = [10_000, 20_000, 40_000, 80_000];
+/// 每隔一段时间把所有任务提回最高层，避免低层任务饿死
+const BOOST_INTERVAL_US: usize = 1_000_000;
+
+const EMPTY_QUEUE: VecDeque<Arc<TaskControlBlock>> = VecDeque::new();
+
+/// 多级反馈队列，第 0 层优先级最高
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    last_boost: usize,
+}
+
+impl MlfqScheduler {
+    pub const fn new() -> Self {
+        Self {
+            queues: [EMPTY_QUEUE; LEVELS],
+            last_boost: 0,
+        }
+    }
+
+    fn boost(&mut self) {
+        for level in 1..LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                let mut sched = task.sched.exclusive_access();
+                sched.level = 0;
+                sched.level_used = 0;
+                drop(sched);
+                self.queues[0].push_back(task);
+            }
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let level = {
+            let mut sched = task.sched.exclusive_access();
+            sched.level_used += sched.last_slice;
+            sched.last_slice = 0;
+            if arch::time_to_usec(sched.level_used) >= QUANTUM_US[sched.level]
+                && sched.level + 1 < LEVELS
+            {
+                sched.level += 1;
+                sched.level_used = 0;
+            }
+            sched.level
+        };
+        self.queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let now = get_time();
+        if arch::time_to_usec(now.saturating_sub(self.last_boost)) >= BOOST_INTERVAL_US {
+            self.last_boost = now;
+            self.boost();
+        }
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    /// 从最低层开始找，高层的任务留在原来的核上
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().rev().find_map(|queue| {
+            let index = queue.iter().rposition(|task| task.can_run_on(cpu))?;
+            queue.remove(index)
+        })
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.queues.iter_mut().any(|queue| {
+            match queue.iter().position(|other| Arc::ptr_eq(other, task)) {
+                Some(index) => queue.remove(index).is_some(),
+                None => false,
+            }
+        })
+    }
+    fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+}
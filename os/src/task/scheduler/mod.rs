@@ -0,0 +1,294 @@
+//! 可替换的调度算法，编译时用 cargo feature 选择，都不选时使用时间片轮转
+//!
+//! - `sched-stride`: stride 调度
+//! - `sched-cfs`: 按权重累计虚拟运行时间，类似 Linux 的 CFS
+//! - `sched-mlfq`: 多级反馈队列
+//! - `sched-priority`: 静态优先级
+//!
+//! 实时任务 (SCHED_FIFO / SCHED_RR / SCHED_DEADLINE) 由单独的 [rt::RtScheduler] 管理，
+//! 总是先于普通任务运行
+
+#[cfg(feature = "sched-cfs")]
+mod cfs;
+#[cfg(feature = "sched-mlfq")]
+mod mlfq;
+#[cfg(feature = "sched-priority")]
+mod priority;
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-cfs",
+    feature = "sched-mlfq",
+    feature = "sched-priority"
+)))]
+mod rr;
+mod rt;
+#[cfg(feature = "sched-stride")]
+mod stride;
+
+use super::TaskControlBlock;
+use crate::timer::{get_time, get_time_ms};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use log::warn;
+use rt::RtScheduler;
+
+#[cfg(feature = "sched-cfs")]
+pub use cfs::CfsScheduler as SchedulerImpl;
+#[cfg(feature = "sched-mlfq")]
+pub use mlfq::MlfqScheduler as SchedulerImpl;
+#[cfg(feature = "sched-priority")]
+pub use priority::PriorityScheduler as SchedulerImpl;
+#[cfg(not(any(
+    feature = "sched-stride",
+    feature = "sched-cfs",
+    feature = "sched-mlfq",
+    feature = "sched-priority"
+)))]
+pub use rr::RoundRobin as SchedulerImpl;
+#[cfg(feature = "sched-stride")]
+pub use stride::StrideScheduler as SchedulerImpl;
+
+/// 每个核的就绪队列都是一个 Scheduler
+pub trait Scheduler {
+    /// 任务进入就绪状态
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// 选出下一个要运行的任务
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// 取出一个允许在 `cpu` 上运行的任务，用来在核之间搬运
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>>;
+    /// 把还在队列里的 `task` 取出来，不在队列里时返回 false
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+    /// 就绪任务的数量
+    fn len(&self) -> usize;
+}
+
+/// 每个核的就绪队列，实时任务总是排在普通任务前面
+pub struct RunQueue {
+    rt: RtScheduler,
+    normal: SchedulerImpl,
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            rt: RtScheduler::new(),
+            normal: SchedulerImpl::new(),
+        }
+    }
+
+    /// 时钟中断时，当前任务是否需要让出 CPU，`ran_ms` 是这次已经连续运行的时间
+    ///
+    /// 普通任务每个时钟中断都轮转，SCHED_FIFO 只让给更高优先级的实时任务，
+    /// SCHED_RR 在此基础上用完时间片后还要让给同优先级的实时任务，
+    /// SCHED_DEADLINE 只让给截止时间更早的任务
+    pub fn should_preempt(
+        &self,
+        policy: SchedPolicy,
+        priority: usize,
+        deadline: usize,
+        ran_ms: usize,
+    ) -> bool {
+        let highest = self.rt.highest_priority();
+        match policy {
+            SchedPolicy::Normal => true,
+            SchedPolicy::Fifo => {
+                self.rt.earliest_deadline().is_some() || highest.map_or(false, |p| p > priority)
+            }
+            SchedPolicy::RoundRobin => {
+                self.rt.earliest_deadline().is_some()
+                    || highest.map_or(false, |p| {
+                        p > priority || (p == priority && ran_ms >= RR_TIMESLICE_MS)
+                    })
+            }
+            SchedPolicy::Deadline => self.rt.earliest_deadline().map_or(false, |d| d < deadline),
+        }
+    }
+}
+
+impl Scheduler for RunQueue {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        if task.sched.exclusive_access().policy.is_rt() {
+            self.rt.add(task);
+        } else {
+            self.normal.add(task);
+        }
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.rt.fetch().or_else(|| self.normal.fetch())
+    }
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        self.rt.steal(cpu).or_else(|| self.normal.steal(cpu))
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.rt.remove(task) || self.normal.remove(task)
+    }
+    fn len(&self) -> usize {
+        self.rt.len() + self.normal.len()
+    }
+}
+
+/// 调度策略，取值和 Linux 的 SCHED_* 一致
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SchedPolicy {
+    Normal = 0,
+    Fifo = 1,
+    RoundRobin = 2,
+    Deadline = 6,
+}
+
+impl SchedPolicy {
+    pub fn from_usize(policy: usize) -> Option<Self> {
+        match policy {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Fifo),
+            2 => Some(Self::RoundRobin),
+            6 => Some(Self::Deadline),
+            _ => None,
+        }
+    }
+
+    pub fn is_rt(self) -> bool {
+        self != Self::Normal
+    }
+}
+
+/// 实时优先级的范围，和 Linux 一样是 1 到 99
+pub const RT_PRIORITY_MAX: usize = 99;
+/// SCHED_RR 的时间片，和 Linux 默认的一样是 100ms
+pub const RR_TIMESLICE_MS: usize = 100;
+
+pub const DEFAULT_PRIORITY: usize = 16;
+/// 和 stride 调度的要求一致，优先级至少为 2
+pub const MIN_PRIORITY: usize = 2;
+
+/// 任务的调度信息，时间的单位都是时钟周期
+pub struct SchedEntity {
+    /// 数值越大越优先
+    pub priority: usize,
+    /// stride 调度的行程
+    pub pass: usize,
+    /// CFS 按权重折算后的运行时间
+    pub vruntime: usize,
+    /// MLFQ 所在的层
+    pub level: usize,
+    /// 在当前层已经用掉的时间
+    pub level_used: usize,
+    /// 最近一次运行的时长，由调度器在入队时结算
+    pub last_slice: usize,
+    /// 累计运行时间
+    pub runtime: usize,
+    /// 累计在就绪队列里等待的时间
+    pub wait_time: usize,
+    pub policy: SchedPolicy,
+    /// SCHED_FIFO / SCHED_RR 的实时优先级，越大越优先
+    pub rt_priority: usize,
+    /// SCHED_DEADLINE 的周期和相对截止时间，单位 ms
+    pub period: usize,
+    pub relative_deadline: usize,
+    /// 当前周期的绝对截止时间和下一次释放的时间，单位 ms
+    pub deadline: usize,
+    pub next_release: usize,
+    ready_since: usize,
+    running_since: usize,
+}
+
+impl SchedEntity {
+    pub fn new(priority: usize) -> Self {
+        Self {
+            priority,
+            pass: 0,
+            vruntime: 0,
+            level: 0,
+            level_used: 0,
+            last_slice: 0,
+            runtime: 0,
+            wait_time: 0,
+            policy: SchedPolicy::Normal,
+            rt_priority: 0,
+            period: 0,
+            relative_deadline: 0,
+            deadline: 0,
+            next_release: 0,
+            ready_since: get_time(),
+            running_since: 0,
+        }
+    }
+
+    /// 进入就绪队列
+    pub fn on_ready(&mut self) {
+        self.ready_since = get_time();
+    }
+
+    /// 被选中开始运行
+    pub fn on_run(&mut self) {
+        let now = get_time();
+        self.wait_time += now.saturating_sub(self.ready_since);
+        self.running_since = now;
+    }
+
+    /// 这次被选中以后已经连续运行的时间，单位 ms
+    pub fn running_ms(&self) -> usize {
+        arch::time_to_usec(get_time().saturating_sub(self.running_since)) / 1000
+    }
+
+    /// 让出 CPU
+    pub fn on_stop(&mut self) {
+        let slice = get_time().saturating_sub(self.running_since);
+        self.runtime += slice;
+        self.last_slice = slice;
+    }
+
+    /// 切换调度策略，SCHED_DEADLINE 从现在开始第一个周期
+    pub fn set_policy(
+        &mut self,
+        policy: SchedPolicy,
+        rt_priority: usize,
+        period: usize,
+        deadline: usize,
+    ) {
+        self.policy = policy;
+        self.rt_priority = rt_priority;
+        self.period = period;
+        self.relative_deadline = deadline;
+        let now = get_time_ms();
+        self.deadline = now + deadline;
+        self.next_release = now + period;
+    }
+
+    /// SCHED_DEADLINE 任务完成本周期的工作，返回下一次释放的时间
+    ///
+    /// 错过的周期直接跳过
+    pub fn finish_period(&mut self) -> usize {
+        let now = get_time_ms();
+        if now > self.deadline {
+            warn!("deadline missed by {}ms", now - self.deadline);
+        }
+        while self.next_release < now {
+            self.next_release += self.period;
+        }
+        let release = self.next_release;
+        self.deadline = release + self.relative_deadline;
+        self.next_release = release + self.period;
+        release
+    }
+}
+
+/// 按 key 排序的队列里，从后往前找一个允许在 `cpu` 上运行的任务
+fn steal_from<K: Ord + Copy>(
+    queue: &mut BTreeMap<K, Arc<TaskControlBlock>>,
+    cpu: usize,
+) -> Option<Arc<TaskControlBlock>> {
+    let key = *queue.iter().rev().find(|(_, task)| task.can_run_on(cpu))?.0;
+    queue.remove(&key)
+}
+
+/// 从按 key 排序的队列里删掉 `task`
+fn remove_from<K: Ord + Copy>(
+    queue: &mut BTreeMap<K, Arc<TaskControlBlock>>,
+    task: &Arc<TaskControlBlock>,
+) -> bool {
+    match queue.iter().find(|(_, other)| Arc::ptr_eq(other, task)) {
+        Some((&key, _)) => queue.remove(&key).is_some(),
+        None => false,
+    }
+}
@@ -0,0 +1,41 @@
+use super::{remove_from, steal_from, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::cmp::Reverse;
+
+/// 静态优先级，总是运行优先级最高的任务，同优先级先进先出
+pub struct PriorityScheduler {
+    /// (优先级从高到低, 入队序号) -> task
+    queue: BTreeMap<(Reverse<usize>, usize), Arc<TaskControlBlock>>,
+    seq: usize,
+}
+
+impl PriorityScheduler {
+    pub const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let priority = task.sched.exclusive_access().priority;
+        self.seq += 1;
+        self.queue.insert((Reverse(priority), self.seq), task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queue.pop_first().map(|(_, task)| task)
+    }
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        steal_from(&mut self.queue, cpu)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_from(&mut self.queue, task)
+    }
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
@@ -0,0 +1,44 @@
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A simple FIFO scheduler.
+pub struct RoundRobin {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    /// 队尾的任务在原来的核上还要等最久
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        let index = self.ready_queue.iter().rposition(|task| task.can_run_on(cpu))?;
+        self.ready_queue.remove(index)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        match self
+            .ready_queue
+            .iter()
+            .position(|other| Arc::ptr_eq(other, task))
+        {
+            Some(index) => self.ready_queue.remove(index).is_some(),
+            None => false,
+        }
+    }
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
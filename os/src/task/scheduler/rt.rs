@@ -0,0 +1,67 @@
+use super::{remove_from, steal_from, SchedPolicy, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::cmp::Reverse;
+
+/// 实时任务的就绪队列，SCHED_DEADLINE 排在 SCHED_FIFO / SCHED_RR 前面
+pub struct RtScheduler {
+    /// (绝对截止时间, 入队序号) -> task，截止时间最早的先运行 (EDF)
+    deadline: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// (优先级从高到低, 入队序号) -> task
+    fixed: BTreeMap<(Reverse<usize>, usize), Arc<TaskControlBlock>>,
+    seq: usize,
+}
+
+impl RtScheduler {
+    pub const fn new() -> Self {
+        Self {
+            deadline: BTreeMap::new(),
+            fixed: BTreeMap::new(),
+            seq: 0,
+        }
+    }
+
+    /// 就绪任务里最早的截止时间
+    pub fn earliest_deadline(&self) -> Option<usize> {
+        self.deadline
+            .first_key_value()
+            .map(|(&(deadline, _), _)| deadline)
+    }
+
+    /// 就绪的固定优先级任务里最高的优先级
+    pub fn highest_priority(&self) -> Option<usize> {
+        self.fixed
+            .first_key_value()
+            .map(|(&(Reverse(priority), _), _)| priority)
+    }
+}
+
+impl Scheduler for RtScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let sched = task.sched.exclusive_access();
+        let (policy, priority, deadline) = (sched.policy, sched.rt_priority, sched.deadline);
+        drop(sched);
+        self.seq += 1;
+        if policy == SchedPolicy::Deadline {
+            self.deadline.insert((deadline, self.seq), task);
+        } else {
+            self.fixed.insert((Reverse(priority), self.seq), task);
+        }
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.deadline
+            .pop_first()
+            .map(|(_, task)| task)
+            .or_else(|| self.fixed.pop_first().map(|(_, task)| task))
+    }
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        steal_from(&mut self.deadline, cpu).or_else(|| steal_from(&mut self.fixed, cpu))
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_from(&mut self.deadline, task) || remove_from(&mut self.fixed, task)
+    }
+    fn len(&self) -> usize {
+        self.deadline.len() + self.fixed.len()
+    }
+}
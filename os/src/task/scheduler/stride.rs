@@ -0,0 +1,54 @@
+use super::{remove_from, steal_from, Scheduler};
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+const BIG_STRIDE: usize = 0x10_0000;
+
+/// 每次选行程最小的任务，运行后行程增加 BIG_STRIDE / priority
+pub struct StrideScheduler {
+    /// (pass, 入队序号) -> task，序号保证相同行程时先进先出
+    queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    seq: usize,
+    /// 最近一次选出的行程，新来的任务从这里起步，不会长时间独占 CPU
+    min_pass: usize,
+}
+
+impl StrideScheduler {
+    pub const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            seq: 0,
+            min_pass: 0,
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let pass = {
+            let mut sched = task.sched.exclusive_access();
+            sched.pass = sched.pass.max(self.min_pass);
+            sched.pass
+        };
+        self.seq += 1;
+        self.queue.insert((pass, self.seq), task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let ((pass, _), task) = self.queue.pop_first()?;
+        self.min_pass = pass;
+        let mut sched = task.sched.exclusive_access();
+        sched.pass += BIG_STRIDE / sched.priority;
+        drop(sched);
+        Some(task)
+    }
+    fn steal(&mut self, cpu: usize) -> Option<Arc<TaskControlBlock>> {
+        steal_from(&mut self.queue, cpu)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        remove_from(&mut self.queue, task)
+    }
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
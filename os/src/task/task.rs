@@ -1,17 +1,22 @@
 use super::id::TaskUserRes;
-use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
-use crate::trap::TrapContext;
-use crate::{
-    mm::PhysPage,mm::VirtPage,
-    sync::{UPIntrFreeCell, UPIntrRefMut},
-};
+use super::scheduler::{SchedEntity, DEFAULT_PRIORITY};
+use super::{current_task, kstack_alloc, KernelStack, ProcessControlBlock};
+use crate::sync::{UPIntrFreeCell, UPIntrRefMut};
+use crate::trap::user_task_loop;
 use alloc::sync::{Arc, Weak};
-use log::error;
+use arch::{Context, KContext};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub struct TaskControlBlock {
     // immutable
     pub process: Weak<ProcessControlBlock>,
     pub kstack: KernelStack,
+    /// 任务的上下文还没有在某个核上保存完，其他核不能切换进来
+    pub on_cpu: AtomicBool,
+    /// 允许运行的核，第 i 位表示 i 号核
+    pub cpu_mask: AtomicUsize,
+    /// 调度器使用的信息，和 inner 分开加锁
+    pub sched: UPIntrFreeCell<SchedEntity>,
     // mutable
     pub inner: UPIntrFreeCell<TaskControlBlockInner>,
 }
@@ -26,20 +31,24 @@ impl TaskControlBlock {
         let inner = process.inner_exclusive_access();
         inner.memory_set.token()
     }
+
+    pub fn can_run_on(&self, cpu: usize) -> bool {
+        self.cpu_mask.load(Ordering::Relaxed) & (1 << cpu) != 0
+    }
 }
 
 pub struct TaskControlBlockInner {
     pub res: Option<TaskUserRes>,
-    pub trap_cx_ppn: PhysPage,
-	pub trap_cx_vpn: VirtPage,
-    pub task_cx: TaskContext,
+    pub trap_cx: Context,
+    pub task_cx: KContext,
     pub task_status: TaskStatus,
     pub exit_code: Option<i32>,
 }
 
 impl TaskControlBlockInner {
-    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
-        self.trap_cx_vpn.get_mut()
+    /// 用户上下文保存在 TCB 里，地址在线程的生命周期内不变
+    pub fn get_trap_cx(&mut self) -> &'static mut Context {
+        unsafe { &mut *(&mut self.trap_cx as *mut Context) }
     }
 
     #[allow(unused)]
@@ -55,22 +64,27 @@ impl TaskControlBlock {
         alloc_user_res: bool,
     ) -> Self {
         let res = TaskUserRes::new(Arc::clone(&process), ustack_base, alloc_user_res);
-        let trap_cx_ppn = res.trap_cx_ppn();
-		let phys_addr=trap_cx_ppn.to_addr();
-		let virt_addr=phys_addr|arch::VIRT_ADDR_START;
-		let trap_cx_vpn=VirtPage::from_addr(virt_addr);
         let kstack = kstack_alloc();
         let kstack_top = kstack.get_top();
-		error!("tcb {} {:#x} {}",trap_cx_ppn,kstack_top,trap_cx_vpn);
+        // 和 Linux 一样，新线程和子进程继承创建者的亲和性和优先级
+        let creator = current_task();
+        let cpu_mask = creator
+            .as_ref()
+            .map_or(usize::MAX, |task| task.cpu_mask.load(Ordering::Relaxed));
+        let priority = creator
+            .as_ref()
+            .map_or(DEFAULT_PRIORITY, |task| task.sched.exclusive_access().priority);
         Self {
             process: Arc::downgrade(&process),
             kstack,
+            on_cpu: AtomicBool::new(false),
+            cpu_mask: AtomicUsize::new(cpu_mask),
+            sched: unsafe { UPIntrFreeCell::new(SchedEntity::new(priority)) },
             inner: unsafe {
                 UPIntrFreeCell::new(TaskControlBlockInner {
                     res: Some(res),
-                    trap_cx_ppn,
-					trap_cx_vpn,
-                    task_cx: TaskContext::goto_trap_return(kstack_top),
+                    trap_cx: Context::new(),
+                    task_cx: KContext::new(user_task_loop as usize, kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
                 })
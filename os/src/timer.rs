@@ -1,27 +1,20 @@
 use core::cmp::Ordering;
 
-use crate::config::CLOCK_FREQ;
-use arch::set_timer;
 use crate::sync::UPIntrFreeCell;
 use crate::task::{wakeup_task, TaskControlBlock};
 use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
-use riscv::register::time;
 
-const TICKS_PER_SEC: usize = 100;
-const MSEC_PER_SEC: usize = 1000;
+const USEC_PER_MSEC: usize = 1000;
 
 pub fn get_time() -> usize {
-    time::read()
+    arch::get_time()
 }
 
 pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
-}
-
-pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    arch::time_to_usec(get_time()) / USEC_PER_MSEC
 }
 
 pub struct TimerCondVar {
@@ -61,14 +54,18 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
 
 pub fn check_timer() {
     let current_ms = get_time_ms();
-    TIMERS.exclusive_session(|timers| {
+    // 登记定时器时持有任务锁，唤醒要等放开 TIMERS 之后，否则两边加锁顺序相反
+    let expired = TIMERS.exclusive_session(|timers| {
+        let mut expired = Vec::new();
         while let Some(timer) = timers.peek() {
-            if timer.expire_ms <= current_ms {
-                wakeup_task(Arc::clone(&timer.task));
-                timers.pop();
-            } else {
+            if timer.expire_ms > current_ms {
                 break;
             }
+            expired.push(timers.pop().unwrap().task);
         }
+        expired
     });
+    for task in expired {
+        wakeup_task(task);
+    }
 }
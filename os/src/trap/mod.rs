@@ -1,173 +1,155 @@
-mod context;
-
-use crate::config::TRAMPOLINE;
+use crate::config::PAGE_SIZE;
+use crate::mm::{MapPermission, MemorySet, VirtAddr};
 use crate::syscall::syscall;
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
-};
-use crate::timer::{check_timer, set_next_trigger};
-use core::arch::{asm, global_asm};
-use log::info;
-use riscv::register::{
-    mtvec::TrapMode,
-    scause::{self, Exception, Interrupt, Trap},
-    sie, sip, sscratch, sstatus, stval, stvec,
+    check_signals_of_current, current_add_signal, current_task, current_trap_cx,
+    exit_current_and_run_next, load_balance, should_preempt, suspend_current_and_run_next,
+    SignalFlags,
 };
-
-global_asm!(include_str!("trap.S"));
+use crate::timer;
+use arch::{run_user_task, Context, ContextOps, TrapType, VIRT_ADDR_START};
 
 pub fn init() {
-    set_kernel_trap_entry();
+    arch::init_interrupt();
 }
 
-fn set_kernel_trap_entry() {
-    extern "C" {
-        fn __alltraps();
-        fn __alltraps_k();
-    }
-    let __alltraps_k_va = __alltraps_k as usize - __alltraps as usize + TRAMPOLINE;
-	info!("__alltraps_k_va is {:#x}",__alltraps_k_va);
-    unsafe {
-        stvec::write(__alltraps_k_va, TrapMode::Direct);
-        sscratch::write(trap_from_kernel as usize);
-    }
+/// 构造从 `entry` 开始执行、用户栈顶为 `sp` 的用户上下文
+pub fn app_init_context(entry: usize, sp: usize) -> Context {
+    let mut cx = Context::new();
+    cx.set_pc(entry);
+    cx.set_sp(sp);
+    cx
 }
 
-fn set_user_trap_entry() {
-    unsafe {
-        stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
+/// `ArchInterface::kernel_interrupt` 的实现，用户态和内核态的 trap 都会先经过这里
+pub fn kernel_interrupt(ctx: &mut Context, trap_type: TrapType) {
+    let from_user = ctx.pc() < VIRT_ADDR_START;
+    match trap_type {
+        // 用户态的缺页可能要读文件，回到 trap_handler 里再处理
+        TrapType::StorePageFault(addr)
+        | TrapType::InstructionPageFault(addr)
+        | TrapType::LoadPageFault(addr)
+            if !from_user =>
+        {
+            // 访问用户内存之前已经在 prepare_user_access 里把页准备好了，
+            // 这里只处理不用等块设备的缺页
+            if !handle_page_fault(addr, trap_type, false) {
+                if is_guard_page(addr) {
+                    panic!(
+                        "[kernel] kernel stack overflow, addr = {:#x}, pc = {:#x}",
+                        addr,
+                        ctx.pc()
+                    );
+                }
+                panic!(
+                    "[kernel] page fault in kernel, addr = {:#x}, pc = {:#x}",
+                    addr,
+                    ctx.pc()
+                );
+            }
+        }
+        TrapType::IllegalInstruction(_) => {
+            if !from_user {
+                panic!("[kernel] illegal instruction in kernel, pc = {:#x}", ctx.pc());
+            }
+            current_add_signal(SignalFlags::SIGILL);
+        }
+        TrapType::Time => {
+            timer::check_timer();
+            load_balance();
+        }
+        // 外部中断已经由 arch 分发给注册的处理函数了
+        _ => {}
     }
 }
 
-pub fn enable_timer_interrupt() {
-    unsafe {
-        sie::set_stimer();
-    }
+/// `addr` 是否落在当前线程内核栈的保护页里
+fn is_guard_page(addr: usize) -> bool {
+    current_task().map_or(false, |task| {
+        addr & !(PAGE_SIZE - 1) == task.kstack.guard_page()
+    })
 }
 
-fn enable_supervisor_interrupt() {
-    unsafe {
-        sstatus::set_sie();
+/// 交给当前地址空间处理缺页，处理不了返回 false。
+/// `may_block` 为 false 时不换出页也不读文件，映射的文件页不在页缓存里也算处理不了
+fn handle_page_fault(addr: usize, trap_type: TrapType, may_block: bool) -> bool {
+    // 内核空间所有页表共享，不会缺页
+    if addr >= VIRT_ADDR_START {
+        return false;
+    }
+    let process = match current_task().and_then(|task| task.process.upgrade()) {
+        Some(process) => process,
+        None => return false,
+    };
+    let access = match trap_type {
+        TrapType::StorePageFault(_) => MapPermission::W,
+        TrapType::InstructionPageFault(_) => MapPermission::X,
+        _ => MapPermission::R,
+    };
+    let fault = |memory_set: &mut MemorySet| {
+        if may_block {
+            memory_set.reclaim(1);
+        }
+        memory_set.handle_page_fault(VirtAddr::from(addr), access)
+    };
+    if may_block {
+        process.handle_faults(fault).is_some()
+    } else {
+        process.handle_cached_faults(fault).is_some()
     }
 }
 
-fn disable_supervisor_interrupt() {
-    unsafe {
-        sstatus::clear_sie();
+/// 每个线程的内核入口：反复回到用户态运行，直到线程退出
+#[no_mangle]
+pub fn user_task_loop() -> ! {
+    loop {
+        arch::disable_irq();
+        let trap_type = run_user_task(current_trap_cx());
+        trap_handler(trap_type);
     }
 }
 
-#[no_mangle]
-pub fn trap_handler() -> ! {
-    set_kernel_trap_entry();
-    let scause = scause::read();
-    let stval = stval::read();
-    // println!("into {:?}", scause.cause());
-    match scause.cause() {
-        Trap::Exception(Exception::UserEnvCall) => {
+fn trap_handler(trap_type: TrapType) {
+    match trap_type {
+        TrapType::UserEnvCall => {
             // jump to next instruction anyway
             let mut cx = current_trap_cx();
-            cx.sepc += 4;
+            cx.syscall_ok();
 
-            enable_supervisor_interrupt();
+            arch::enable_irq();
 
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(cx.syscall_number(), cx.args());
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
-            cx.x[10] = result as usize;
+            cx.set_ret(result as usize);
         }
-        Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::InstructionFault)
-        | Trap::Exception(Exception::InstructionPageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            /*
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            */
+        TrapType::Unknown => {
             current_add_signal(SignalFlags::SIGSEGV);
         }
-        Trap::Exception(Exception::IllegalInstruction) => {
-            current_add_signal(SignalFlags::SIGILL);
+        TrapType::StorePageFault(addr)
+        | TrapType::LoadPageFault(addr)
+        | TrapType::InstructionPageFault(addr) => {
+            // 读文件时要等块设备的中断
+            arch::enable_irq();
+            if !handle_page_fault(addr, trap_type, true) {
+                current_add_signal(SignalFlags::SIGSEGV);
+            }
         }
-        Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            check_timer();
-            suspend_current_and_run_next();
+        TrapType::IllegalInstruction(_) => {
+            // 在 kernel_interrupt 里已经处理过，会留下信号
         }
-        Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            crate::board::irq_handler();
-        }
-        _ => {
-            panic!(
-                "Unsupported trap {:?}, stval = {:#x}!",
-                scause.cause(),
-                stval
-            );
+        TrapType::Time => {
+            // 定时器已经在 kernel_interrupt 里检查过了
+            if should_preempt() {
+                suspend_current_and_run_next();
+            }
         }
+        TrapType::SupervisorExternal | TrapType::Breakpoint => {}
     }
     // check signals
     if let Some((errno, msg)) = check_signals_of_current() {
         println!("[kernel] {}", msg);
         exit_current_and_run_next(errno);
     }
-    trap_return();
-}
-
-#[no_mangle]
-pub fn trap_return() -> ! {
-	info!("go into trap return");
-    disable_supervisor_interrupt();
-    set_user_trap_entry();
-    let trap_cx_user_va = current_trap_cx_user_va();
-    let user_satp = current_user_token();
-    extern "C" {
-        fn __alltraps();
-        fn __restore();
-    }
-    let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
-    //println!("before return");
-    unsafe {
-        asm!(
-            "fence.i",
-            "jr {restore_va}",
-            restore_va = in(reg) restore_va,
-            in("a0") trap_cx_user_va,
-            in("a1") user_satp,
-            options(noreturn)
-        );
-    }
-}
-
-#[no_mangle]
-pub fn trap_from_kernel(_trap_cx: &TrapContext) {
-	info!("trap from kernel");
-    let scause = scause::read();
-    let stval = stval::read();
-    match scause.cause() {
-        Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            crate::board::irq_handler();
-        }
-        Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            check_timer();
-            // do not schedule now
-        }
-        _ => {
-            panic!(
-                "Unsupported trap from kernel: {:?}, stval = {:#x}!",
-                scause.cause(),
-                stval
-            );
-        }
-    }
 }
-
-pub use context::TrapContext;
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, sched_getaffinity, sched_setaffinity, wait, yield_};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let online = sched_getaffinity(0);
+    assert!(online > 0);
+    println!("online cpu mask = {:#x}", online);
+    // 空集合或者只有不存在的核都应该失败
+    assert_eq!(sched_setaffinity(0, 0), -1);
+    assert_eq!(sched_setaffinity(0, 1 << 63), -1);
+
+    // 把自己绑到最后一个核上，子进程会继承
+    let last = 1 << (usize::BITS - 1 - (online as usize).leading_zeros());
+    assert_eq!(sched_setaffinity(0, last), 0);
+    assert_eq!(sched_getaffinity(0), last as isize);
+    let pid = fork();
+    if pid == 0 {
+        for _ in 0..10 {
+            yield_();
+        }
+        assert_eq!(sched_getaffinity(0), last as isize);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(sched_setaffinity(0, online as usize), 0);
+    println!("affinity passed!");
+    0
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, meminfo, wait, yield_};
+
+/// 同时存活的子进程个数
+const CONCURRENT: usize = 8;
+const SWITCHES: usize = 16;
+
+/// 每个进程各自写入自己的编号，TLB 里残留别的地址空间的表项时会读到别人的值
+static mut OWNER: usize = 0;
+
+/// 读出 meminfo 里 `key` 对应的数值
+fn read(key: &str) -> usize {
+    let mut buf = [0u8; 1024];
+    let len = meminfo(&mut buf);
+    assert!(len > 0);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()?.strip_suffix(':')? == key {
+                fields.next()?.parse().ok()
+            } else {
+                None
+            }
+        })
+        .unwrap()
+}
+
+fn owner() -> usize {
+    unsafe { core::ptr::addr_of!(OWNER).read_volatile() }
+}
+
+fn set_owner(id: usize) {
+    unsafe { core::ptr::addr_of_mut!(OWNER).write_volatile(id) }
+}
+
+fn child(id: usize) -> ! {
+    set_owner(id);
+    for _ in 0..SWITCHES {
+        yield_();
+        assert_eq!(owner(), id);
+    }
+    exit(0)
+}
+
+/// 先后创建比 ASID 个数更多的进程，让 ASID 至少换一代，换代前后每个进程都只能看到自己的数据
+#[no_mangle]
+pub fn main() -> i32 {
+    let asids = read("Asids");
+    let generation = read("AsidGeneration");
+    if asids < 2 {
+        println!("no ASID support, every switch flushes the whole TLB");
+        return 0;
+    }
+    let total = asids + asids / 2;
+    println!(
+        "{} ASIDs, generation {}, forking {} processes",
+        asids, generation, total
+    );
+    set_owner(usize::MAX);
+    let mut forked = 0;
+    while forked < total {
+        let batch = CONCURRENT.min(total - forked);
+        for id in forked..forked + batch {
+            if fork() == 0 {
+                child(id);
+            }
+        }
+        for _ in 0..batch {
+            let mut exit_code = 0;
+            assert!(wait(&mut exit_code) > 0);
+            assert_eq!(exit_code, 0);
+        }
+        forked += batch;
+        assert_eq!(owner(), usize::MAX);
+    }
+    let now = read("AsidGeneration");
+    println!("generation {} -> {}", generation, now);
+    assert!(now > generation);
+    println!("asid_rollover passed!");
+    0
+}
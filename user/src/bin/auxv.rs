@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::getauxval;
+
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(getauxval(AT_PAGESZ), Some(4096));
+    assert_eq!(getauxval(AT_ENTRY), Some(user_lib::_start as usize));
+    // 内核给的 16 个随机字节
+    let random = getauxval(AT_RANDOM).unwrap();
+    let bytes = unsafe { core::slice::from_raw_parts(random as *const u8, 16) };
+    assert!(bytes.iter().any(|&b| b != 0));
+    // 没有的项
+    assert_eq!(getauxval(1000), None);
+    println!("auxv passed!");
+    0
+}
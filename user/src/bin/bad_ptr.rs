@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, pipe, read, write};
+
+const EFAULT: isize = -14;
+
+/// 没有映射的地址，在程序装载的基址之下
+const UNMAPPED: usize = 0x1000;
+
+/// 只读的数据，内核不能往里面写
+static RODATA: [u8; 16] = *b"read only data\0\0";
+
+fn bad_buf(addr: usize, len: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+fn bad_fds(addr: usize) -> &'static mut [usize] {
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, 2) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 内核读写不到的地址都返回 EFAULT，而不是让内核 panic
+    assert_eq!(write(1, bad_buf(UNMAPPED, 16)), EFAULT);
+    assert_eq!(write(1, bad_buf(usize::MAX - 8, 16)), EFAULT);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(write(pipe_fd[1], b"fault"), 5);
+    assert_eq!(read(pipe_fd[0], bad_buf(UNMAPPED, 5)), EFAULT);
+    assert_eq!(
+        read(pipe_fd[0], bad_buf(RODATA.as_ptr() as usize, 5)),
+        EFAULT
+    );
+    // 前面失败的读没有把数据取走
+    let mut buf = [0u8; 5];
+    assert_eq!(read(pipe_fd[0], &mut buf), 5);
+    assert_eq!(&buf, b"fault");
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    assert_eq!(pipe(bad_fds(UNMAPPED)), EFAULT);
+
+    println!("bad_ptr passed!");
+    0
+}
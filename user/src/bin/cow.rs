@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, pipe, read, waitpid, write};
+
+const PAGES: usize = 4;
+const PAGE_SIZE: usize = 4096;
+
+static mut DATA: [u8; PAGES * PAGE_SIZE] = [0; PAGES * PAGE_SIZE];
+
+fn data() -> &'static mut [u8; PAGES * PAGE_SIZE] {
+    unsafe { &mut *core::ptr::addr_of_mut!(DATA) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for (i, byte) in data().iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        // 子进程修改自己的副本，父进程不应该看到
+        for page in 0..PAGES {
+            data()[page * PAGE_SIZE] = 0xff;
+        }
+        // 内核通过 read 写入共享页，同样只能改到子进程这一份
+        let buf = &mut data()[PAGE_SIZE..PAGE_SIZE + 5];
+        assert_eq!(read(pipe_fd[0], buf), 5);
+        assert_eq!(buf, b"hello");
+        close(pipe_fd[0]);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"hello"), 5);
+    close(pipe_fd[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    for (i, byte) in data().iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+    println!("cow passed!");
+    0
+}
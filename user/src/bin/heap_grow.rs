@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use user_lib::{brk, sbrk};
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let bottom = brk(0);
+    assert!(bottom > 0);
+
+    // sbrk 出来的页第一次访问时是 0
+    let old = sbrk(2 * PAGE_SIZE as isize);
+    assert_eq!(old, bottom);
+    let area = unsafe { core::slice::from_raw_parts_mut(old as *mut u8, 2 * PAGE_SIZE) };
+    assert!(area.iter().all(|&b| b == 0));
+    area.fill(0x5a);
+    assert_eq!(
+        sbrk(-(2 * PAGE_SIZE as isize)),
+        old + 2 * PAGE_SIZE as isize
+    );
+    assert_eq!(brk(0), bottom);
+    // 堆顶不能低于堆底
+    assert_eq!(sbrk(-1), -1);
+
+    // 远超过 user_lib 自带的 32KiB 堆
+    let mut big: Vec<usize> = Vec::new();
+    for i in 0..128 * 1024 {
+        big.push(i);
+    }
+    let boxes: Vec<Box<[u8; 1024]>> = (0..64).map(|i| Box::new([i as u8; 1024])).collect();
+    for (i, value) in big.iter().enumerate() {
+        assert_eq!(*value, i);
+    }
+    for (i, b) in boxes.iter().enumerate() {
+        assert!(b.iter().all(|&x| x == i as u8));
+    }
+    assert!(brk(0) > bottom);
+    println!("heap_grow passed!");
+    0
+}
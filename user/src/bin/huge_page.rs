@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, mprotect, munmap, waitpid, MmapFlags, MmapProt};
+
+const PAGE_SIZE: usize = 4096;
+const HUGE_PAGE_SIZE: usize = 0x20_0000;
+/// 2M 对齐，内核可以整块用大页映射
+const ADDR: usize = 0x4000_0000;
+const LEN: usize = 2 * HUGE_PAGE_SIZE;
+
+fn slice() -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(ADDR as *mut u8, LEN) }
+}
+
+/// 每一页写上不同的值，方便检查拆开大页之后内容有没有错位
+fn pattern(offset: usize) -> u8 {
+    (offset / PAGE_SIZE) as u8 ^ 0x5a
+}
+
+fn check_pattern(skip: usize) {
+    let buf = slice();
+    for offset in (0..LEN).step_by(PAGE_SIZE) {
+        if offset / PAGE_SIZE != skip {
+            assert_eq!(buf[offset], pattern(offset));
+            assert_eq!(buf[offset + PAGE_SIZE - 1], pattern(offset));
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    let flags = MmapFlags::PRIVATE | MmapFlags::ANONYMOUS | MmapFlags::FIXED;
+    assert_eq!(mmap(ADDR, LEN, rw, flags, 0, 0), ADDR as isize);
+    let buf = slice();
+    assert!(buf.iter().all(|&b| b == 0));
+    for offset in (0..LEN).step_by(PAGE_SIZE) {
+        buf[offset] = pattern(offset);
+        buf[offset + PAGE_SIZE - 1] = pattern(offset);
+    }
+
+    // fork 之后子进程写一页只会复制这一页，父进程看到的内容不变
+    let pid = fork();
+    if pid == 0 {
+        slice()[3 * PAGE_SIZE] = 0;
+        check_pattern(3);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    check_pattern(usize::MAX);
+
+    // 只读一页，其他页还能写
+    let page = 5;
+    assert_eq!(
+        mprotect(ADDR + page * PAGE_SIZE, PAGE_SIZE, MmapProt::READ),
+        0
+    );
+    let pid = fork();
+    if pid == 0 {
+        slice()[page * PAGE_SIZE] = 0;
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -11);
+    buf[(page + 1) * PAGE_SIZE] = pattern((page + 1) * PAGE_SIZE);
+    check_pattern(usize::MAX);
+
+    // 从大页中间挖掉一页，前后的页不受影响，挖掉的地方重新映射后是 0
+    let hole = HUGE_PAGE_SIZE + 7 * PAGE_SIZE;
+    assert_eq!(munmap(ADDR + hole, PAGE_SIZE), 0);
+    check_pattern(hole / PAGE_SIZE);
+    assert_eq!(
+        mmap(ADDR + hole, PAGE_SIZE, rw, flags, 0, 0),
+        (ADDR + hole) as isize
+    );
+    assert_eq!(buf[hole], 0);
+    assert_eq!(munmap(ADDR, LEN), 0);
+    println!("huge_page passed!");
+    0
+}
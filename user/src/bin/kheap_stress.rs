@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, condvar_create, exit, fork, meminfo, mutex_create, pipe, read, semaphore_create,
+    thread_create, wait, waittid, write,
+};
+
+const ROUNDS: usize = 16;
+const PIPES: usize = 64;
+const LOCKS: usize = 64;
+const THREADS: usize = 4;
+/// 管道的缓冲区只有 32 字节，写多了会一直阻塞
+const PIPE_BUF: usize = 32;
+
+/// 所有 slab 缓存里正在使用的对象个数之和
+fn slab_in_use() -> usize {
+    let mut buf = [0u8; 1024];
+    let len = meminfo(&mut buf);
+    assert!(len > 0);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .filter(|line| line.starts_with("kmalloc-"))
+        .map(|line| {
+            line.split_whitespace()
+                .nth(1)
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        })
+        .sum()
+}
+
+fn thread_exit(arg: usize) -> ! {
+    exit(arg as i32)
+}
+
+/// 在子进程里申请一大批小的内核对象，退出时全部释放
+fn allocate(round: usize) -> ! {
+    let before = slab_in_use();
+    let mut fds = [[0usize; 2]; PIPES];
+    for (i, fd) in fds.iter_mut().enumerate() {
+        assert_eq!(pipe(fd), 0);
+        let data = [round as u8; PIPE_BUF];
+        let len = i % PIPE_BUF + 1;
+        assert_eq!(write(fd[1], &data[..len]), len as isize);
+    }
+    for _ in 0..LOCKS {
+        assert!(mutex_create() >= 0);
+        assert!(semaphore_create(1) >= 0);
+        assert!(condvar_create() >= 0);
+    }
+    let mut tids = [0usize; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        let ret = thread_create(thread_exit as usize, i);
+        assert!(ret > 0);
+        *tid = ret as usize;
+    }
+    assert!(slab_in_use() > before + PIPES);
+    for (i, &tid) in tids.iter().enumerate() {
+        assert_eq!(waittid(tid), i as isize);
+    }
+    for (i, fd) in fds.iter().enumerate() {
+        let mut data = [0u8; PIPE_BUF];
+        let len = i % PIPE_BUF + 1;
+        assert_eq!(read(fd[0], &mut data), len as isize);
+        assert!(data[..len].iter().all(|&byte| byte == round as u8));
+        close(fd[0]);
+        close(fd[1]);
+    }
+    exit(0)
+}
+
+/// 反复在子进程里申请和释放大量小的内核对象，最后内核堆里用着的对象数要回到开始时的水平
+#[no_mangle]
+pub fn main() -> i32 {
+    let baseline = slab_in_use();
+    for round in 0..ROUNDS {
+        if fork() == 0 {
+            allocate(round);
+        }
+        let mut exit_code = 0;
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    let after = slab_in_use();
+    println!("slab objects in use: {} -> {}", baseline, after);
+    // 内核里别的缓存可能多留了几个对象，但不能随着轮数增长
+    assert!(after <= baseline + PIPES);
+    println!("kheap_stress passed!");
+    0
+}
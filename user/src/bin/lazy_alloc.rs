@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, pipe, read, write};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 256;
+
+/// 1MiB 的 .bss，只有访问到的页才会分配
+static mut BIG: [u8; PAGES * PAGE_SIZE] = [0; PAGES * PAGE_SIZE];
+
+/// 初始值放在 .data 里，缺页时从 ELF 文件内容填充
+static mut TABLE: [usize; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+fn big() -> &'static mut [u8; PAGES * PAGE_SIZE] {
+    unsafe { &mut *core::ptr::addr_of_mut!(BIG) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let table = unsafe { &*core::ptr::addr_of!(TABLE) };
+    assert_eq!(table.iter().sum::<usize>(), 36);
+
+    // 第一次读到的必须是 0
+    for page in (0..PAGES).step_by(16) {
+        assert_eq!(big()[page * PAGE_SIZE + 7], 0);
+        big()[page * PAGE_SIZE + 7] = page as u8;
+    }
+    for page in (0..PAGES).step_by(16) {
+        assert_eq!(big()[page * PAGE_SIZE + 7], page as u8);
+    }
+
+    // 内核写入一个从来没有访问过的页
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(write(pipe_fd[1], b"lazy"), 4);
+    let untouched = PAGES - 1;
+    let buf = &mut big()[untouched * PAGE_SIZE..untouched * PAGE_SIZE + 4];
+    assert_eq!(read(pipe_fd[0], buf), 4);
+    assert_eq!(buf, b"lazy");
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+
+    println!("lazy_alloc passed!");
+    0
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, mmap, munmap, MmapFlags, MmapProt};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 64;
+
+/// 读出 `key` 对应的数值，第二个返回值是每个 order 的空闲块数之和换算成的 kB
+fn read(key: &str) -> (usize, usize) {
+    let mut buf = [0u8; 1024];
+    let len = meminfo(&mut buf);
+    assert!(len > 0);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    let mut value = None;
+    let mut blocks = 0;
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let name = fields.next().unwrap();
+        if name.strip_suffix(':') == Some(key) {
+            value = fields.next().and_then(|v| v.parse().ok());
+        } else if name == "FreeBlocks:" {
+            for (order, count) in fields.enumerate() {
+                blocks += count.parse::<usize>().unwrap() << order;
+            }
+        }
+    }
+    (value.unwrap(), blocks * PAGE_SIZE / 1024)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 1024];
+    let len = meminfo(&mut buf) as usize;
+    let text = core::str::from_utf8(&buf[..len]).unwrap();
+    print!("{}", text);
+    // 内核堆每个 slab 缓存一行：使用中的对象、总对象数、slab 数
+    let mut caches = 0;
+    for line in text.lines().filter(|line| line.starts_with("kmalloc-")) {
+        let mut fields = line.split_whitespace().skip(1);
+        let mut next = || fields.next().unwrap().parse::<usize>().unwrap();
+        let (in_use, capacity) = (next(), next());
+        assert!(in_use <= capacity);
+        caches += 1;
+    }
+    assert!(caches > 0);
+    // 缓冲区不够时只写一部分
+    assert_eq!(meminfo(&mut buf[..8]), 8);
+
+    let (total, _) = read("MemTotal");
+    let (before, blocks) = read("MemFree");
+    assert!(before <= total);
+    assert_eq!(before, blocks);
+
+    let addr = mmap(
+        0,
+        PAGES * PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let area = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, PAGES * PAGE_SIZE) };
+    for offset in (0..area.len()).step_by(PAGE_SIZE) {
+        area[offset] = 1;
+    }
+    let (during, blocks) = read("MemFree");
+    assert_eq!(during, blocks);
+    assert!(before - during >= PAGES * PAGE_SIZE / 1024);
+
+    // 释放之后页帧回到分配器，新建的页表和内核堆的 slab 可能还占着几页
+    assert_eq!(munmap(addr as usize, PAGES * PAGE_SIZE), 0);
+    let (after, _) = read("MemFree");
+    assert!(after + 16 * PAGE_SIZE / 1024 >= before);
+    println!("meminfo passed!");
+    0
+}
@@ -0,0 +1,171 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::{
+    close, exit, fork, mmap, mprotect, munmap, open, read, waitpid, write, MmapFlags, MmapProt,
+    OpenFlags,
+};
+
+const PAGE_SIZE: usize = 4096;
+const FILE: &str = "mmap_file\0";
+
+fn slice(addr: isize, len: usize) -> &'static mut [u8] {
+    assert!(addr > 0);
+    unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) }
+}
+
+/// 在子进程里执行 `f`，返回子进程的退出码
+fn in_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn anonymous() {
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    let private = MmapFlags::PRIVATE | MmapFlags::ANONYMOUS;
+    let addr = mmap(0, 4 * PAGE_SIZE, rw, private, 0, 0);
+    let buf = slice(addr, 4 * PAGE_SIZE);
+    assert!(buf.iter().all(|&b| b == 0));
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    // 从中间挖掉一页，区域被拆成两段
+    let hole = addr as usize + PAGE_SIZE;
+    assert_eq!(munmap(hole, PAGE_SIZE), 0);
+    assert_eq!(buf[0], 0);
+    assert_eq!(buf[2 * PAGE_SIZE + 1], 1);
+    // 挖掉的地方可以用 MAP_FIXED 重新映射，内容是新的
+    assert_eq!(
+        mmap(hole, PAGE_SIZE, rw, private | MmapFlags::FIXED, 0, 0),
+        hole as isize
+    );
+    assert_eq!(buf[PAGE_SIZE + 1], 0);
+    assert_eq!(munmap(addr as usize, 4 * PAGE_SIZE), 0);
+
+    // 私有映射 fork 之后互不影响，共享映射父子进程写的是同一份
+    static mut PRIVATE: isize = 0;
+    static mut SHARED: isize = 0;
+    unsafe {
+        PRIVATE = mmap(0, PAGE_SIZE, rw, private, 0, 0);
+        SHARED = mmap(
+            0,
+            PAGE_SIZE,
+            rw,
+            MmapFlags::SHARED | MmapFlags::ANONYMOUS,
+            0,
+            0,
+        );
+    }
+    assert_eq!(
+        in_child(|| unsafe {
+            slice(PRIVATE, PAGE_SIZE)[0] = 1;
+            slice(SHARED, PAGE_SIZE)[0] = 1;
+        }),
+        0
+    );
+    unsafe {
+        assert_eq!(slice(PRIVATE, PAGE_SIZE)[0], 0);
+        assert_eq!(slice(SHARED, PAGE_SIZE)[0], 1);
+    }
+}
+
+fn protect() {
+    static mut ADDR: isize = 0;
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    unsafe {
+        ADDR = mmap(
+            0,
+            2 * PAGE_SIZE,
+            rw,
+            MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+            0,
+            0,
+        );
+        slice(ADDR, 2 * PAGE_SIZE)[PAGE_SIZE] = 42;
+        // 只把第二页改成只读
+        assert_eq!(
+            mprotect(ADDR as usize + PAGE_SIZE, PAGE_SIZE, MmapProt::READ),
+            0
+        );
+        slice(ADDR, 2 * PAGE_SIZE)[0] = 1;
+    }
+    assert_eq!(
+        in_child(|| unsafe { slice(ADDR, 2 * PAGE_SIZE)[PAGE_SIZE] = 0 }),
+        -11
+    );
+    unsafe {
+        assert_eq!(slice(ADDR, 2 * PAGE_SIZE)[PAGE_SIZE], 42);
+        assert_eq!(mprotect(ADDR as usize + PAGE_SIZE, PAGE_SIZE, rw), 0);
+        slice(ADDR, 2 * PAGE_SIZE)[PAGE_SIZE] = 43;
+        assert_eq!(munmap(ADDR as usize, 2 * PAGE_SIZE), 0);
+    }
+    // 范围里有空洞
+    assert_eq!(
+        mprotect(unsafe { ADDR } as usize, PAGE_SIZE, MmapProt::READ),
+        -1
+    );
+}
+
+fn file_backed() {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let content = vec![b'a'; PAGE_SIZE + 100];
+    assert_eq!(write(fd, &content), content.len() as isize);
+
+    let rw = MmapProt::READ | MmapProt::WRITE;
+    // 私有映射的修改不会写回文件，超出文件的部分是 0
+    let private = mmap(0, 2 * PAGE_SIZE, rw, MmapFlags::PRIVATE, fd, 0);
+    let buf = slice(private, 2 * PAGE_SIZE);
+    assert_eq!(&buf[..content.len()], &content[..]);
+    assert_eq!(buf[content.len()], 0);
+    buf[0] = b'x';
+    assert_eq!(munmap(private as usize, 2 * PAGE_SIZE), 0);
+
+    // 共享映射的修改在 munmap 时写回
+    let shared = mmap(0, 2 * PAGE_SIZE, rw, MmapFlags::SHARED, fd, 0);
+    let another = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ,
+        MmapFlags::SHARED,
+        fd,
+        PAGE_SIZE,
+    );
+    let buf = slice(shared, 2 * PAGE_SIZE);
+    assert_eq!(buf[0], b'a');
+    buf[PAGE_SIZE + 1] = b'y';
+    // 映射同一个文件的其他区域马上就能看到
+    assert_eq!(slice(another, PAGE_SIZE)[1], b'y');
+    assert_eq!(munmap(shared as usize, 2 * PAGE_SIZE), 0);
+    assert_eq!(munmap(another as usize, PAGE_SIZE), 0);
+    close(fd);
+
+    let fd = open(FILE, OpenFlags::RDONLY) as usize;
+    let mut buffer = vec![0u8; 2 * PAGE_SIZE];
+    // 写回不会让文件变长
+    assert_eq!(read(fd, &mut buffer), content.len() as isize);
+    assert_eq!(buffer[0], b'a');
+    assert_eq!(buffer[PAGE_SIZE + 1], b'y');
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    anonymous();
+    protect();
+    file_backed();
+    println!("mmap passed!");
+    0
+}
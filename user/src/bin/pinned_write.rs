@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, mmap, mprotect, pipe, read, sleep, thread_create, waittid, write, MmapFlags, MmapProt,
+};
+
+const PAGE_SIZE: usize = 4096;
+const MESSAGE: &[u8] = b"pinned page";
+
+static mut PIPE_FD: [usize; 2] = [0; 2];
+
+fn pipe_fd() -> [usize; 2] {
+    unsafe { core::ptr::addr_of!(PIPE_FD).read() }
+}
+
+/// 阻塞在管道上，内核一直拿着 `buf` 所在的页
+fn reader(buf: usize) -> ! {
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, MESSAGE.len()) };
+    exit(read(pipe_fd()[0], buf) as i32)
+}
+
+/// 内核为一个还没返回的 read 拿着缓冲区所在的页，用户在同一页上写不能触发写时复制，
+/// 否则内核之后写进的是复制之前的旧页帧，用户看不到读到的数据
+#[no_mangle]
+pub fn main() -> i32 {
+    let addr = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let page = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, PAGE_SIZE) };
+    page.fill(0);
+    let mut fd = [0usize; 2];
+    assert_eq!(pipe(&mut fd), 0);
+    unsafe { core::ptr::addr_of_mut!(PIPE_FD).write(fd) };
+
+    let tid = thread_create(reader as usize, addr as usize);
+    assert!(tid > 0);
+    // 等读线程阻塞在空管道上
+    sleep(20);
+    // 权限没变，但页会被重新映射一遍，只有自己在用的页要保持可写
+    assert_eq!(
+        mprotect(addr as usize, PAGE_SIZE, MmapProt::READ | MmapProt::WRITE),
+        0
+    );
+    page[PAGE_SIZE - 1] = 0x5a;
+    assert_eq!(write(fd[1], MESSAGE), MESSAGE.len() as isize);
+    assert_eq!(waittid(tid as usize), MESSAGE.len() as isize);
+    assert_eq!(&page[..MESSAGE.len()], MESSAGE);
+    assert_eq!(page[PAGE_SIZE - 1], 0x5a);
+    println!("pinned_write passed!");
+    0
+}
@@ -4,6 +4,9 @@
 #[macro_use]
 extern crate user_lib;
 
+#[cfg(not(target_arch = "riscv64"))]
+use core::arch::asm;
+#[cfg(target_arch = "riscv64")]
 use riscv::register::sstatus::{self, SPP};
 
 #[no_mangle]
@@ -11,7 +14,14 @@ fn main() -> i32 {
     println!("Try to access privileged CSR in U Mode");
     println!("Kernel should kill this application!");
     unsafe {
+        #[cfg(target_arch = "riscv64")]
         sstatus::set_spp(SPP::User);
+        #[cfg(target_arch = "aarch64")]
+        asm!("mrs {}, sctlr_el1", out(reg) _);
+        #[cfg(target_arch = "x86_64")]
+        asm!("mov {}, cr0", out(reg) _);
+        #[cfg(target_arch = "loongarch64")]
+        asm!("csrrd {}, 0x0", out(reg) _);
     }
     0
 }
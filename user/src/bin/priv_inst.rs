@@ -11,7 +11,14 @@ fn main() -> i32 {
     println!("Try to execute privileged instruction in U Mode");
     println!("Kernel should kill this application!");
     unsafe {
+        #[cfg(target_arch = "riscv64")]
         asm!("sret");
+        #[cfg(target_arch = "aarch64")]
+        asm!("eret");
+        #[cfg(target_arch = "x86_64")]
+        asm!("hlt");
+        #[cfg(target_arch = "loongarch64")]
+        asm!("ertn");
     }
     0
 }
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, sched_getscheduler, sched_setscheduler, wait, yield_, SchedParam,
+    SCHED_DEADLINE, SCHED_FIFO, SCHED_NORMAL, SCHED_RR,
+};
+
+const PERIOD_MS: usize = 50;
+const ROUNDS: usize = 5;
+
+fn fixed(priority: usize) -> SchedParam {
+    SchedParam {
+        priority,
+        ..Default::default()
+    }
+}
+
+fn deadline(period_ms: usize, deadline_ms: usize) -> SchedParam {
+    SchedParam {
+        period_ms,
+        deadline_ms,
+        ..Default::default()
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(sched_getscheduler(0), SCHED_NORMAL as isize);
+    // 非法参数
+    assert_eq!(sched_setscheduler(0, 3, &fixed(10)), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_FIFO, &fixed(0)), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_RR, &fixed(100)), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_DEADLINE, &deadline(0, 0)), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_DEADLINE, &deadline(10, 20)), -1);
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(sched_setscheduler(0, SCHED_FIFO, &fixed(10)), 0);
+        assert_eq!(sched_getscheduler(0), SCHED_FIFO as isize);
+        let start = get_time();
+        while get_time() - start < 20 {}
+        assert_eq!(sched_setscheduler(0, SCHED_RR, &fixed(20)), 0);
+        assert_eq!(sched_getscheduler(0), SCHED_RR as isize);
+
+        // 每次 yield 都会睡到下一个周期才被释放
+        assert_eq!(
+            sched_setscheduler(0, SCHED_DEADLINE, &deadline(PERIOD_MS, PERIOD_MS / 2)),
+            0
+        );
+        assert_eq!(sched_getscheduler(0), SCHED_DEADLINE as isize);
+        let start = get_time();
+        for _ in 0..ROUNDS {
+            yield_();
+        }
+        let elapsed = (get_time() - start) as usize;
+        println!("{} periods took {}ms", ROUNDS, elapsed);
+        assert!(elapsed >= (ROUNDS - 1) * PERIOD_MS);
+
+        assert_eq!(sched_setscheduler(0, SCHED_NORMAL, &fixed(0)), 0);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("rt_sched passed!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_priority, wait};
+
+const WORKERS: usize = 3;
+
+fn spin(ms: isize) -> usize {
+    let start = get_time();
+    let mut count = 0usize;
+    while get_time() - start < ms {
+        count = count.wrapping_add(1);
+    }
+    count
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(set_priority(0), -1);
+    assert_eq!(set_priority(16), 16);
+
+    // 不同调度算法下各个优先级拿到的计算量不同，这里只打印出来对比
+    for i in 0..WORKERS {
+        let pid = fork();
+        if pid == 0 {
+            let prio = 4 * (i as isize + 1);
+            assert_eq!(set_priority(prio), prio);
+            let count = spin(500);
+            println!("priority {} count {}", prio, count);
+            exit(0);
+        }
+    }
+    let mut exit_code: i32 = 0;
+    for _ in 0..WORKERS {
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    println!("set_priority passed!");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::null;
+use user_lib::{close, exec, fork, open, waitpid, write, OpenFlags};
+
+const SCRIPT: &str = "shebang_script\0";
+
+fn write_file(path: &str, content: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, content);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // 作为解释器被执行：解释器、#! 行里的参数、脚本路径
+    if argc == 3 && argv[1] == "interp" {
+        assert_eq!(argv[2], "shebang_script");
+        return 42;
+    }
+    write_file(SCRIPT, b"#!/bin/shebang interp\n");
+    let pid = fork();
+    if pid == 0 {
+        exec(SCRIPT, &[SCRIPT.as_ptr(), null()]);
+        panic!("exec script failed");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 42);
+    // 认不出来的格式和不存在的文件
+    write_file("shebang_garbage\0", b"not an executable");
+    assert_eq!(exec("shebang_garbage\0", &[null()]), -8);
+    assert_eq!(exec("shebang_missing\0", &[null()]), -1);
+    println!("shebang passed!");
+    0
+}
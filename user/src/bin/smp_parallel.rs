@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, sched_getaffinity, sched_setaffinity, wait};
+
+/// 每个子进程做的计算量
+const WORK: usize = 2_000_000;
+
+fn work() -> usize {
+    let mut x: usize = 1;
+    for i in 0..WORK {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(i);
+    }
+    x
+}
+
+/// 每个上线的核 fork 一个子进程，子进程把自己绑到这个核上再计算，
+/// 退出码是它所在的核，最后检查每个核都有子进程跑过
+#[no_mangle]
+pub fn main() -> i32 {
+    let online = sched_getaffinity(0) as usize;
+    assert!(online > 0);
+    let harts = online.count_ones();
+    println!("online cpu mask = {:#x}, {} hart(s)", online, harts);
+    if harts < 2 {
+        println!("smp_parallel needs at least 2 harts, run with SMP=2 or more");
+        return 0;
+    }
+    let start = get_time();
+    for hart in (0..usize::BITS as usize).filter(|hart| online & (1 << hart) != 0) {
+        if fork() == 0 {
+            // 不在这个核上时 sched_setaffinity 会让出 CPU，换到绑定的核上
+            assert_eq!(sched_setaffinity(0, 1 << hart), 0);
+            let x = work();
+            assert_eq!(sched_getaffinity(0), 1 << hart);
+            // 防止计算被优化掉
+            assert_ne!(x, 0);
+            exit(hart as i32);
+        }
+    }
+    let mut ran_on = 0usize;
+    for _ in 0..harts {
+        let mut exit_code: i32 = 0;
+        assert!(wait(&mut exit_code) > 0);
+        assert!((0..usize::BITS as i32).contains(&exit_code));
+        ran_on |= 1 << exit_code;
+    }
+    println!(
+        "children ran on harts {:#x}, time cost = {}ms",
+        ran_on,
+        get_time() - start
+    );
+    assert_eq!(ran_on, online);
+    assert!(ran_on.count_ones() > 1);
+    println!("smp_parallel passed!");
+    0
+}
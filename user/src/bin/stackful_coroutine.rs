@@ -4,12 +4,14 @@
 #![no_std]
 #![no_main]
 #![feature(naked_functions)]
+#![cfg_attr(not(target_arch = "riscv64"), allow(dead_code))]
 //#![feature(asm)]
 
 extern crate alloc;
 #[macro_use]
 extern crate user_lib;
 
+#[cfg(target_arch = "riscv64")]
 use core::arch::asm;
 
 //#[macro_use]
@@ -258,6 +260,7 @@ pub fn yield_task() {
 /// see: https://github.com/rust-lang/rfcs/blob/master/text/1201-naked-fns.md
 /// see: https://doc.rust-lang.org/nightly/reference/inline-assembly.html
 /// see: https://doc.rust-lang.org/nightly/rust-by-example/unsafe/asm.html
+#[cfg(target_arch = "riscv64")]
 #[naked]
 #[no_mangle]
 unsafe extern "C" fn switch(old: *mut TaskContext, new: *const TaskContext) {
@@ -302,6 +305,15 @@ unsafe extern "C" fn switch(old: *mut TaskContext, new: *const TaskContext) {
     );
 }
 
+// 切换上下文的汇编只有 riscv64 的版本，其他架构上不运行
+#[cfg(not(target_arch = "riscv64"))]
+#[no_mangle]
+pub fn main() {
+    println!("stackful_coroutine only supports riscv64, skipped");
+    exit(-1);
+}
+
+#[cfg(target_arch = "riscv64")]
 #[no_mangle]
 pub fn main() {
     println!("stackful_coroutine begin...");
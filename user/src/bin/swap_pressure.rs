@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, mmap, munmap, MmapFlags, MmapProt};
+
+const PAGE_SIZE: usize = 4096;
+/// 每页检查的字数，分散在整页里
+const WORDS: usize = 8;
+
+/// 读出 meminfo 里 `key` 对应的数值，单位 kB
+fn read(key: &str) -> usize {
+    let mut buf = [0u8; 1024];
+    let len = meminfo(&mut buf);
+    assert!(len > 0);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()?.strip_suffix(':')? == key {
+                fields.next()?.parse().ok()
+            } else {
+                None
+            }
+        })
+        .unwrap()
+}
+
+fn pattern(page: usize, word: usize) -> usize {
+    ((page << 16) | word) ^ 0x5a5a_a5a5
+}
+
+/// 第 `page` 页里第 `word` 个检查的字在数组里的下标
+fn index(page: usize, word: usize) -> usize {
+    page * PAGE_SIZE / 8 + word * (PAGE_SIZE / 8 / WORDS)
+}
+
+fn check(area: &[usize], page: usize) {
+    for word in 0..WORDS {
+        assert_eq!(
+            area[index(page, word)],
+            pattern(page, word),
+            "page {} corrupted",
+            page
+        );
+    }
+}
+
+/// 映射比空闲内存多的匿名页，写满之后换一个顺序读回来检查
+#[no_mangle]
+pub fn main() -> i32 {
+    let swap_total = read("SwapTotal") * 1024 / PAGE_SIZE;
+    assert!(swap_total > 0, "swap_pressure needs a swap device");
+    let swap_before = read("SwapFree") * 1024 / PAGE_SIZE;
+    let free = read("MemFree") * 1024 / PAGE_SIZE;
+    // 比空闲内存多出交换区剩余空间的一半，页表和别的进程还要占一些
+    let pages = free + swap_before / 2;
+    println!(
+        "free {} pages, swap {} / {} pages, touching {} pages",
+        free, swap_before, swap_total, pages
+    );
+
+    let addr = mmap(
+        0,
+        pages * PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(addr > 0);
+    let area =
+        unsafe { core::slice::from_raw_parts_mut(addr as *mut usize, pages * PAGE_SIZE / 8) };
+    for page in 0..pages {
+        for word in 0..WORDS {
+            area[index(page, word)] = pattern(page, word);
+        }
+    }
+    let swap_used = read("SwapFree") * 1024 / PAGE_SIZE;
+    println!("{} pages in swap", swap_before - swap_used);
+    assert!(swap_used < swap_before);
+
+    // 先倒着读，最早写的页已经被换出去了，再正着读一遍，换入的页又会挤掉别的页
+    for page in (0..pages).rev() {
+        check(area, page);
+    }
+    for page in 0..pages {
+        check(area, page);
+    }
+
+    // 解除映射之后交换区里的页也要还回去，别的进程的页可能也被换出去了一些
+    assert_eq!(munmap(addr as usize, pages * PAGE_SIZE), 0);
+    let swap_after = read("SwapFree") * 1024 / PAGE_SIZE;
+    assert!(swap_after + 256 >= swap_before);
+    println!("swap_pressure passed!");
+    0
+}
@@ -41,6 +41,23 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("threads_arg\0", "\0", "\0", "\0", 0),
     ("threads\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
+    ("affinity\0", "\0", "\0", "\0", 0),
+    ("smp_parallel\0", "\0", "\0", "\0", 0),
+    ("set_priority\0", "\0", "\0", "\0", 0),
+    ("rt_sched\0", "\0", "\0", "\0", 0),
+    ("cow\0", "\0", "\0", "\0", 0),
+    ("pinned_write\0", "\0", "\0", "\0", 0),
+    ("lazy_alloc\0", "\0", "\0", "\0", 0),
+    ("mmap\0", "\0", "\0", "\0", 0),
+    ("heap_grow\0", "\0", "\0", "\0", 0),
+    ("huge_page\0", "\0", "\0", "\0", 0),
+    ("meminfo\0", "\0", "\0", "\0", 0),
+    ("swap_pressure\0", "\0", "\0", "\0", 0),
+    ("kheap_stress\0", "\0", "\0", "\0", 0),
+    ("asid_rollover\0", "\0", "\0", "\0", 0),
+    ("bad_ptr\0", "\0", "\0", "\0", 0),
+    ("auxv\0", "\0", "\0", "\0", 0),
+    ("shebang\0", "\0", "\0", "\0", 0),
     ("barrier_fail\0", "\0", "\0", "\0", 0),
     ("barrier_condvar\0", "\0", "\0", "\0", 0),
 ];
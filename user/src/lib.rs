@@ -8,6 +8,7 @@ pub mod console;
 mod file;
 mod io;
 mod lang_items;
+mod mm;
 mod net;
 mod sync;
 mod syscall;
@@ -18,9 +19,10 @@ extern crate alloc;
 extern crate bitflags;
 
 use alloc::vec::Vec;
-use buddy_system_allocator::LockedHeap;
+use core::sync::atomic::{AtomicUsize, Ordering};
 pub use file::*;
 pub use io::*;
+pub use mm::*;
 pub use net::*;
 pub use sync::*;
 use syscall::*;
@@ -30,21 +32,46 @@ const USER_HEAP_SIZE: usize = 32768;
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 
+/// 先用 `HEAP_SPACE`，不够了再通过 sbrk 扩展
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: mm::GrowableHeap = mm::GrowableHeap::empty();
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
+/// 初始用户栈上 argv 数组的地址，后面依次是 envp 和辅助向量
+static ARGV: AtomicUsize = AtomicUsize::new(0);
+
+/// 内核放在初始用户栈上的辅助向量里 `key` 对应的值
+pub fn getauxval(key: usize) -> Option<usize> {
+    let mut p = ARGV.load(Ordering::Relaxed) as *const usize;
+    unsafe {
+        // 跳过 argv 和 envp，它们都以空指针结尾
+        for _ in 0..2 {
+            while p.read() != 0 {
+                p = p.add(1);
+            }
+            p = p.add(1);
+        }
+        loop {
+            match (p.read(), p.add(1).read()) {
+                (0, _) => return None,
+                (k, v) if k == key => return Some(v),
+                _ => p = p.add(2),
+            }
+        }
+    }
+}
+
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        HEAP.init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
+    ARGV.store(argv, Ordering::Relaxed);
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
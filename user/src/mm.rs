@@ -0,0 +1,88 @@
+use super::*;
+use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+
+bitflags! {
+    pub struct MmapProt: usize {
+        const NONE = 0;
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    pub struct MmapFlags: usize {
+        const SHARED = 1 << 0;
+        const PRIVATE = 1 << 1;
+        const FIXED = 1 << 4;
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+/// 失败返回 -1，成功返回映射的起始地址
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(addr, len, prot.bits, flags.bits, fd, offset)
+}
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+pub fn mprotect(addr: usize, len: usize, prot: MmapProt) -> isize {
+    sys_mprotect(addr, len, prot.bits)
+}
+/// 参数为 0 时只返回当前的堆顶
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+/// 返回原来的堆顶，失败返回 -1
+pub fn sbrk(increment: isize) -> isize {
+    sys_sbrk(increment)
+}
+/// 读取 /proc/meminfo 格式的内存统计，返回写入 `buf` 的字节数
+pub fn meminfo(buf: &mut [u8]) -> isize {
+    sys_meminfo(buf)
+}
+
+/// 每次至少向内核要这么多
+const HEAP_GROW_SIZE: usize = 64 * 1024;
+
+/// 空间不够时用 sbrk 向内核要更多空间的堆
+pub(crate) struct GrowableHeap(LockedHeap);
+
+impl GrowableHeap {
+    pub(crate) const fn empty() -> Self {
+        Self(LockedHeap::empty())
+    }
+    pub(crate) unsafe fn init(&self, start: usize, size: usize) {
+        self.0.lock().init(start, size);
+    }
+}
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // buddy 分配器只从对齐的块里分配，要两倍大小才能保证放得下
+        let size = (layout.size().max(layout.align()).next_power_of_two() * 2).max(HEAP_GROW_SIZE);
+        let start = sbrk(size as isize);
+        if start < 0 {
+            return core::ptr::null_mut();
+        }
+        heap.add_to_heap(start as usize, start as usize + size);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
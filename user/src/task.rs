@@ -61,10 +61,50 @@ pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
+pub fn set_priority(prio: isize) -> isize {
+    sys_set_priority(prio)
+}
+
+pub const SCHED_NORMAL: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+pub const SCHED_DEADLINE: usize = 6;
+
+/// SCHED_FIFO / SCHED_RR 用 priority (1~99)，SCHED_DEADLINE 用 period_ms 和 deadline_ms
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct SchedParam {
+    pub priority: usize,
+    pub period_ms: usize,
+    pub deadline_ms: usize,
+}
+
+/// pid 为 0 表示当前线程
+pub fn sched_setscheduler(pid: usize, policy: usize, param: &SchedParam) -> isize {
+    sys_sched_setscheduler(pid, policy, param)
+}
+
+pub fn sched_getscheduler(pid: usize) -> isize {
+    sys_sched_getscheduler(pid)
+}
+
 pub fn sleep(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
 
+/// pid 为 0 表示当前线程，`mask` 的第 i 位表示 i 号核
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, &mask)
+}
+
+pub fn sched_getaffinity(pid: usize) -> isize {
+    let mut mask = 0;
+    match sys_sched_getaffinity(pid, &mut mask) {
+        ret if ret < 0 => ret,
+        _ => mask as isize,
+    }
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }